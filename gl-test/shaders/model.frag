@@ -0,0 +1,26 @@
+#version 150
+
+in vec3 Normal;
+in vec2 Texcoord;
+
+out vec4 out_color;
+
+uniform vec3 diffuse_color;
+uniform sampler2D diffuse_texture;
+
+// The direction towards a fixed light, so that differently facing sides are shaded differently.
+const vec3 light_direction = vec3(0.267261, 0.534522, 0.801784);
+
+void main() {
+    vec3 normal = normalize(Normal);
+#ifdef DOUBLE_SIDED
+    // Light the back of a surface as facing away from its front.
+    if (!gl_FrontFacing) {
+        normal = -normal;
+    }
+#endif
+
+    float light = 0.25 + 0.75 * max(dot(normal, light_direction), 0.0);
+    vec3 diffuse = diffuse_color * texture(diffuse_texture, Texcoord).rgb;
+    out_color = vec4(diffuse * light, 1.0);
+}
@@ -0,0 +1,19 @@
+#version 150
+
+in vec3 position;
+in vec3 normal;
+in vec2 texcoord;
+
+out vec3 Normal;
+out vec2 Texcoord;
+
+uniform mat4 trans;
+uniform mat4 model;
+
+void main() {
+    // The upper 3x3 of the model matrix only keeps normals perpendicular to their surfaces under
+    // uniform scaling, which is all the scenes use.
+    Normal = mat3(model) * normal;
+    Texcoord = texcoord;
+    gl_Position = trans * vec4(position, 1.0);
+}
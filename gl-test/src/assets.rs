@@ -0,0 +1,425 @@
+//! Loading assets from disk once and sharing them.
+//!
+//! Assets are handed out as `Rc`s. The caches only hold weak references, so an asset is unloaded,
+//! and its GL objects deleted, as soon as the last handle to it is dropped; asking for it again
+//! loads it afresh. Models are cached by the attribute bindings they were uploaded with as well
+//! as by path, since their vertex arrays are only right for programs with the same attribute
+//! locations.
+//!
+//! Textures and models can also be loaded in the background: they are read and decoded on worker
+//! threads and uploaded to GL by `Assets::upload`, which the render loop calls once a frame.
+
+use context::GlContext;
+use gl::types::*;
+use loader::{Pending, UploadQueue, WorkerPool};
+use model::{DecodedModel, Model};
+use obj::{self, ObjError};
+use object::Texture2d;
+use reload::{LoadError, ShaderFiles};
+use shader::Program;
+use std::cell::{Ref, RefCell};
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::env;
+use std::fmt;
+use std::fs;
+use std::hash::Hash;
+use std::path::{Path, PathBuf};
+use std::rc::{Rc, Weak};
+use texture::{self, Image, SamplerDesc, TextureError};
+use vertex::AttributeBindings;
+
+/// The directory to load assets from when none is given on the command line: the `assets`
+/// directory next to the binary if there is one, and otherwise the one in the source tree.
+pub fn default_asset_dir() -> PathBuf {
+    let next_to_binary = env::current_exe().ok()
+        .and_then(|exe| exe.parent().map(|dir| dir.join("assets")));
+
+    match next_to_binary {
+        Some(ref dir) if dir.is_dir() => dir.clone(),
+        _ => PathBuf::from(concat!(env!("CARGO_MANIFEST_DIR"), "/assets")),
+    }
+}
+
+/// Loaded values of type `T`, looked up by keys of type `K`, kept only while something else
+/// holds on to them.
+pub struct Cache<K, T> {
+    entries: RefCell<HashMap<K, Weak<T>>>,
+}
+
+impl<K: Hash + Eq, T> Cache<K, T> {
+    pub fn new() -> Cache<K, T> {
+        Cache { entries: RefCell::new(HashMap::new()) }
+    }
+
+    /// Return the value cached under `key` if it is still alive, and otherwise load it with
+    /// `load` and cache it. Errors aren't cached, so the next call tries loading again.
+    pub fn get_or_load<E, F>(&self, key: K, load: F) -> Result<Rc<T>, E>
+        where F: FnOnce() -> Result<T, E>
+    {
+        if let Some(value) = self.entries.borrow().get(&key).and_then(Weak::upgrade) {
+            return Ok(value);
+        }
+
+        // `load` may itself load other values through this cache, so it mustn't be borrowed.
+        let value = Rc::new(load()?);
+
+        let mut entries = self.entries.borrow_mut();
+        entries.retain(|_, weak| weak.strong_count() > 0);
+        entries.insert(key, Rc::downgrade(&value));
+        Ok(value)
+    }
+
+    /// Like `get_or_load`, for values that can't fail to load.
+    pub fn get_or_make<F: FnOnce() -> T>(&self, key: K, make: F) -> Rc<T> {
+        match self.get_or_load(key, || Ok::<_, Infallible>(make())) {
+            Ok(value) => value,
+            Err(e) => match e {},
+        }
+    }
+
+    /// The number of values that are still alive.
+    pub fn loaded(&self) -> usize {
+        self.entries.borrow().values().filter(|weak| weak.strong_count() > 0).count()
+    }
+}
+
+impl<K: Hash + Eq, T> Default for Cache<K, T> {
+    fn default() -> Cache<K, T> {
+        Cache::new()
+    }
+}
+
+/// What an asset loaded in the background has got to.
+enum State<T> {
+    Loading,
+    Ready(T),
+    Failed,
+}
+
+/// An asset that is loaded in the background, and can only be used once it has been uploaded.
+pub struct Streamed<T> {
+    state: Rc<RefCell<State<T>>>,
+}
+
+impl<T> Streamed<T> {
+    /// The asset, if it has been uploaded.
+    pub fn get(&self) -> Option<Ref<'_, T>> {
+        Ref::filter_map(self.state.borrow(), |state| match *state {
+            State::Ready(ref value) => Some(value),
+            State::Loading | State::Failed => None,
+        }).ok()
+    }
+
+    pub fn is_ready(&self) -> bool {
+        matches!(*self.state.borrow(), State::Ready(_))
+    }
+}
+
+/// A texture that is loaded in the background. Until it has been uploaded, or if it fails to
+/// load, binding it binds a placeholder instead.
+pub struct StreamedTexture<'a> {
+    texture: Streamed<Texture2d<'a>>,
+    placeholder: Rc<Texture2d<'a>>,
+}
+
+impl<'a> StreamedTexture<'a> {
+    /// Make `unit` the active texture unit and bind the texture, or the placeholder, to it.
+    pub fn bind(&self, unit: GLuint) {
+        match self.texture.get() {
+            Some(texture) => texture.bind(unit),
+            None => self.placeholder.bind(unit),
+        }
+    }
+
+    pub fn is_ready(&self) -> bool {
+        self.texture.is_ready()
+    }
+}
+
+/// The textures, shader programs and models loaded in one context.
+pub struct Assets<'a> {
+    context: &'a GlContext,
+    root: PathBuf,
+    shader_dir: PathBuf,
+    textures: Cache<(PathBuf, SamplerDesc, bool), Texture2d<'a>>,
+    streamed_textures: Cache<(PathBuf, SamplerDesc, bool), StreamedTexture<'a>>,
+    programs: Cache<String, Program<'a>>,
+    models: Cache<(PathBuf, AttributeBindings), Model<'a>>,
+    streamed_models: Cache<(PathBuf, AttributeBindings), Streamed<Model<'a>>>,
+    placeholder: Rc<Texture2d<'a>>,
+    uploads: RefCell<UploadQueue<'a>>,
+
+    // Dropped last, after waiting for the jobs in flight, whose results are then thrown away.
+    workers: WorkerPool,
+}
+
+impl<'a> Assets<'a> {
+    /// Load assets from under `root`, and shader programs from `shader_dir`.
+    pub fn new(context: &'a GlContext, root: &Path, shader_dir: &Path) -> Assets<'a> {
+        Assets {
+            context,
+            root: root.to_owned(),
+            shader_dir: shader_dir.to_owned(),
+            textures: Cache::new(),
+            streamed_textures: Cache::new(),
+            programs: Cache::new(),
+            models: Cache::new(),
+            streamed_models: Cache::new(),
+            placeholder: Rc::new(texture::placeholder(context)),
+            uploads: RefCell::new(UploadQueue::new()),
+            workers: WorkerPool::with_default_threads(),
+        }
+    }
+
+    pub fn context(&self) -> &'a GlContext {
+        self.context
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// The path of an asset, given relative to the asset root.
+    pub fn resolve<P: AsRef<Path>>(&self, path: P) -> PathBuf {
+        self.root.join(path)
+    }
+
+    /// Load an image as a texture, as by `texture::load`. The same image loaded with another
+    /// sampler or flip is a separate texture.
+    pub fn texture<P: AsRef<Path>>(&self, path: P, sampler: &SamplerDesc, flip: bool)
+                                   -> Result<Rc<Texture2d<'a>>, TextureError> {
+        let path = self.resolve(path);
+        self.textures.get_or_load((path.clone(), *sampler, flip), || {
+            texture::load(self.context, &path, sampler, flip)
+        })
+    }
+
+    /// Load an image as a texture in the background, as by `texture::load`. The image is
+    /// decoded on a worker thread and uploaded by a later call to `upload`.
+    pub fn texture_async<P: AsRef<Path>>(&self, path: P, sampler: &SamplerDesc, flip: bool)
+                                         -> Result<Rc<StreamedTexture<'a>>, TextureError> {
+        sampler.validate()?;
+        let path = self.resolve(path);
+        let sampler = *sampler;
+        let key = (path.clone(), sampler, flip);
+
+        Ok(self.streamed_textures.get_or_make(key, || {
+            let context = self.context;
+            let decode = move || -> Result<Image, TextureError> {
+                let mut image = Image::read(path)?;
+                if flip {
+                    image.flip_vertically();
+                }
+                Ok(image)
+            };
+            let upload = move |image: Image| texture::upload(context, &image, &sampler);
+            let texture = self.stream(decode, |image| image.pixels.len(), upload,
+                                      "Drawing a placeholder texture instead.");
+
+            StreamedTexture { texture, placeholder: self.placeholder.clone() }
+        }))
+    }
+
+    /// Load an OBJ model in the background, like `model`. The model and the images of its
+    /// textures are read on a worker thread and uploaded by a later call to `upload`.
+    pub fn model_async<P: AsRef<Path>>(&self, path: P, attributes: &AttributeBindings)
+                                       -> Rc<Streamed<Model<'a>>> {
+        let path = path.as_ref().to_owned();
+        let key = (self.resolve(&path), attributes.clone());
+
+        self.streamed_models.get_or_make(key, || {
+            // Reading through the asset root keeps the texture paths relative to it.
+            let root = self.root.clone();
+            let decode = move || -> Result<DecodedModel, ObjError> {
+                let parts = obj::read_with(&path, &mut |path| fs::read_to_string(root.join(path)))?;
+                Ok(DecodedModel::decode(&root, parts)?)
+            };
+
+            let (context, attributes) = (self.context, attributes.clone());
+            let upload = move |decoded: DecodedModel| {
+                Model::upload(context, &attributes, decoded).map_err(ObjError::from)
+            };
+            self.stream(decode, DecodedModel::bytes, upload, "Leaving the model out.")
+        })
+    }
+
+    /// Load an asset in the background: run `decode` on a worker thread, and make the asset from
+    /// its result with `upload` during a later call to `upload`. Nothing waits for the asset, so
+    /// errors are reported on standard error, followed by `instead` saying what happens instead.
+    fn stream<T, X, E, D, C, U>(&self, decode: D, cost: C, upload: U, instead: &'static str)
+                                -> Streamed<T>
+        where T: 'a,
+              X: Send + 'static,
+              E: fmt::Display + Send + 'static,
+              D: FnOnce() -> Result<X, E> + Send + 'static,
+              C: Fn(&X) -> usize + 'a,
+              U: FnOnce(X) -> Result<T, E> + 'a
+    {
+        let state = Rc::new(RefCell::new(State::Loading));
+        let weak_state = Rc::downgrade(&state);
+
+        let cost = move |decoded: &Result<X, E>| decoded.as_ref().map_or(0, &cost);
+        let upload = move |decoded: Result<X, E>| {
+            // Nothing to do if every handle was dropped while the asset was decoding.
+            let state = match weak_state.upgrade() {
+                Some(state) => state,
+                None => return,
+            };
+
+            *state.borrow_mut() = match decoded.and_then(upload) {
+                Ok(value) => State::Ready(value),
+                Err(e) => {
+                    eprintln!("{}\n{}", e, instead);
+                    State::Failed
+                }
+            };
+        };
+        self.load_async(decode, cost, upload);
+
+        Streamed { state }
+    }
+
+    /// Run `decode` on a worker thread, and pass its result to `upload` on this thread during a
+    /// later call to `Assets::upload`. `cost` estimates the bytes that uploading will transfer.
+    pub fn load_async<T, D, C, U>(&self, decode: D, cost: C, upload: U)
+        where T: Send + 'static,
+              D: FnOnce() -> T + Send + 'static,
+              C: Fn(&T) -> usize + 'a,
+              U: FnOnce(T) + 'a
+    {
+        let pending = self.workers.decode(decode);
+        self.uploads.borrow_mut().push(pending, cost, upload);
+    }
+
+    /// Run `job` on a worker thread, for results that the caller polls for itself rather than
+    /// having them passed to an upload.
+    pub fn decode<T, F>(&self, job: F) -> Pending<T>
+        where T: Send + 'static, F: FnOnce() -> T + Send + 'static
+    {
+        self.workers.decode(job)
+    }
+
+    /// Upload assets that have finished decoding, up to about `budget` bytes of them, and
+    /// return the number of bytes uploaded.
+    pub fn upload(&self, budget: usize) -> usize {
+        // Uploads may load more assets, so the queue mustn't be borrowed while they run.
+        let ready = self.uploads.borrow_mut().take_ready(budget);
+        let bytes = ready.bytes();
+        ready.upload();
+        bytes
+    }
+
+    /// Wait for every asset being loaded in the background, including any that uploading the
+    /// others starts loading, and upload them all.
+    pub fn finish_loading(&self) {
+        loop {
+            let ready = self.uploads.borrow_mut().take_all();
+            if ready.is_empty() {
+                break;
+            }
+            ready.upload();
+        }
+    }
+
+    /// Load the program built from `NAME.vert` and `NAME.frag` in the shader directory.
+    pub fn program(&self, name: &str) -> Result<Rc<Program<'a>>, LoadError> {
+        self.programs.get_or_load(name.to_string(), || {
+            ShaderFiles::new(&self.shader_dir, name).load(self.context)
+        })
+    }
+
+    /// Load an OBJ model, as by `obj::load`, with its vertices fed to the attributes given by
+    /// `attributes`.
+    pub fn model<P: AsRef<Path>>(&self, path: P, attributes: &AttributeBindings)
+                                 -> Result<Rc<Model<'a>>, ObjError> {
+        let path = path.as_ref();
+        self.models.get_or_load((self.resolve(path), attributes.clone()), || {
+            obj::load(self, path, attributes)
+        })
+    }
+}
+
+#[test]
+fn test_cache() {
+    use std::cell::Cell;
+
+    let cache = Cache::new();
+    let loads = Cell::new(0);
+    let load = |value: &'static str| {
+        loads.set(loads.get() + 1);
+        Ok::<_, ()>(value)
+    };
+
+    let first = cache.get_or_load("a", || load("first")).unwrap();
+    let again = cache.get_or_load("a", || load("second")).unwrap();
+    assert!(Rc::ptr_eq(&first, &again));
+    assert_eq!((1, 1), (loads.get(), cache.loaded()));
+
+    // Failures aren't cached.
+    assert!(cache.get_or_load("b", || Err(())).is_err());
+    let other = cache.get_or_load("b", || load("other")).unwrap();
+    assert_eq!((2, 2), (loads.get(), cache.loaded()));
+
+    // Dropping the last handle unloads the value, and the next request loads it again.
+    drop(first);
+    assert_eq!(2, cache.loaded());
+    drop(again);
+    assert_eq!(1, cache.loaded());
+    assert_eq!("third", *cache.get_or_load("a", || load("third")).unwrap());
+    assert_eq!("other", *other);
+    assert_eq!(3, loads.get());
+}
+
+#[test]
+fn test_model_cache() {
+    use headless::HeadlessContext;
+    use imagefmt::{self, ColFmt, ColType};
+    use model::ModelVertex;
+    use std::process;
+
+    // A directory of the process's own, so that test runs at the same time don't share files.
+    let dir = env::temp_dir().join(format!("gl-test-assets-{}", process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("triangle.obj"),
+              "mtllib triangle.mtl\nv 0 0 0\nv 1 0 0\nv 0 1 0\nusemtl red\nf 1 2 3\n").unwrap();
+    fs::write(dir.join("triangle.mtl"), "newmtl red\nmap_Kd red.png\n").unwrap();
+    imagefmt::write(dir.join("red.png"), 1, 1, ColFmt::RGB, &[255, 0, 0], ColType::Color)
+        .unwrap();
+
+    let context = match HeadlessContext::for_test(1, 1) {
+        Some(context) => context,
+        None => return,
+    };
+    let assets = Assets::new(context.gl(), &dir, &dir);
+    let program = Program::new(context.gl(),
+                               "#version 150\n\
+                                in vec3 position;\n\
+                                void main() { gl_Position = vec4(position, 1.0); }\n",
+                               "#version 150\n\
+                                out vec4 out_color;\n\
+                                void main() { out_color = vec4(1.0); }\n",
+                               &["out_color"]).unwrap();
+    let attributes = AttributeBindings::new::<ModelVertex>(&program).unwrap();
+
+    let model = assets.model("triangle.obj", &attributes).unwrap();
+    assert!(Rc::ptr_eq(&model, &assets.model("triangle.obj", &attributes).unwrap()));
+    assert_eq!(1, model.parts.len());
+    assert!(model.parts[0].diffuse_texture.is_some());
+
+    drop(model);
+    assert_eq!(0, assets.models.loaded());
+    assert!(assets.model("missing.obj", &attributes).is_err());
+
+    // In the background, the model is only there once it has been uploaded. A model that fails
+    // to load never is.
+    let streamed = assets.model_async("triangle.obj", &attributes);
+    let missing = assets.model_async("missing.obj", &attributes);
+    assert!(Rc::ptr_eq(&streamed, &assets.model_async("triangle.obj", &attributes)));
+    assert!(!streamed.is_ready());
+    assets.finish_loading();
+    assert!(streamed.get().unwrap().parts[0].diffuse_texture.is_some());
+    assert!(!missing.is_ready());
+
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,238 @@
+//! Cameras moved by the mouse and keyboard.
+//!
+//! The cameras are moved through the `orbit`, `move_forward`, `move_right` and `move_up` actions
+//! of the input bindings. The world is Z-up, like the demo scene.
+
+use input::Input;
+use math::{Mat4, Vec3};
+
+/// How far the cameras turn, in radians per screen coordinate of cursor movement.
+const SENSITIVITY: f32 = 0.005;
+
+/// How close the cameras can get to looking straight up or down, where `look_at` breaks down.
+const MAX_PITCH: f32 = 1.55;
+
+const UP: Vec3 = Vec3([0.0, 0.0, 1.0]);
+
+/// How far in front of a fly camera the target of the orbit camera switched to from it is.
+const SWITCH_DISTANCE: f32 = 3.0;
+
+/// The unit vector pointing in the direction given by `yaw` around the Z-axis, starting from the
+/// X-axis, and `pitch` above the XY plane.
+fn direction(yaw: f32, pitch: f32) -> Vec3 {
+    Vec3([pitch.cos() * yaw.cos(), pitch.cos() * yaw.sin(), pitch.sin()])
+}
+
+/// The yaw and pitch of a direction, as taken by `direction`.
+fn angles(direction: Vec3) -> (f32, f32) {
+    let horizontal = (direction[0] * direction[0] + direction[1] * direction[1]).sqrt();
+    (direction[1].atan2(direction[0]), direction[2].atan2(horizontal))
+}
+
+/// A camera circling a target. Dragging with the `orbit` button held turns it around the target,
+/// and scrolling moves it closer or further away.
+#[derive(Copy, Clone, Debug)]
+pub struct OrbitCamera {
+    pub target: Vec3,
+    pub distance: f32,
+    pub yaw: f32,
+    pub pitch: f32,
+}
+
+impl OrbitCamera {
+    /// The closest and furthest the camera zooms to the target.
+    pub const MIN_DISTANCE: f32 = 0.5;
+    pub const MAX_DISTANCE: f32 = 50.0;
+
+    /// A camera at `eye`, looking at `target`.
+    pub fn new(eye: Vec3, target: Vec3) -> OrbitCamera {
+        let (yaw, pitch) = angles(eye - target);
+        OrbitCamera {
+            target,
+            distance: (eye - target).length(),
+            yaw,
+            pitch,
+        }
+    }
+
+    pub fn eye(&self) -> Vec3 {
+        self.target + direction(self.yaw, self.pitch) * self.distance
+    }
+
+    /// Turn the camera around the target for a drag by the given cursor movement. Dragging right
+    /// turns the scene right, so the camera goes left.
+    pub fn turn(&mut self, dx: f32, dy: f32) {
+        self.yaw -= dx * SENSITIVITY;
+        self.pitch = (self.pitch + dy * SENSITIVITY).clamp(-MAX_PITCH, MAX_PITCH);
+    }
+
+    /// Zoom in for positive `offset`, by a tenth of the distance for each step of the wheel.
+    pub fn scroll(&mut self, offset: f64) {
+        self.distance = (self.distance * 0.9f32.powf(offset as f32))
+            .clamp(OrbitCamera::MIN_DISTANCE, OrbitCamera::MAX_DISTANCE);
+    }
+
+    pub fn view(&self) -> Mat4 {
+        Mat4::look_at(self.eye(), self.target, UP)
+    }
+}
+
+/// A camera that flies where it looks. The cursor turns it, and the movement actions move it.
+#[derive(Copy, Clone, Debug)]
+pub struct FlyCamera {
+    pub position: Vec3,
+    pub yaw: f32,
+    pub pitch: f32,
+
+    /// How fast the camera moves, in units per second.
+    pub speed: f32,
+}
+
+impl FlyCamera {
+    /// A camera at `eye`, looking toward `target`.
+    pub fn new(eye: Vec3, target: Vec3) -> FlyCamera {
+        let (yaw, pitch) = angles(target - eye);
+        FlyCamera {
+            position: eye,
+            yaw,
+            pitch,
+            speed: 2.0,
+        }
+    }
+
+    pub fn forward(&self) -> Vec3 {
+        direction(self.yaw, self.pitch)
+    }
+
+    /// Turn the camera to follow the given cursor movement.
+    pub fn turn(&mut self, dx: f32, dy: f32) {
+        self.yaw -= dx * SENSITIVITY;
+        self.pitch = (self.pitch - dy * SENSITIVITY).clamp(-MAX_PITCH, MAX_PITCH);
+    }
+
+    /// Move the camera for `seconds`, going forward, right and up by the given fractions of its
+    /// speed.
+    pub fn fly(&mut self, seconds: f32, ahead: f32, across: f32, above: f32) {
+        let forward = self.forward();
+        let mut right = forward.cross(UP);
+        right.normalize();
+
+        let velocity = forward * ahead + right * across + UP * above;
+        self.position = self.position + velocity * (self.speed * seconds);
+    }
+
+    pub fn view(&self) -> Mat4 {
+        Mat4::look_at(self.position, self.position + self.forward(), UP)
+    }
+}
+
+/// The camera the window is viewed through, which can be switched between orbiting and flying.
+#[derive(Copy, Clone, Debug)]
+pub enum Camera {
+    Orbit(OrbitCamera),
+    Fly(FlyCamera),
+}
+
+impl Camera {
+    /// Switch to the other kind of camera, keeping the view where it is. A fly camera has no
+    /// target, so the orbit camera switched to from one circles the point `SWITCH_DISTANCE` in
+    /// front of it.
+    pub fn switch(&mut self) {
+        *self = match *self {
+            Camera::Orbit(orbit) => Camera::Fly(FlyCamera::new(orbit.eye(), orbit.target)),
+            Camera::Fly(fly) => {
+                let target = fly.position + fly.forward() * SWITCH_DISTANCE;
+                Camera::Orbit(OrbitCamera::new(fly.position, target))
+            }
+        };
+    }
+
+    /// Move the camera for the input of a frame that took `seconds`.
+    pub fn update(&mut self, input: &Input, seconds: f32) {
+        let (dx, dy) = input.cursor_delta();
+        let (dx, dy) = (dx as f32, dy as f32);
+
+        match *self {
+            Camera::Orbit(ref mut orbit) => {
+                if input.held("orbit") {
+                    orbit.turn(dx, dy);
+                }
+                orbit.scroll(input.scroll().1);
+            }
+            Camera::Fly(ref mut fly) => {
+                fly.turn(dx, dy);
+                fly.fly(seconds, input.axis("move_forward"), input.axis("move_right"),
+                        input.axis("move_up"));
+            }
+        }
+    }
+
+    pub fn view(&self) -> Mat4 {
+        match *self {
+            Camera::Orbit(ref orbit) => orbit.view(),
+            Camera::Fly(ref fly) => fly.view(),
+        }
+    }
+}
+
+#[cfg(test)]
+fn assert_near(expected: Vec3, actual: Vec3) {
+    assert!((expected - actual).length() < 1e-5, "expected {:?}, got {:?}", expected, actual);
+}
+
+#[test]
+fn test_orbit_camera() {
+    use input::{Bindings, Button, Event};
+
+    let eye = Vec3([2.0, 2.0, 1.5]);
+    let mut camera = Camera::Orbit(OrbitCamera::new(eye, Vec3::zero()));
+    let orbit = |camera: &Camera| match *camera {
+        Camera::Orbit(orbit) => orbit,
+        Camera::Fly(_) => panic!("expected an orbit camera"),
+    };
+    assert_near(eye, orbit(&camera).eye());
+
+    // The cursor only turns the camera while the button is held.
+    let mut input = Input::new(Bindings::default());
+    input.handle(&Event::CursorMoved(100.0, 100.0));
+    input.handle(&Event::CursorMoved(200.0, 100.0));
+    camera.update(&input, 0.1);
+    assert_near(eye, orbit(&camera).eye());
+
+    input.begin_frame();
+    input.handle(&Event::Press(Button::mouse(1)));
+    input.handle(&Event::CursorMoved(200.0, 10000.0));
+    input.handle(&Event::Scrolled(0.0, 1.0));
+    camera.update(&input, 0.1);
+    assert_eq!(MAX_PITCH, orbit(&camera).pitch);
+    assert!((orbit(&camera).distance - eye.length() * 0.9).abs() < 1e-5);
+
+    let mut zoomed = orbit(&camera);
+    zoomed.scroll(-1000.0);
+    assert_eq!(OrbitCamera::MAX_DISTANCE, zoomed.distance);
+}
+
+#[test]
+fn test_fly_camera() {
+    let mut camera = FlyCamera::new(Vec3([0.0, -2.0, 0.0]), Vec3::zero());
+    assert_near(Vec3([0.0, 1.0, 0.0]), camera.forward());
+
+    // Forward and right for half a second at 2 units per second.
+    camera.fly(0.5, 1.0, 1.0, 0.0);
+    assert_near(Vec3([1.0, -1.0, 0.0]), camera.position);
+
+    camera.turn(0.0, 10000.0);
+    assert_eq!(-MAX_PITCH, camera.pitch);
+
+    // Switching to orbiting and back keeps the view.
+    let mut switched = Camera::Fly(camera);
+    switched.switch();
+    switched.switch();
+    match switched {
+        Camera::Fly(fly) => {
+            assert_near(camera.position, fly.position);
+            assert_near(camera.forward(), fly.forward());
+        }
+        Camera::Orbit(_) => panic!("expected a fly camera"),
+    }
+}
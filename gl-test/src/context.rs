@@ -0,0 +1,49 @@
+//! A token standing for the current OpenGL context.
+//!
+//! GL objects borrow the token when they're created, so the borrow checker makes sure they are
+//! all dropped (and deleted) before it, and so before the context itself goes away. The token
+//! isn't `Send`, and neither is anything borrowing it, because a context is only current on the
+//! thread that made it current.
+
+use gl;
+use gl::types::*;
+use std::ffi::CStr;
+use std::marker::PhantomData;
+
+pub struct GlContext {
+    _not_send: PhantomData<*const ()>,
+}
+
+impl GlContext {
+    /// Create the token for the context that is current on this thread.
+    ///
+    /// The context must have its function pointers loaded, and must stay current on this thread
+    /// and alive for as long as the token exists. Create the token right after the window or
+    /// headless context, so that it is dropped first.
+    pub unsafe fn new() -> GlContext {
+        GlContext { _not_send: PhantomData }
+    }
+
+    /// The major and minor version of the context, like `(3, 2)`.
+    pub fn version(&self) -> (GLint, GLint) {
+        let (mut major, mut minor) = (0, 0);
+        unsafe {
+            gl::GetIntegerv(gl::MAJOR_VERSION, &mut major);
+            gl::GetIntegerv(gl::MINOR_VERSION, &mut minor);
+        }
+        (major, minor)
+    }
+
+    /// Whether the context supports the named extension, like `"GL_ARB_texture_swizzle"`.
+    pub fn has_extension(&self, name: &str) -> bool {
+        unsafe {
+            let mut count = 0;
+            gl::GetIntegerv(gl::NUM_EXTENSIONS, &mut count);
+            (0..count as GLuint).any(|i| {
+                let extension = gl::GetStringi(gl::EXTENSIONS, i);
+                !extension.is_null()
+                    && CStr::from_ptr(extension as *const _).to_bytes() == name.as_bytes()
+            })
+        }
+    }
+}
@@ -0,0 +1,199 @@
+//! Reporting OpenGL errors and driver messages while debugging.
+//!
+//! With `GL_KHR_debug`, which is core since GL 4.3, the driver calls back with a message for
+//! each error, warning or hint as it happens, and messages below a chosen severity are dropped
+//! by the driver itself. Without it, the calls wrapped in `gl_check!` ask `glGetError` whether
+//! they failed, and report where they were made from.
+
+use context::GlContext;
+use gl;
+use gl::types::*;
+use std::ffi::CStr;
+use std::fmt;
+use std::os::raw::c_void;
+use std::ptr;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Make a GL call and, when error checks are enabled, report any error it raised along with the
+/// call and where it was made. Evaluates to the result of the call.
+macro_rules! gl_check {
+    ($call:expr) => {{
+        let result = $call;
+        $crate::debug::check_errors(stringify!($call), file!(), line!());
+        result
+    }};
+}
+
+/// Whether `gl_check!` calls `glGetError`, which is only worth its cost in debug mode without
+/// debug output.
+static CHECK_ERRORS: AtomicBool = AtomicBool::new(false);
+
+/// How important a driver message is, from least to most.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Notification,
+    Low,
+    Medium,
+    High,
+}
+
+impl Severity {
+    pub const ALL: [Severity; 4] =
+        [Severity::Notification, Severity::Low, Severity::Medium, Severity::High];
+
+    pub fn from_gl(severity: GLenum) -> Option<Severity> {
+        Severity::ALL.iter().cloned().find(|s| s.gl_enum() == severity)
+    }
+
+    pub fn gl_enum(self) -> GLenum {
+        match self {
+            Severity::Notification => gl::DEBUG_SEVERITY_NOTIFICATION,
+            Severity::Low => gl::DEBUG_SEVERITY_LOW,
+            Severity::Medium => gl::DEBUG_SEVERITY_MEDIUM,
+            Severity::High => gl::DEBUG_SEVERITY_HIGH,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Severity::Notification => "notification",
+            Severity::Low => "low",
+            Severity::Medium => "medium",
+            Severity::High => "high",
+        }
+    }
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Severity {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Severity, String> {
+        Severity::ALL.iter().cloned().find(|severity| severity.name() == s)
+            .ok_or_else(|| format!("unknown severity `{}`", s))
+    }
+}
+
+/// How `enable` arranged for errors to be reported.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Reporting {
+    /// The driver reports errors and other messages through debug output.
+    DebugOutput,
+
+    /// Calls wrapped in `gl_check!` check for errors, and nothing else is reported.
+    ErrorChecks,
+}
+
+/// Report GL errors, and driver messages of at least `severity`, on standard error from now on.
+///
+/// Debug output is only guaranteed to report everything in a debug context, so create the
+/// context with the debug flag where possible.
+pub fn enable(context: &GlContext, severity: Severity) -> Reporting {
+    if context.version() >= (4, 3) || context.has_extension("GL_KHR_debug") {
+        unsafe {
+            gl::Enable(gl::DEBUG_OUTPUT);
+
+            // Call back from inside the call that caused the message rather than later from
+            // another thread, so that a debugger stopped in the callback shows the call site.
+            gl::Enable(gl::DEBUG_OUTPUT_SYNCHRONOUS);
+            gl::DebugMessageCallback(Some(message_callback), ptr::null());
+
+            for &other in &Severity::ALL {
+                let enabled = if other >= severity { gl::TRUE } else { gl::FALSE };
+                gl::DebugMessageControl(gl::DONT_CARE, gl::DONT_CARE, other.gl_enum(), 0,
+                                        ptr::null(), enabled);
+            }
+        }
+        Reporting::DebugOutput
+    } else {
+        // Clear errors raised before now, so that they aren't blamed on the first checked call.
+        unsafe { while gl::GetError() != gl::NO_ERROR {} }
+        CHECK_ERRORS.store(true, Ordering::Relaxed);
+        Reporting::ErrorChecks
+    }
+}
+
+/// Report the errors raised since the last check, if error checks are enabled, as coming from
+/// `call` at `file` and `line`. An error raised by a call that isn't checked is reported at the
+/// next one that is.
+pub fn check_errors(call: &str, file: &str, line: u32) {
+    if !CHECK_ERRORS.load(Ordering::Relaxed) {
+        return;
+    }
+
+    loop {
+        let error = unsafe { gl::GetError() };
+        if error == gl::NO_ERROR {
+            break;
+        }
+        eprintln!("{}:{}: {} from `{}`", file, line, error_name(error), call);
+    }
+}
+
+fn error_name(error: GLenum) -> &'static str {
+    match error {
+        gl::INVALID_ENUM => "GL_INVALID_ENUM",
+        gl::INVALID_VALUE => "GL_INVALID_VALUE",
+        gl::INVALID_OPERATION => "GL_INVALID_OPERATION",
+        gl::INVALID_FRAMEBUFFER_OPERATION => "GL_INVALID_FRAMEBUFFER_OPERATION",
+        gl::OUT_OF_MEMORY => "GL_OUT_OF_MEMORY",
+        gl::STACK_UNDERFLOW => "GL_STACK_UNDERFLOW",
+        gl::STACK_OVERFLOW => "GL_STACK_OVERFLOW",
+        _ => "unknown GL error",
+    }
+}
+
+extern "system" fn message_callback(source: GLenum, gltype: GLenum, id: GLuint, severity: GLenum,
+                                    _length: GLsizei, message: *const GLchar,
+                                    _user_param: *mut c_void) {
+    let message = unsafe { CStr::from_ptr(message) }.to_string_lossy();
+    eprintln!("{}", describe(source, gltype, id, severity, &message));
+}
+
+/// A line describing a debug output message.
+fn describe(source: GLenum, gltype: GLenum, id: GLuint, severity: GLenum, message: &str)
+            -> String {
+    let source = match source {
+        gl::DEBUG_SOURCE_API => "the API",
+        gl::DEBUG_SOURCE_WINDOW_SYSTEM => "the window system",
+        gl::DEBUG_SOURCE_SHADER_COMPILER => "the shader compiler",
+        gl::DEBUG_SOURCE_THIRD_PARTY => "a third party",
+        gl::DEBUG_SOURCE_APPLICATION => "the application",
+        _ => "an unknown source",
+    };
+    let gltype = match gltype {
+        gl::DEBUG_TYPE_ERROR => "error",
+        gl::DEBUG_TYPE_DEPRECATED_BEHAVIOR => "deprecated behavior",
+        gl::DEBUG_TYPE_UNDEFINED_BEHAVIOR => "undefined behavior",
+        gl::DEBUG_TYPE_PORTABILITY => "portability issue",
+        gl::DEBUG_TYPE_PERFORMANCE => "performance issue",
+        gl::DEBUG_TYPE_MARKER => "marker",
+        gl::DEBUG_TYPE_PUSH_GROUP => "group push",
+        gl::DEBUG_TYPE_POP_GROUP => "group pop",
+        _ => "message",
+    };
+    let severity = Severity::from_gl(severity).map_or("unknown", Severity::name);
+
+    format!("GL {} severity {} {} from {}: {}", severity, gltype, id, source, message)
+}
+
+#[test]
+fn test_severity() {
+    assert!(Severity::Notification < Severity::Low && Severity::Medium < Severity::High);
+    for &severity in &Severity::ALL {
+        assert_eq!(Some(severity), Severity::from_gl(severity.gl_enum()));
+        assert_eq!(Ok(severity), severity.to_string().parse());
+    }
+    assert_eq!(None, Severity::from_gl(gl::DEBUG_TYPE_ERROR));
+    assert!("loud".parse::<Severity>().is_err());
+
+    assert_eq!("GL high severity error 1280 from the API: GL_INVALID_ENUM in glTexParameteri",
+               describe(gl::DEBUG_SOURCE_API, gl::DEBUG_TYPE_ERROR, 1280,
+                        gl::DEBUG_SEVERITY_HIGH, "GL_INVALID_ENUM in glTexParameteri"));
+}
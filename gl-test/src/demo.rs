@@ -0,0 +1,411 @@
+use gl;
+use gltf;
+use imagefmt;
+use math;
+use model;
+use raster;
+use texture;
+use assets::{Assets, Streamed, StreamedTexture};
+use context::GlContext;
+use mesh::{Mesh, Primitive};
+use model::Model;
+use object::{Texture2d, Usage};
+use reload::{LoadError, ShaderFiles, ShaderSources};
+use render_state::RenderState;
+use shader::{Program, ReflectionError};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use texture::SamplerDesc;
+use uniform::TextureUnit;
+use vertex::AttributeBindings;
+
+#[derive(Copy, Clone, Debug, PartialEq, VertexLayout)]
+#[repr(C, packed)]
+pub struct Vertex {
+    pub position: [f32; 3],
+    pub color: [f32; 3],
+    pub texcoord: [f32; 2],
+}
+
+/// A cube with the image on every face. Each face has its corners in the same order as seen from
+/// outside the cube, with the sides upright around the Z-axis: top-left (red), top-right (green),
+/// bottom-right (blue), bottom-left (white).
+pub static VERTICES: [Vertex; 24] = [
+    // +X
+    Vertex { position: [ 0.5, -0.5,  0.5], color: [1.0, 0.0, 0.0], texcoord: [0.0, 0.0] },
+    Vertex { position: [ 0.5,  0.5,  0.5], color: [0.0, 1.0, 0.0], texcoord: [1.0, 0.0] },
+    Vertex { position: [ 0.5,  0.5, -0.5], color: [0.0, 0.0, 1.0], texcoord: [1.0, 1.0] },
+    Vertex { position: [ 0.5, -0.5, -0.5], color: [1.0, 1.0, 1.0], texcoord: [0.0, 1.0] },
+    // -X
+    Vertex { position: [-0.5,  0.5,  0.5], color: [1.0, 0.0, 0.0], texcoord: [0.0, 0.0] },
+    Vertex { position: [-0.5, -0.5,  0.5], color: [0.0, 1.0, 0.0], texcoord: [1.0, 0.0] },
+    Vertex { position: [-0.5, -0.5, -0.5], color: [0.0, 0.0, 1.0], texcoord: [1.0, 1.0] },
+    Vertex { position: [-0.5,  0.5, -0.5], color: [1.0, 1.0, 1.0], texcoord: [0.0, 1.0] },
+    // +Y
+    Vertex { position: [ 0.5,  0.5,  0.5], color: [1.0, 0.0, 0.0], texcoord: [0.0, 0.0] },
+    Vertex { position: [-0.5,  0.5,  0.5], color: [0.0, 1.0, 0.0], texcoord: [1.0, 0.0] },
+    Vertex { position: [-0.5,  0.5, -0.5], color: [0.0, 0.0, 1.0], texcoord: [1.0, 1.0] },
+    Vertex { position: [ 0.5,  0.5, -0.5], color: [1.0, 1.0, 1.0], texcoord: [0.0, 1.0] },
+    // -Y
+    Vertex { position: [-0.5, -0.5,  0.5], color: [1.0, 0.0, 0.0], texcoord: [0.0, 0.0] },
+    Vertex { position: [ 0.5, -0.5,  0.5], color: [0.0, 1.0, 0.0], texcoord: [1.0, 0.0] },
+    Vertex { position: [ 0.5, -0.5, -0.5], color: [0.0, 0.0, 1.0], texcoord: [1.0, 1.0] },
+    Vertex { position: [-0.5, -0.5, -0.5], color: [1.0, 1.0, 1.0], texcoord: [0.0, 1.0] },
+    // +Z, with +Y up
+    Vertex { position: [-0.5,  0.5,  0.5], color: [1.0, 0.0, 0.0], texcoord: [0.0, 0.0] },
+    Vertex { position: [ 0.5,  0.5,  0.5], color: [0.0, 1.0, 0.0], texcoord: [1.0, 0.0] },
+    Vertex { position: [ 0.5, -0.5,  0.5], color: [0.0, 0.0, 1.0], texcoord: [1.0, 1.0] },
+    Vertex { position: [-0.5, -0.5,  0.5], color: [1.0, 1.0, 1.0], texcoord: [0.0, 1.0] },
+    // -Z, with +Y up
+    Vertex { position: [ 0.5,  0.5, -0.5], color: [1.0, 0.0, 0.0], texcoord: [0.0, 0.0] },
+    Vertex { position: [-0.5,  0.5, -0.5], color: [0.0, 1.0, 0.0], texcoord: [1.0, 0.0] },
+    Vertex { position: [-0.5, -0.5, -0.5], color: [0.0, 0.0, 1.0], texcoord: [1.0, 1.0] },
+    Vertex { position: [ 0.5, -0.5, -0.5], color: [1.0, 1.0, 1.0], texcoord: [0.0, 1.0] },
+];
+
+/// Two counter-clockwise triangles for each face: bottom-left, then top-right.
+pub static ELEMENTS: [u32; 36] = [
+     0,  3,  2,  2,  1,  0,
+     4,  7,  6,  6,  5,  4,
+     8, 11, 10, 10,  9,  8,
+    12, 15, 14, 14, 13, 12,
+    16, 19, 18, 18, 17, 16,
+    20, 23, 22, 22, 21, 20,
+];
+
+/// The images used for the `tex_kitten` and `tex_puppy` textures, relative to the asset root.
+pub const KITTEN: &str = "sample.png";
+pub const PUPPY: &str = "sample2.png";
+
+/// The kitten and puppy images from the asset root in RGB format, for the software rasterizer.
+pub fn load_images(asset_dir: &Path) -> (imagefmt::Image, imagefmt::Image) {
+    (imagefmt::read(asset_dir.join(KITTEN), imagefmt::ColFmt::RGB).unwrap(),
+     imagefmt::read(asset_dir.join(PUPPY), imagefmt::ColFmt::RGB).unwrap())
+}
+
+/// Where the scene is viewed from when no camera moves it, looking at the origin.
+pub const EYE: [f32; 3] = [2.0, 2.0, 1.5];
+
+/// The view matrix for looking at the scene from `EYE`.
+pub fn default_view() -> math::Mat4 {
+    math::Mat4::look_at(
+        math::Vec3(EYE),
+        math::Vec3([0.0, 0.0, 0.0]),
+        math::Vec3([0.0, 0.0, 1.0]))
+}
+
+/// The projection of the demo's own cameras, for a viewport with the given aspect ratio.
+pub fn projection(aspect: f32) -> math::Mat4 {
+    // The far plane leaves room for cameras to move away from the scene.
+    math::Mat4::perspective(math::TAU / 8.0, aspect, 0.1, 100.0)
+}
+
+/// Build the combined model, view and projection matrix (the `trans` uniform) for the scene as it
+/// looks `elapsed_seconds` after the start of the animation through the camera given by `view`.
+pub fn transform(elapsed_seconds: f32, view: math::Mat4, aspect: f32) -> math::Mat4 {
+    let proj = projection(aspect);
+
+    // Vary the model matrix over time.
+    let scale = (elapsed_seconds * 5.0).sin() * 0.25 + 0.75;
+    let model =
+        math::Mat4::rotate_z(math::TAU / 2.0 * elapsed_seconds) *
+        math::Mat4::scale(scale, scale, scale);
+
+    proj * view * model
+}
+
+/// Whether `path` is drawn as a glTF scene rather than an OBJ model, going by its extension.
+fn is_gltf(path: &Path) -> bool {
+    path.extension().is_some_and(|extension| extension == "gltf" || extension == "glb")
+}
+
+/// The shader files the demo draws with: `demo` for the cube, or `model` for the model or scene
+/// at `scene`. When `state` culls no faces, they are built with `DOUBLE_SIDED` defined, so that
+/// the back faces that then show are shaded as such.
+pub fn shader_files(shader_dir: &Path, scene: Option<&Path>, state: RenderState) -> ShaderFiles {
+    let mut shaders =
+        ShaderFiles::new(shader_dir, if scene.is_some() { "model" } else { "demo" });
+    if state.cull_face.is_none() {
+        shaders.define("DOUBLE_SIDED", "1");
+    }
+    shaders
+}
+
+/// Models and scenes are modelled with +Y up, but the demo's cameras have +Z up.
+fn y_up() -> math::Mat4 {
+    math::Mat4::rotate_x(math::TAU / 4.0)
+}
+
+/// What the demo draws.
+enum Content<'a> {
+    /// The kitten/puppy cube, animated over time.
+    Cube {
+        mesh: Mesh<'a, Vertex>,
+        textures: [Rc<StreamedTexture<'a>>; 2],
+    },
+
+    /// An OBJ model at the origin, drawn once it has loaded in the background. The path is kept
+    /// to load it again for a reloaded program with other attribute locations.
+    Model(PathBuf, Rc<Streamed<Model<'a>>>),
+
+    /// A glTF scene, which can be seen through its own camera.
+    Scene(gltf::Scene<'a>),
+}
+
+/// The GL objects making up the demo scene: the kitten/puppy cube, or a model or scene loaded
+/// from a file. Both the windowed and the headless render loops draw through this, so they
+/// exercise exactly the same pipeline.
+pub struct Demo<'a> {
+    context: &'a GlContext,
+    program: Program<'a>,
+    content: Content<'a>,
+
+    /// Drawn in place of the texture of model parts that don't have one.
+    blank: Texture2d<'a>,
+
+    /// Whether a scene with a camera is seen through it rather than the view passed to `draw`.
+    scene_camera: bool,
+
+    state: RenderState,
+}
+
+/// Check that the program has the uniforms the demo sets, so that setting them can't fail later,
+/// and match its attributes to the vertex data.
+fn check_program(program: &Program) -> Result<AttributeBindings, ReflectionError> {
+    program.uniform("tex_kitten", gl::SAMPLER_2D)?;
+    program.uniform("tex_puppy", gl::SAMPLER_2D)?;
+    program.uniform("trans", gl::FLOAT_MAT4)?;
+    program.uniform("time", gl::FLOAT)?;
+    AttributeBindings::new::<Vertex>(program)
+}
+
+impl<'a> Demo<'a> {
+    /// Create the demo's GL objects in the context of `assets`, with the shader program loaded
+    /// from `shaders`, which should come from `shader_files`. With a `scene`, the OBJ model or
+    /// glTF scene at that path in the asset root is drawn instead of the cube. Either is drawn
+    /// with the depth and culling given by `state`.
+    pub fn new(assets: &Assets<'a>, shaders: &mut ShaderFiles, state: RenderState,
+               scene: Option<&Path>) -> Result<Demo<'a>, LoadError> {
+        let context = assets.context();
+
+        // Compile the vertex and fragment shaders and link them into a shader program.
+        let program = shaders.load(context)?;
+
+        let content = match scene {
+            Some(path) if is_gltf(path) => {
+                let attributes = model::check_program(&program)?;
+                Content::Scene(gltf::load(assets, path, &attributes)?)
+            }
+            Some(path) => {
+                // The model is left out until it has loaded, and for good if it fails to.
+                let attributes = model::check_program(&program)?;
+                Content::Model(path.to_owned(), assets.model_async(path, &attributes))
+            }
+            None => {
+                let attributes = check_program(&program)?;
+
+                // Copy the vertex and element data to buffers, and specify the layout of the
+                // vertex data.
+                let mesh = Mesh::indexed(context, attributes, &VERTICES, &ELEMENTS,
+                                         Usage::Static, Primitive::Triangles);
+
+                // Start loading the textures, which are drawn as placeholders until `assets`
+                // uploads them. The images aren't flipped, because the texture coordinates of
+                // the cube put the top row at 0.
+                let sampler = SamplerDesc::default();
+                let textures = [assets.texture_async(KITTEN, &sampler, false)?,
+                                assets.texture_async(PUPPY, &sampler, false)?];
+                Content::Cube { mesh, textures }
+            }
+        };
+
+        let demo = Demo {
+            context,
+            program,
+            content,
+            blank: texture::white(context),
+            scene_camera: true,
+            state,
+        };
+        demo.setup_program();
+        Ok(demo)
+    }
+
+    /// Replace the shader program with one built by `shaders` from `sources`, which were read by
+    /// its `reader`. If the new program fails to build or lacks a variable the demo uses, the old
+    /// one is kept and the error is returned. A model is loaded again through `assets` if the new
+    /// program reads its attributes from other locations.
+    pub fn reload_shaders(&mut self, assets: &Assets<'a>, shaders: &mut ShaderFiles,
+                          sources: ShaderSources) -> Result<(), LoadError> {
+        let program = shaders.build(self.context, sources)?;
+
+        // The new program may have assigned different attribute locations.
+        match self.content {
+            Content::Cube { ref mut mesh, .. } => mesh.set_attributes(check_program(&program)?),
+            Content::Model(ref path, ref mut model) => {
+                *model = assets.model_async(path, &model::check_program(&program)?);
+            }
+            Content::Scene(ref mut scene) => {
+                scene.set_attributes(&model::check_program(&program)?);
+            }
+        }
+
+        self.program = program;
+        self.setup_program();
+        Ok(())
+    }
+
+    /// Assign the program's samplers to texture units.
+    fn setup_program(&self) {
+        if let Content::Cube { .. } = self.content {
+            self.program.bind();
+            self.program.set("tex_kitten", TextureUnit(0)).expect("checked by check_program");
+            self.program.set("tex_puppy", TextureUnit(1)).expect("checked by check_program");
+        }
+    }
+
+    /// Switch between seeing a glTF scene through its own camera, if it has one, and through the
+    /// view passed to `draw`. Scenes start out seen through their camera.
+    pub fn toggle_scene_camera(&mut self) {
+        self.scene_camera = !self.scene_camera;
+    }
+
+    /// Draw one frame of the scene as it looks `elapsed_seconds` after the start of the animation,
+    /// seen through `view` and projected for a viewport with the given aspect ratio.
+    pub fn draw(&self, elapsed_seconds: f32, view: math::Mat4, aspect: f32) {
+        // Clear the screen to black and the depth buffer to the far plane.
+        self.state.apply();
+        self.state.clear([0.0, 0.0, 0.0, 1.0]);
+
+        match self.content {
+            Content::Cube { ref mesh, ref textures } => {
+                self.program.bind();
+                textures[0].bind(0);
+                textures[1].bind(1);
+
+                // Update the `time` and `trans` uniforms.
+                self.program.set("time", elapsed_seconds).expect("checked by check_program");
+                self.program.set("trans", transform(elapsed_seconds, view, aspect))
+                    .expect("checked by check_program");
+
+                mesh.draw();
+            }
+            Content::Model(_, ref model) => {
+                if let Some(model) = model.get() {
+                    model.draw(&self.program, projection(aspect) * view, y_up(), &self.blank);
+                }
+            }
+            Content::Scene(ref scene) => {
+                let camera = if self.scene_camera { scene.camera(aspect) } else { None };
+                let view_projection =
+                    camera.unwrap_or_else(|| projection(aspect) * view * y_up());
+                scene.draw(&self.program, view_projection, &self.blank);
+            }
+        }
+    }
+}
+
+/// The demo scene drawn by the software rasterizer instead of OpenGL.
+pub struct SoftwareDemo {
+    tex_kitten: raster::Texture,
+    tex_puppy: raster::Texture,
+    state: RenderState,
+}
+
+impl SoftwareDemo {
+    pub fn new(asset_dir: &Path, state: RenderState) -> SoftwareDemo {
+        let (kitten, puppy) = load_images(asset_dir);
+
+        SoftwareDemo {
+            tex_kitten: raster::Texture::new(kitten.w, kitten.h, &kitten.buf),
+            tex_puppy: raster::Texture::new(puppy.w, puppy.h, &puppy.buf),
+            state,
+        }
+    }
+
+    /// Draw one frame of the scene into `framebuffer`, like `Demo::draw`.
+    pub fn draw(&self, framebuffer: &mut raster::Framebuffer, elapsed_seconds: f32) {
+        let aspect = framebuffer.width as f32 / framebuffer.height as f32;
+        let uniforms = raster::Uniforms {
+            trans: transform(elapsed_seconds, default_view(), aspect),
+            time: elapsed_seconds,
+            tex_kitten: &self.tex_kitten,
+            tex_puppy: &self.tex_puppy,
+        };
+
+        framebuffer.clear(0.0, 0.0, 0.0, self.state.clear_depth);
+        raster::draw_elements(framebuffer, &VERTICES, &ELEMENTS, &uniforms, &self.state);
+    }
+}
+
+#[test]
+fn test_draw_scene() {
+    use headless::{Framebuffer, HeadlessContext};
+    use reload;
+    use screenshot;
+    use std::env;
+    use std::fs;
+    use std::process;
+
+    // A quad half the height of the camera's view, moved to fill the middle of its right half,
+    // seen head on through an orthographic camera. The file's other scene, which isn't the one
+    // shown, has a camera and a copy of the quad of its own that come first in the file.
+    let dir = env::temp_dir().join(format!("gl-test-scene-{}", process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let quad = [-0.5f32, -0.5, 0.0, 0.5, -0.5, 0.0, 0.5, 0.5, 0.0, -0.5, 0.5, 0.0];
+    let bin = quad.iter().flat_map(|x| x.to_le_bytes()).collect::<Vec<_>>();
+    fs::write(dir.join("quad.bin"), bin).unwrap();
+    fs::write(dir.join("quad.gltf"), r#"{
+        "asset": { "version": "2.0" },
+        "scene": 1,
+        "scenes": [{ "nodes": [0, 1] }, { "nodes": [2] }],
+        "nodes": [
+            { "camera": 1, "translation": [0, 0, 3] },
+            { "mesh": 0, "translation": [-0.5, 0, 0] },
+            { "children": [3, 4] },
+            { "mesh": 0, "translation": [0.5, 0, 0] },
+            { "camera": 0, "translation": [0, 0, 3] }
+        ],
+        "cameras": [
+            { "type": "orthographic",
+              "orthographic": { "xmag": 1, "ymag": 1, "znear": 0.1, "zfar": 10 } },
+            { "type": "orthographic",
+              "orthographic": { "xmag": 2, "ymag": 2, "znear": 0.1, "zfar": 10 } }
+        ],
+        "meshes": [{ "primitives": [{ "attributes": { "POSITION": 0 }, "mode": 6,
+                                      "material": 0 }] }],
+        "materials": [{ "pbrMetallicRoughness": { "baseColorFactor": [1, 0, 0, 1] } }],
+        "buffers": [{ "uri": "quad.bin", "byteLength": 48 }],
+        "bufferViews": [{ "buffer": 0, "byteLength": 48 }],
+        "accessors": [{ "bufferView": 0, "componentType": 5126, "count": 4, "type": "VEC3" }]
+    }"#).unwrap();
+
+    let context = match HeadlessContext::for_test(8, 8) {
+        Some(context) => context,
+        None => return,
+    };
+    let framebuffer = unsafe { Framebuffer::new(8, 8).unwrap() };
+    unsafe { framebuffer.bind(); }
+
+    let scene = Path::new("quad.gltf");
+    let assets = Assets::new(context.gl(), &dir, &dir);
+    let mut shaders =
+        shader_files(&reload::default_shader_dir(), Some(scene), RenderState::default());
+    let demo = Demo::new(&assets, &mut shaders, RenderState::default(), Some(scene))
+        .unwrap_or_else(|e| panic!("{}", e));
+    demo.draw(0.0, default_view(), 1.0);
+    let pixels = unsafe { screenshot::read_pixels(8, 8) };
+
+    // The quad is lit from in front, so it is some shade of its material's red.
+    let pixel = |x: usize, y: usize| &pixels[(y * 8 + x) * 3..][..3];
+    for &(x, y) in &[(4, 3), (7, 4)] {
+        assert!(pixel(x, y)[0] > 128 && pixel(x, y)[1..] == [0, 0], "{:?}", pixel(x, y));
+    }
+    for &(x, y) in &[(3, 3), (0, 4), (6, 1), (6, 6)] {
+        assert_eq!([0, 0, 0], pixel(x, y));
+    }
+
+    unsafe { framebuffer.delete(); }
+
+    fs::remove_dir_all(&dir).unwrap();
+}
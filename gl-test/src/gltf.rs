@@ -0,0 +1,1026 @@
+//! Importing glTF 2.0 scenes, from `.gltf` files with separate or embedded buffers and from
+//! binary `.glb` files.
+//!
+//! The node hierarchy, the triangles of meshes, the base color of materials and cameras are
+//! imported. Animations, skins, morph targets and the other material properties are ignored, and
+//! texture coordinates always come from `TEXCOORD_0`.
+
+use assets::Assets;
+use gl;
+use gl::types::*;
+use math::{Mat4, Vec3};
+use model::{self, IndexedVertices, Model, ModelPart, ModelVertex};
+use object::Texture2d;
+use serde_json::{self, Value};
+use shader::Program;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use texture::{self, Filter, Image, SamplerDesc, TextureError, Wrap};
+use vertex::AttributeBindings;
+
+// The chunk types of a binary glTF file: "JSON" and "BIN\0" as little-endian integers.
+const GLB_JSON_CHUNK: u32 = 0x4E4F_534A;
+const GLB_BIN_CHUNK: u32 = 0x004E_4942;
+
+// glTF samplers use the GL enums for these.
+const FILTERS: [Filter; 6] = [
+    Filter::Nearest,
+    Filter::Linear,
+    Filter::NearestMipmapNearest,
+    Filter::LinearMipmapNearest,
+    Filter::NearestMipmapLinear,
+    Filter::LinearMipmapLinear,
+];
+const WRAPS: [Wrap; 3] = [Wrap::Repeat, Wrap::MirroredRepeat, Wrap::ClampToEdge];
+
+/// An error from importing a glTF file.
+#[derive(Debug)]
+pub enum GltfError {
+    Io(PathBuf, io::Error),
+    Json(PathBuf, serde_json::Error),
+
+    /// A file that breaks the glTF format, or uses a feature that isn't supported.
+    Invalid(PathBuf, String),
+
+    Texture(TextureError),
+}
+
+impl fmt::Display for GltfError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            GltfError::Io(ref path, ref e) =>
+                write!(f, "failed to read {}: {}", path.display(), e),
+            GltfError::Json(ref path, ref e) =>
+                write!(f, "{}: invalid JSON: {}", path.display(), e),
+            GltfError::Invalid(ref path, ref message) =>
+                write!(f, "{}: {}", path.display(), message),
+            GltfError::Texture(ref e) => e.fmt(f),
+        }
+    }
+}
+
+impl Error for GltfError {}
+
+impl From<TextureError> for GltfError {
+    fn from(e: TextureError) -> GltfError {
+        GltfError::Texture(e)
+    }
+}
+
+/// A node of the scene hierarchy.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Node {
+    pub name: String,
+
+    /// The transform from the node's space to its parent's.
+    pub local: Mat4,
+
+    /// The transform from the node's space to the scene's: its parent's `world` times `local`.
+    pub world: Mat4,
+
+    pub children: Vec<usize>,
+    pub mesh: Option<usize>,
+    pub camera: Option<usize>,
+}
+
+impl Node {
+    /// The view matrix of a camera on this node. Cameras look down the node's -Z axis, with its
+    /// +Y axis up.
+    pub fn view(&self) -> Mat4 {
+        let axis = |i: usize| Vec3([self.world[i][0], self.world[i][1], self.world[i][2]]);
+        let eye = axis(3);
+        Mat4::look_at(eye, eye - axis(2), axis(1))
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Projection {
+    /// A perspective projection. `aspect` is `None` if the viewport's aspect ratio is to be used,
+    /// and `z_far` is `None` if there is no far clipping plane.
+    Perspective { fov_y: f32, aspect: Option<f32>, z_near: f32, z_far: Option<f32> },
+
+    Orthographic { x_mag: f32, y_mag: f32, z_near: f32, z_far: f32 },
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Camera {
+    pub name: String,
+    pub projection: Projection,
+}
+
+impl Camera {
+    /// The projection matrix for a viewport with the given aspect ratio, unless the camera has an
+    /// aspect ratio of its own.
+    pub fn projection(&self, viewport_aspect: f32) -> Mat4 {
+        match self.projection {
+            Projection::Perspective { fov_y, aspect, z_near, z_far: Some(z_far) } =>
+                Mat4::perspective(fov_y, aspect.unwrap_or(viewport_aspect), z_near, z_far),
+            Projection::Perspective { fov_y, aspect, z_near, z_far: None } =>
+                Mat4::perspective_infinite(fov_y, aspect.unwrap_or(viewport_aspect), z_near),
+            Projection::Orthographic { x_mag, y_mag, z_near, z_far } =>
+                Mat4::orthographic(x_mag, y_mag, z_near, z_far),
+        }
+    }
+}
+
+/// Where the data of an image comes from.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ImageSource {
+    /// An image file, relative to the same directory as the glTF file.
+    File(PathBuf),
+
+    /// An image file embedded in a buffer or a data URI.
+    Embedded(Vec<u8>),
+}
+
+/// An image and how it is sampled.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct TextureDesc {
+    pub image: usize,
+    pub sampler: SamplerDesc,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Material {
+    pub name: String,
+    pub base_color: [f32; 4],
+    pub base_color_texture: Option<usize>,
+}
+
+/// The contents of a glTF file, read into memory. Objects refer to each other by their index in
+/// these lists, like in the file.
+#[derive(Clone, Debug)]
+pub struct Document {
+    pub nodes: Vec<Node>,
+
+    /// The top-level nodes of the scene to show.
+    pub roots: Vec<usize>,
+
+    pub cameras: Vec<Camera>,
+
+    /// The primitives of each mesh, as triangles and the index of their material.
+    pub meshes: Vec<Vec<(IndexedVertices, Option<usize>)>>,
+
+    pub materials: Vec<Material>,
+    pub textures: Vec<TextureDesc>,
+    pub images: Vec<ImageSource>,
+}
+
+/// A glTF scene uploaded to GL.
+pub struct Scene<'a> {
+    pub nodes: Vec<Node>,
+    pub roots: Vec<usize>,
+    pub cameras: Vec<Camera>,
+
+    /// The meshes, with a part for each primitive.
+    pub meshes: Vec<Model<'a>>,
+}
+
+impl<'a> Scene<'a> {
+    /// The nodes of the scene to show, parents before their children, starting from `roots`.
+    /// Nodes that belong only to other scenes in the file are left out.
+    fn shown_nodes(&self) -> Vec<&Node> {
+        let mut stack: Vec<usize> = self.roots.iter().rev().cloned().collect();
+        let mut shown = Vec::new();
+        while let Some(i) = stack.pop() {
+            shown.push(&self.nodes[i]);
+            stack.extend(self.nodes[i].children.iter().rev());
+        }
+        shown
+    }
+
+    /// The view and projection matrix of the first camera in the hierarchy, for a viewport with
+    /// the given aspect ratio, or `None` if the scene has no camera.
+    pub fn camera(&self, aspect: f32) -> Option<Mat4> {
+        let node = self.shown_nodes().into_iter().find(|node| node.camera.is_some())?;
+        let camera = &self.cameras[node.camera.unwrap()];
+        Some(camera.projection(aspect) * node.view())
+    }
+
+    /// Draw the mesh of every node of the scene where its world transform puts it, with
+    /// `program`, which must have been checked by `model::check_program`.
+    pub fn draw(&self, program: &Program, view_projection: Mat4, blank: &Texture2d) {
+        for node in self.shown_nodes() {
+            if let Some(mesh) = node.mesh {
+                self.meshes[mesh].draw(program, view_projection, node.world, blank);
+            }
+        }
+    }
+
+    /// Point the meshes at the attribute locations of another program, as after reloading
+    /// shaders.
+    pub fn set_attributes(&mut self, attributes: &AttributeBindings) {
+        for part in self.meshes.iter_mut().flat_map(|mesh| &mut mesh.parts) {
+            part.mesh.set_attributes(attributes.clone());
+        }
+    }
+}
+
+/// Import the glTF file at `path`, relative to the asset root, and upload its meshes and
+/// textures.
+pub fn load<'a>(assets: &Assets<'a>, path: &Path, attributes: &AttributeBindings)
+                -> Result<Scene<'a>, GltfError> {
+    // Reading through the asset root keeps the image paths relative to it.
+    let document = read_with(path, &mut |path| fs::read(assets.resolve(path)))?;
+
+    // glTF puts texture coordinate 0 at the top of the image, which is the first row uploaded,
+    // so unlike OBJ textures these aren't flipped. Embedded images are decoded only once.
+    let mut decoded = vec![None; document.images.len()];
+    let textures = document.textures.iter().map(|desc| {
+        let texture = match document.images[desc.image] {
+            ImageSource::File(ref file) => assets.texture(file, &desc.sampler, false)?,
+            ImageSource::Embedded(ref data) => {
+                if decoded[desc.image].is_none() {
+                    let image = Image::decode(data)
+                        .map_err(|e| TextureError::Read(path.to_owned(), e))?;
+                    decoded[desc.image] = Some(image);
+                }
+                let image = decoded[desc.image].as_ref().unwrap();
+                Rc::new(texture::upload(assets.context(), image, &desc.sampler)?)
+            }
+        };
+        Ok(texture)
+    }).collect::<Result<Vec<_>, GltfError>>()?;
+
+    let meshes = document.meshes.iter().map(|primitives| {
+        let parts = primitives.iter().map(|(part, material)| {
+            let material = material.map(|i| &document.materials[i]);
+            let color = material.map_or([1.0; 3], |material| {
+                let [r, g, b, _] = material.base_color;
+                [r, g, b]
+            });
+            let texture = material.and_then(|material| material.base_color_texture)
+                .map(|i| textures[i].clone());
+            ModelPart::new(assets.context(), attributes, part, color, texture)
+        }).collect();
+        Model { parts }
+    }).collect();
+
+    Ok(Scene { nodes: document.nodes, roots: document.roots, cameras: document.cameras, meshes })
+}
+
+/// Read the glTF file at `path`, in either format, along with its buffers.
+pub fn read(path: &Path) -> Result<Document, GltfError> {
+    read_with(path, &mut |path| fs::read(path))
+}
+
+/// Like `read`, but reading files with the given function.
+pub fn read_with<F>(path: &Path, read: &mut F) -> Result<Document, GltfError>
+    where F: FnMut(&Path) -> io::Result<Vec<u8>>
+{
+    let data = read(path).map_err(|e| GltfError::Io(path.to_owned(), e))?;
+    let invalid = |message: String| GltfError::Invalid(path.to_owned(), message);
+
+    let (json, bin) = if data.starts_with(b"glTF") {
+        split_glb(&data).map_err(invalid)?
+    } else {
+        (&data[..], None)
+    };
+    let json: Value = serde_json::from_slice(json)
+        .map_err(|e| GltfError::Json(path.to_owned(), e))?;
+    let dir = path.parent().unwrap_or_else(|| Path::new(""));
+
+    let mut buffers = Vec::new();
+    for (i, buffer) in array(&json, "buffers").iter().enumerate() {
+        let in_buffer = |e: String| invalid(format!("buffer {}: {}", i, e));
+
+        let data = match buffer["uri"].as_str() {
+            Some(uri) => match data_uri(uri).map_err(in_buffer)? {
+                Some(data) => data,
+                None => {
+                    let file = dir.join(percent_decode(uri));
+                    read(&file).map_err(|e| GltfError::Io(file, e))?
+                }
+            },
+            // The binary chunk of a `.glb` file is the first buffer.
+            None => match bin {
+                Some(bin) if i == 0 => bin.to_vec(),
+                _ => return Err(in_buffer("no `uri`".to_string())),
+            },
+        };
+
+        let length = required(integer(buffer, "byteLength").map_err(in_buffer)?, "byteLength")
+            .map_err(in_buffer)?;
+        if data.len() < length {
+            return Err(in_buffer(format!("only {} of {} bytes", data.len(), length)));
+        }
+        buffers.push(data);
+    }
+
+    parse(&json, &buffers, dir).map_err(invalid)
+}
+
+/// Split a binary glTF file into its JSON chunk and its binary chunk, if it has one.
+fn split_glb(data: &[u8]) -> Result<(&[u8], Option<&[u8]>), String> {
+    let word = |offset: usize| {
+        data.get(offset..offset + 4).map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+    };
+
+    if word(4) != Some(2) {
+        return Err("only version 2 of the binary format is supported".to_string());
+    }
+    let length = word(8).unwrap_or(0) as usize;
+    if length > data.len() {
+        return Err(format!("file is truncated to {} of {} bytes", data.len(), length));
+    }
+
+    let mut chunks = Vec::new();
+    let mut offset = 12;
+    while offset + 8 <= length {
+        let chunk_length = word(offset).unwrap() as usize;
+        let chunk_type = word(offset + 4).unwrap();
+        let chunk = data.get(offset + 8..offset + 8 + chunk_length)
+            .ok_or_else(|| "a chunk runs past the end of the file".to_string())?;
+        chunks.push((chunk_type, chunk));
+        offset += 8 + chunk_length;
+    }
+
+    match chunks.first() {
+        Some(&(GLB_JSON_CHUNK, json)) => {
+            let bin = chunks.get(1).filter(|chunk| chunk.0 == GLB_BIN_CHUNK).map(|chunk| chunk.1);
+            Ok((json, bin))
+        }
+        _ => Err("the first chunk isn't JSON".to_string()),
+    }
+}
+
+fn parse(json: &Value, buffers: &[Vec<u8>], dir: &Path) -> Result<Document, String> {
+    let version = json["asset"]["version"].as_str().unwrap_or("none");
+    if !version.starts_with("2.") {
+        return Err(format!("glTF version {} is not supported", version));
+    }
+
+    let images = parse_all(json, "images", "image", |image| {
+        match image["uri"].as_str() {
+            Some(uri) => Ok(match data_uri(uri)? {
+                Some(data) => ImageSource::Embedded(data),
+                None => ImageSource::File(dir.join(percent_decode(uri))),
+            }),
+            None => {
+                let view = required(integer(image, "bufferView")?, "bufferView")?;
+                Ok(ImageSource::Embedded(buffer_view(json, buffers, view)?.0.to_vec()))
+            }
+        }
+    })?;
+
+    let samplers = parse_all(json, "samplers", "sampler", parse_sampler)?;
+
+    let textures = parse_all(json, "textures", "texture", |texture| {
+        let sampler = reference(texture, "sampler", samplers.len())?;
+        Ok(TextureDesc {
+            image: required(reference(texture, "source", images.len())?, "source")?,
+            sampler: sampler.map_or_else(SamplerDesc::default, |i| samplers[i]),
+        })
+    })?;
+
+    let materials = parse_all(json, "materials", "material", |material| {
+        let pbr = &material["pbrMetallicRoughness"];
+        Ok(Material {
+            name: name(material),
+            base_color: numbers(pbr, "baseColorFactor", [1.0; 4])?,
+            base_color_texture: reference(&pbr["baseColorTexture"], "index", textures.len())?,
+        })
+    })?;
+
+    let meshes = parse_all(json, "meshes", "mesh", |mesh| {
+        array(mesh, "primitives").iter().enumerate().map(|(i, primitive)| {
+            parse_primitive(json, buffers, primitive, materials.len())
+                .map_err(|e| format!("primitive {}: {}", i, e))
+        }).collect()
+    })?;
+
+    let cameras = parse_all(json, "cameras", "camera", parse_camera)?;
+
+    let node_count = array(json, "nodes").len();
+    let mut nodes = parse_all(json, "nodes", "node", |node| {
+        parse_node(node, node_count, meshes.len(), cameras.len())
+    })?;
+    let top_level = resolve_hierarchy(&mut nodes)?;
+
+    // Without a scene to show, every top-level node is shown.
+    let scenes = array(json, "scenes");
+    let scene = integer(json, "scene")?.or(if scenes.is_empty() { None } else { Some(0) });
+    let roots = match scene {
+        Some(scene) => {
+            let scene = scenes.get(scene).ok_or_else(|| format!("scene {} doesn't exist", scene))?;
+            indices(scene, "nodes", node_count)?
+        }
+        None => top_level,
+    };
+
+    Ok(Document { nodes, roots, cameras, meshes, materials, textures, images })
+}
+
+fn parse_sampler(sampler: &Value) -> Result<SamplerDesc, String> {
+    let filter = |key| -> Result<Option<Filter>, String> {
+        match integer(sampler, key)? {
+            Some(code) => FILTERS.iter().cloned().find(|filter| filter.gl_enum() as usize == code)
+                .map(Some).ok_or_else(|| format!("invalid `{}` {}", key, code)),
+            None => Ok(None),
+        }
+    };
+    let wrap = |key| -> Result<Option<Wrap>, String> {
+        match integer(sampler, key)? {
+            Some(code) => WRAPS.iter().cloned().find(|wrap| wrap.gl_enum() as usize == code)
+                .map(Some).ok_or_else(|| format!("invalid `{}` {}", key, code)),
+            None => Ok(None),
+        }
+    };
+
+    // Filters that are left out are up to us, so they get the defaults. Mipmaps are only
+    // generated if the minification filter uses them.
+    let mut desc = SamplerDesc::default();
+    if let Some(mag_filter) = filter("magFilter")? {
+        desc.mag_filter = mag_filter;
+    }
+    if let Some(min_filter) = filter("minFilter")? {
+        desc.min_filter = min_filter;
+        desc.mipmaps = min_filter.uses_mipmaps();
+    }
+    desc.wrap_s = wrap("wrapS")?.unwrap_or(Wrap::Repeat);
+    desc.wrap_t = wrap("wrapT")?.unwrap_or(Wrap::Repeat);
+
+    desc.validate().map_err(|e| e.to_string())?;
+    Ok(desc)
+}
+
+fn parse_primitive(json: &Value, buffers: &[Vec<u8>], primitive: &Value, material_count: usize)
+                   -> Result<(IndexedVertices, Option<usize>), String> {
+    let attributes = &primitive["attributes"];
+    let attribute = |key, components| -> Result<Option<Vec<f64>>, String> {
+        match integer(attributes, key)? {
+            Some(accessor) => read_accessor(json, buffers, accessor, components).map(Some),
+            None => Ok(None),
+        }
+    };
+
+    let positions = required(attribute("POSITION", 3)?, "POSITION")?;
+    let normals = attribute("NORMAL", 3)?;
+    let texcoords = attribute("TEXCOORD_0", 2)?;
+    let count = positions.len() / 3;
+    if normals.as_ref().is_some_and(|normals| normals.len() != count * 3) ||
+        texcoords.as_ref().is_some_and(|texcoords| texcoords.len() != count * 2) {
+        return Err("attributes have different counts".to_string());
+    }
+
+    let indices: Vec<usize> = match integer(primitive, "indices")? {
+        Some(accessor) => read_accessor(json, buffers, accessor, 1)?.into_iter()
+            .map(|index| index as usize).collect(),
+        None => (0..count).collect(),
+    };
+    if let Some(index) = indices.iter().find(|&&index| index >= count) {
+        return Err(format!("index {} out of range", index));
+    }
+
+    let triangles: Vec<[usize; 3]> = match integer(primitive, "mode")?.unwrap_or(4) {
+        4 => indices.chunks(3).filter(|triangle| triangle.len() == 3)
+            .map(|triangle| [triangle[0], triangle[1], triangle[2]]).collect(),
+        5 => (2..indices.len()).map(|i| {
+            // Every other triangle of a strip is reversed, to keep them all facing the same way.
+            if i % 2 == 0 {
+                [indices[i - 2], indices[i - 1], indices[i]]
+            } else {
+                [indices[i - 1], indices[i - 2], indices[i]]
+            }
+        }).collect(),
+        6 => (2..indices.len()).map(|i| [indices[0], indices[i - 1], indices[i]]).collect(),
+        mode => return Err(format!("primitive mode {} is not supported", mode)),
+    };
+
+    let vec3 = |values: &[f64], i: usize| {
+        [values[i * 3] as f32, values[i * 3 + 1] as f32, values[i * 3 + 2] as f32]
+    };
+
+    let mut part = IndexedVertices::new();
+    for triangle in triangles {
+        // Primitives without normals are meant to be flat shaded.
+        let flat_normal = match normals {
+            Some(_) => [0.0; 3],
+            None => model::plane_normal(vec3(&positions, triangle[0]),
+                                        vec3(&positions, triangle[1]),
+                                        vec3(&positions, triangle[2])),
+        };
+
+        for &i in &triangle {
+            part.push(ModelVertex {
+                position: vec3(&positions, i),
+                normal: normals.as_ref().map_or(flat_normal, |normals| vec3(normals, i)),
+                texcoord: texcoords.as_ref().map_or([0.0; 2], |texcoords| {
+                    [texcoords[i * 2] as f32, texcoords[i * 2 + 1] as f32]
+                }),
+            });
+        }
+    }
+
+    Ok((part, reference(primitive, "material", material_count)?))
+}
+
+fn parse_camera(camera: &Value) -> Result<Camera, String> {
+    let projection = match camera["type"].as_str() {
+        Some("perspective") => {
+            let perspective = &camera["perspective"];
+            Projection::Perspective {
+                fov_y: required(number(perspective, "yfov")?, "yfov")?,
+                aspect: number(perspective, "aspectRatio")?,
+                z_near: required(number(perspective, "znear")?, "znear")?,
+                z_far: number(perspective, "zfar")?,
+            }
+        }
+        Some("orthographic") => {
+            let orthographic = &camera["orthographic"];
+            Projection::Orthographic {
+                x_mag: required(number(orthographic, "xmag")?, "xmag")?,
+                y_mag: required(number(orthographic, "ymag")?, "ymag")?,
+                z_near: required(number(orthographic, "znear")?, "znear")?,
+                z_far: required(number(orthographic, "zfar")?, "zfar")?,
+            }
+        }
+        _ => return Err("`type` must be \"perspective\" or \"orthographic\"".to_string()),
+    };
+
+    Ok(Camera { name: name(camera), projection })
+}
+
+fn parse_node(node: &Value, node_count: usize, mesh_count: usize, camera_count: usize)
+              -> Result<Node, String> {
+    let local = if node.get("matrix").is_some() {
+        let m = numbers(node, "matrix", [0.0; 16])?;
+        Mat4([
+            [m[0],  m[1],  m[2],  m[3]],
+            [m[4],  m[5],  m[6],  m[7]],
+            [m[8],  m[9],  m[10], m[11]],
+            [m[12], m[13], m[14], m[15]],
+        ])
+    } else {
+        let [x, y, z] = numbers(node, "translation", [0.0; 3])?;
+        let rotation = numbers(node, "rotation", [0.0, 0.0, 0.0, 1.0])?;
+        let [scale_x, scale_y, scale_z] = numbers(node, "scale", [1.0; 3])?;
+        Mat4::translate(x, y, z) * Mat4::rotate(rotation) * Mat4::scale(scale_x, scale_y, scale_z)
+    };
+
+    Ok(Node {
+        name: name(node),
+        local,
+        world: local,
+        children: indices(node, "children", node_count)?,
+        mesh: reference(node, "mesh", mesh_count)?,
+        camera: reference(node, "camera", camera_count)?,
+    })
+}
+
+/// Set the world transforms of the nodes, and return the nodes at the top of the hierarchy.
+fn resolve_hierarchy(nodes: &mut [Node]) -> Result<Vec<usize>, String> {
+    let mut parents = vec![None; nodes.len()];
+    for (i, node) in nodes.iter().enumerate() {
+        for &child in &node.children {
+            if parents[child].replace(i).is_some() {
+                return Err(format!("node {} has more than one parent", child));
+            }
+        }
+    }
+
+    let top_level: Vec<usize> = (0..nodes.len()).filter(|&i| parents[i].is_none()).collect();
+
+    // With one parent each, the nodes in a cycle can't be reached from the top.
+    let mut stack = top_level.clone();
+    let mut reached = 0;
+    while let Some(i) = stack.pop() {
+        reached += 1;
+        for j in 0..nodes[i].children.len() {
+            let child = nodes[i].children[j];
+            nodes[child].world = nodes[i].world * nodes[child].local;
+            stack.push(child);
+        }
+    }
+    if reached < nodes.len() {
+        return Err("the node hierarchy has a cycle".to_string());
+    }
+
+    Ok(top_level)
+}
+
+/// Read every element of the accessor with the given index, which must have `components`
+/// components, converting them to floats.
+fn read_accessor(json: &Value, buffers: &[Vec<u8>], index: usize, components: usize)
+                 -> Result<Vec<f64>, String> {
+    let accessor = array(json, "accessors").get(index)
+        .ok_or_else(|| format!("accessor {} doesn't exist", index))?;
+    read_accessor_values(json, buffers, accessor, components)
+        .map_err(|e| format!("accessor {}: {}", index, e))
+}
+
+fn read_accessor_values(json: &Value, buffers: &[Vec<u8>], accessor: &Value, components: usize)
+                        -> Result<Vec<f64>, String> {
+    if accessor.get("sparse").is_some() {
+        return Err("sparse accessors are not supported".to_string());
+    }
+
+    let count = required(integer(accessor, "count")?, "count")?;
+    let type_components = match accessor["type"].as_str() {
+        Some("SCALAR") => 1,
+        Some("VEC2") => 2,
+        Some("VEC3") => 3,
+        Some("VEC4") | Some("MAT2") => 4,
+        Some("MAT3") => 9,
+        Some("MAT4") => 16,
+        _ => return Err("invalid `type`".to_string()),
+    };
+    if type_components != components {
+        return Err(format!("expected {} components, not {}", components, type_components));
+    }
+
+    let component_type = required(integer(accessor, "componentType")?, "componentType")? as GLenum;
+    let size = match component_type {
+        gl::BYTE | gl::UNSIGNED_BYTE => 1,
+        gl::SHORT | gl::UNSIGNED_SHORT => 2,
+        gl::UNSIGNED_INT | gl::FLOAT => 4,
+        _ => return Err(format!("invalid `componentType` {}", component_type)),
+    };
+    let normalized = accessor["normalized"].as_bool().unwrap_or(false);
+
+    // Without a buffer view every element is zero, which is only useful as the base for sparse
+    // values. The count couldn't be checked against any data either.
+    let view = required(integer(accessor, "bufferView")?, "bufferView")?;
+    let (data, stride) = buffer_view(json, buffers, view)?;
+    let offset = integer(accessor, "byteOffset")?.unwrap_or(0);
+    let element_size = size * components;
+    let stride = stride.unwrap_or(element_size);
+    if stride < element_size {
+        return Err(format!("stride {} is less than the element size {}", stride, element_size));
+    }
+
+    // The counts come from the file, so check that the elements fit before allocating for them.
+    let end = match count.checked_sub(1) {
+        Some(last) => stride.checked_mul(last).and_then(|start| start.checked_add(offset))
+            .and_then(|start| start.checked_add(element_size)),
+        None => Some(0),
+    };
+    if end.is_none_or(|end| end > data.len()) {
+        return Err("elements run past the end of the buffer view".to_string());
+    }
+
+    let mut values = Vec::with_capacity(count * components);
+    for element in 0..count {
+        let start = offset + stride * element;
+        for bytes in data[start..start + element_size].chunks(size) {
+            values.push(component(bytes, component_type, normalized));
+        }
+    }
+    Ok(values)
+}
+
+/// Convert a little-endian component to a float, mapping normalized integers to [0, 1] or
+/// [-1, 1].
+fn component(bytes: &[u8], component_type: GLenum, normalized: bool) -> f64 {
+    let (value, max) = match component_type {
+        gl::BYTE => (bytes[0] as i8 as f64, 127.0),
+        gl::UNSIGNED_BYTE => (bytes[0] as f64, 255.0),
+        gl::SHORT => (i16::from_le_bytes([bytes[0], bytes[1]]) as f64, 32767.0),
+        gl::UNSIGNED_SHORT => (u16::from_le_bytes([bytes[0], bytes[1]]) as f64, 65535.0),
+        gl::UNSIGNED_INT =>
+            (u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as f64, 4294967295.0),
+        _ => return f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as f64,
+    };
+
+    if normalized { (value / max).max(-1.0) } else { value }
+}
+
+/// The bytes of the buffer view with the given index, and its stride if it has one.
+fn buffer_view<'b>(json: &Value, buffers: &'b [Vec<u8>], index: usize)
+                   -> Result<(&'b [u8], Option<usize>), String> {
+    let view = array(json, "bufferViews").get(index)
+        .ok_or_else(|| format!("buffer view {} doesn't exist", index))?;
+    let in_view = |e: String| format!("buffer view {}: {}", index, e);
+
+    let buffer = required(reference(view, "buffer", buffers.len()).map_err(in_view)?, "buffer")
+        .map_err(in_view)?;
+    let offset = integer(view, "byteOffset").map_err(in_view)?.unwrap_or(0);
+    let length = required(integer(view, "byteLength").map_err(in_view)?, "byteLength")
+        .map_err(in_view)?;
+    let stride = integer(view, "byteStride").map_err(in_view)?;
+
+    let data = offset.checked_add(length).and_then(|end| buffers[buffer].get(offset..end))
+        .ok_or_else(|| in_view(format!("runs past the end of buffer {}", buffer)))?;
+    Ok((data, stride))
+}
+
+/// The data of a `data:` URI, or `None` for any other URI.
+fn data_uri(uri: &str) -> Result<Option<Vec<u8>>, String> {
+    if !uri.starts_with("data:") {
+        return Ok(None);
+    }
+
+    match uri.find(";base64,") {
+        Some(start) => decode_base64(&uri[start + 8..]).map(Some),
+        None => Err("only base64 data URIs are supported".to_string()),
+    }
+}
+
+fn decode_base64(text: &str) -> Result<Vec<u8>, String> {
+    let mut data = Vec::with_capacity(text.len() / 4 * 3);
+    let mut bits = 0u32;
+    let mut bit_count = 0;
+
+    for c in text.trim_end_matches('=').bytes() {
+        let value = match c {
+            b'A'..=b'Z' => c - b'A',
+            b'a'..=b'z' => c - b'a' + 26,
+            b'0'..=b'9' => c - b'0' + 52,
+            b'+' => 62,
+            b'/' => 63,
+            _ => return Err(format!("invalid base64 character `{}`", c as char)),
+        };
+
+        // Bits that have already been written out are shifted off the top.
+        bits = bits << 6 | value as u32;
+        bit_count += 6;
+        if bit_count >= 8 {
+            bit_count -= 8;
+            data.push((bits >> bit_count) as u8);
+        }
+    }
+
+    Ok(data)
+}
+
+/// Decode the `%XX` escapes in a relative URI, which exporters write for spaces and the like.
+fn percent_decode(uri: &str) -> String {
+    let bytes = uri.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+
+    while i < bytes.len() {
+        let escaped = if bytes[i] == b'%' {
+            uri.get(i + 1..i + 3).and_then(|hex| u8::from_str_radix(hex, 16).ok())
+        } else {
+            None
+        };
+
+        match escaped {
+            Some(byte) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            None => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+/// Parse each element of the array `key` of `json`, saying which element an error is in.
+fn parse_all<T, F>(json: &Value, key: &str, what: &str, mut parse: F) -> Result<Vec<T>, String>
+    where F: FnMut(&Value) -> Result<T, String>
+{
+    array(json, key).iter().enumerate().map(|(i, element)| {
+        parse(element).map_err(|e| format!("{} {}: {}", what, i, e))
+    }).collect()
+}
+
+/// The elements of the array `key` of `object`, which may be left out.
+fn array<'j>(object: &'j Value, key: &str) -> &'j [Value] {
+    object[key].as_array().map_or(&[], |array| &array[..])
+}
+
+fn name(object: &Value) -> String {
+    object["name"].as_str().unwrap_or("").to_string()
+}
+
+/// The value of a property that can't be left out.
+fn required<T>(value: Option<T>, key: &str) -> Result<T, String> {
+    value.ok_or_else(|| format!("missing `{}`", key))
+}
+
+/// The non-negative integer `key` of `object`, if it's there.
+fn integer(object: &Value, key: &str) -> Result<Option<usize>, String> {
+    match object.get(key) {
+        Some(value) => value.as_u64().map(|value| Some(value as usize))
+            .ok_or_else(|| format!("`{}` must be a non-negative integer", key)),
+        None => Ok(None),
+    }
+}
+
+/// The index `key` of `object`, if it's there, into a list of `count` objects.
+fn reference(object: &Value, key: &str, count: usize) -> Result<Option<usize>, String> {
+    match integer(object, key)? {
+        Some(index) if index >= count => Err(format!("`{}` {} doesn't exist", key, index)),
+        index => Ok(index),
+    }
+}
+
+/// The array of indices `key` of `object` into a list of `count` objects.
+fn indices(object: &Value, key: &str, count: usize) -> Result<Vec<usize>, String> {
+    array(object, key).iter().map(|value| {
+        match value.as_u64() {
+            Some(index) if (index as usize) < count => Ok(index as usize),
+            _ => Err(format!("`{}` has an invalid index {}", key, value)),
+        }
+    }).collect()
+}
+
+fn number(object: &Value, key: &str) -> Result<Option<f32>, String> {
+    match object.get(key) {
+        Some(value) => value.as_f64().map(|value| Some(value as f32))
+            .ok_or_else(|| format!("`{}` must be a number", key)),
+        None => Ok(None),
+    }
+}
+
+/// The array of `N` numbers `key` of `object`, or `default` if it's left out.
+fn numbers<const N: usize>(object: &Value, key: &str, default: [f32; N])
+                           -> Result<[f32; N], String> {
+    let values = match object.get(key) {
+        Some(values) => values.as_array().filter(|values| values.len() == N)
+            .ok_or_else(|| format!("`{}` must be an array of {} numbers", key, N))?,
+        None => return Ok(default),
+    };
+
+    let mut result = default;
+    for (number, value) in result.iter_mut().zip(values) {
+        *number = value.as_f64().ok_or_else(|| format!("`{}` must only have numbers", key))? as f32;
+    }
+    Ok(result)
+}
+
+#[test]
+fn test_read_gltf() {
+    // A quad with interleaved positions and normals, normalized byte texture coordinates and
+    // short indices.
+    let mut bin = Vec::new();
+    let quad = [[0.0f32, 0.0, 0.0], [1.0, 0.0, 0.0], [1.0, 1.0, 0.0], [0.0, 1.0, 0.0]];
+    for position in &quad {
+        for &x in position.iter().chain(&[0.0, 0.0, 1.0]) {
+            bin.extend_from_slice(&x.to_le_bytes());
+        }
+    }
+    bin.extend_from_slice(&[0, 255, 255, 255, 255, 0, 0, 0]);
+    for &index in &[0u16, 1, 2, 0, 2, 3] {
+        bin.extend_from_slice(&index.to_le_bytes());
+    }
+
+    // The second primitive is the same quad as a fan, without normals or texture coordinates.
+    let json = r#"{
+        "asset": { "version": "2.0" },
+        "scene": 0,
+        "scenes": [{ "nodes": [0] }],
+        "nodes": [
+            { "name": "root", "translation": [1, 2, 3], "children": [1, 2] },
+            { "mesh": 0, "rotation": [0, 0, 0.70710678, 0.70710678], "scale": [2, 2, 2] },
+            { "camera": 0, "matrix": [1, 0, 0, 0, 0, 1, 0, 0, 0, 0, 1, 0, 0, 0, 5, 1] }
+        ],
+        "cameras": [{ "type": "perspective", "perspective": { "yfov": 1.0, "znear": 0.1 } }],
+        "meshes": [{ "primitives": [
+            { "attributes": { "POSITION": 0, "NORMAL": 1, "TEXCOORD_0": 2 }, "indices": 3,
+              "material": 0 },
+            { "attributes": { "POSITION": 0 }, "mode": 6 }
+        ] }],
+        "materials": [{ "pbrMetallicRoughness": {
+            "baseColorFactor": [1, 0.5, 0, 1], "baseColorTexture": { "index": 0 }
+        } }],
+        "textures": [{ "source": 0, "sampler": 0 }],
+        "samplers": [{ "magFilter": 9728, "minFilter": 9729, "wrapS": 33071 }],
+        "images": [{ "uri": "textures/base%20color.png" }],
+        "buffers": [{ "uri": "scene.bin", "byteLength": 116 }],
+        "bufferViews": [
+            { "buffer": 0, "byteLength": 96, "byteStride": 24 },
+            { "buffer": 0, "byteOffset": 96, "byteLength": 8 },
+            { "buffer": 0, "byteOffset": 104, "byteLength": 12 }
+        ],
+        "accessors": [
+            { "bufferView": 0, "componentType": 5126, "count": 4, "type": "VEC3" },
+            { "bufferView": 0, "byteOffset": 12, "componentType": 5126, "count": 4,
+              "type": "VEC3" },
+            { "bufferView": 1, "componentType": 5121, "normalized": true, "count": 4,
+              "type": "VEC2" },
+            { "bufferView": 2, "componentType": 5123, "count": 6, "type": "SCALAR" }
+        ]
+    }"#;
+
+    // The same scene as a binary file, with the buffer in a chunk of its own.
+    let glb_json = json.replace(r#""uri": "scene.bin", "#, "");
+    let mut glb = b"glTF\x02\0\0\0\0\0\0\0".to_vec();
+    for &(chunk_type, chunk, padding) in &[(GLB_JSON_CHUNK, glb_json.as_bytes(), b' '),
+                                           (GLB_BIN_CHUNK, &bin[..], 0)] {
+        let padded = chunk.len().div_ceil(4) * 4;
+        glb.extend_from_slice(&(padded as u32).to_le_bytes());
+        glb.extend_from_slice(&chunk_type.to_le_bytes());
+        glb.extend_from_slice(chunk);
+        glb.resize(glb.len() + padded - chunk.len(), padding);
+    }
+    let glb_length = (glb.len() as u32).to_le_bytes();
+    glb[8..12].copy_from_slice(&glb_length);
+
+    let files = [
+        ("models/scene.gltf", json.as_bytes().to_vec()),
+        ("models/scene.bin", bin),
+        ("models/scene.glb", glb),
+    ];
+    let mut read = |path: &Path| {
+        files.iter().find(|&&(name, _)| Path::new(name) == path).map(|(_, data)| data.clone())
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no such file"))
+    };
+
+    use math::Vec4;
+
+    let assert_close = |expected: [f32; 3], actual: Vec4| {
+        for i in 0..3 {
+            assert!((expected[i] - actual[i]).abs() < 1e-5, "{:?} != {:?}", expected, actual);
+        }
+    };
+
+    for path in &["models/scene.gltf", "models/scene.glb"] {
+        let document = read_with(Path::new(path), &mut read).unwrap();
+        assert_eq!(vec![0], document.roots);
+        assert_eq!(vec![1, 2], document.nodes[0].children);
+
+        // The mesh node is turned a quarter turn and scaled up inside the translated root.
+        let origin = Vec4([0.0, 0.0, 0.0, 1.0]);
+        assert_close([1.0, 4.0, 3.0], document.nodes[1].world * Vec4([1.0, 0.0, 0.0, 1.0]));
+        assert_close([1.0, 2.0, 8.0], document.nodes[2].world * origin);
+        assert_close([0.0, 0.0, -5.0], document.nodes[2].view() * document.nodes[0].world * origin);
+        assert_eq!(Projection::Perspective { fov_y: 1.0, aspect: None, z_near: 0.1, z_far: None },
+                   document.cameras[0].projection);
+
+        let (ref quad, material) = document.meshes[0][0];
+        assert_eq!(vec![0, 1, 2, 0, 2, 3], quad.indices);
+        assert_eq!(ModelVertex { position: [1.0, 0.0, 0.0], normal: [0.0, 0.0, 1.0],
+                                 texcoord: [1.0, 1.0] }, quad.vertices[1]);
+        assert_eq!(Some(0), material);
+
+        let (ref fan, material) = document.meshes[0][1];
+        assert_eq!(vec![0, 1, 2, 0, 2, 3], fan.indices);
+        assert_eq!(ModelVertex { position: [0.0, 1.0, 0.0], normal: [0.0, 0.0, 1.0],
+                                 texcoord: [0.0, 0.0] }, fan.vertices[3]);
+        assert_eq!(None, material);
+
+        assert_eq!(Material { name: String::new(), base_color: [1.0, 0.5, 0.0, 1.0],
+                              base_color_texture: Some(0) }, document.materials[0]);
+        assert_eq!(TextureDesc {
+            image: 0,
+            sampler: SamplerDesc {
+                wrap_s: Wrap::ClampToEdge,
+                min_filter: Filter::Linear,
+                mag_filter: Filter::Nearest,
+                mipmaps: false,
+                ..SamplerDesc::default()
+            },
+        }, document.textures[0]);
+        assert_eq!(vec![ImageSource::File(PathBuf::from("models/textures/base color.png"))],
+                   document.images);
+    }
+}
+
+#[test]
+fn test_accessor_bounds() {
+    let json: Value = serde_json::from_str(r#"{
+        "bufferViews": [
+            { "buffer": 0, "byteLength": 16 },
+            { "buffer": 0, "byteOffset": 8, "byteLength": 18446744073709551615 },
+            { "buffer": 0, "byteLength": 16, "byteStride": 0 }
+        ]
+    }"#).unwrap();
+    let buffers = [vec![0; 16]];
+    let read = |accessor: &str| {
+        let accessor = serde_json::from_str(accessor).unwrap();
+        read_accessor_values(&json, &buffers, &accessor, 1)
+    };
+
+    assert_eq!(Ok(vec![0.0; 4]),
+               read(r#"{ "bufferView": 0, "componentType": 5126, "count": 4, "type": "SCALAR" }"#));
+    assert_eq!(Ok(vec![]),
+               read(r#"{ "bufferView": 0, "componentType": 5126, "count": 0, "type": "SCALAR" }"#));
+
+    // Counts and offsets too big for the view, even ones that overflow, are errors rather than
+    // huge allocations.
+    for accessor in &[
+        r#"{ "bufferView": 0, "componentType": 5126, "count": 5, "type": "SCALAR" }"#,
+        r#"{ "bufferView": 0, "byteOffset": 4, "componentType": 5126, "count": 4,
+             "type": "SCALAR" }"#,
+        r#"{ "bufferView": 0, "componentType": 5126, "count": 4611686018427387905,
+             "type": "SCALAR" }"#,
+        r#"{ "bufferView": 0, "byteOffset": 18446744073709551615, "componentType": 5126,
+             "count": 1, "type": "SCALAR" }"#,
+        r#"{ "bufferView": 1, "componentType": 5126, "count": 1, "type": "SCALAR" }"#,
+        r#"{ "bufferView": 2, "componentType": 5126, "count": 1000000000, "type": "SCALAR" }"#,
+        r#"{ "componentType": 5126, "count": 1000000000000, "type": "SCALAR" }"#,
+    ] {
+        assert!(read(accessor).is_err(), "{}", accessor);
+    }
+}
+
+#[test]
+fn test_decode_base64() {
+    assert_eq!(Ok(b"glTF".to_vec()), decode_base64("Z2xURg=="));
+    assert_eq!(Ok(vec![0, 1, 2, 0xfe, 0xff]), decode_base64("AAEC/v8="));
+    assert_eq!(Some(b"hi".to_vec()),
+               data_uri("data:application/octet-stream;base64,aGk=").unwrap());
+    assert_eq!(None, data_uri("scene.bin").unwrap());
+    assert!(decode_base64("not base64!").is_err());
+}
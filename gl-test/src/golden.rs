@@ -0,0 +1,205 @@
+//! Golden-image regression tests.
+//!
+//! The demo is rendered headlessly at fixed values of the `time` uniform, both through OpenGL and
+//! through the software rasterizer, and compared against the reference images checked in under
+//! `golden/`. When a comparison fails, the rendered image and a diff image are written to
+//! `target/golden/` for inspection. Run the tests with `GOLDEN_BLESS=1` set to replace the
+//! reference images with the current GL output after an intentional change.
+
+use imagefmt::{self, ColFmt, ColType};
+use render_state::RenderState;
+use std::env;
+use std::fs;
+use std::path::Path;
+
+const GOLDEN_DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/golden");
+const OUTPUT_DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/target/golden");
+
+/// The values of the `time` uniform the demo is checked at.
+const TIMES: [f32; 4] = [0.0, 0.3, 0.75, 1.5];
+
+/// The render states the demo is checked in, with the names of their reference images. Without
+/// culling or depth testing, the darkened back faces show through the front ones.
+fn variants() -> [(&'static str, RenderState); 2] {
+    let double_sided = RenderState { depth_test: None, cull_face: None, ..RenderState::default() };
+    [("demo", RenderState::default()), ("demo-double-sided", double_sided)]
+}
+
+/// The size of the rendered images.
+const WIDTH: u32 = 160;
+const HEIGHT: u32 = 120;
+
+/// The largest difference allowed in any color channel of any pixel. Software rasterizers differ
+/// slightly between Mesa versions in how they round texture filtering and varyings.
+pub const TOLERANCE: u8 = 3;
+
+/// The result of comparing two RGB images of the same size.
+pub struct Comparison {
+    /// The number of pixels with some channel differing by more than the tolerance.
+    pub mismatched: usize,
+
+    /// The largest difference in any channel of any pixel.
+    pub max_difference: u8,
+
+    /// An RGB image showing mismatched pixels in red over a faded copy of the expected image.
+    pub diff: Vec<u8>,
+}
+
+/// Compare two RGB images pixel by pixel.
+pub fn compare(actual: &[u8], expected: &[u8], tolerance: u8) -> Comparison {
+    assert_eq!(actual.len(), expected.len());
+
+    let mut mismatched = 0;
+    let mut max_difference = 0;
+    let mut diff = Vec::with_capacity(expected.len());
+
+    for (a, e) in actual.chunks(3).zip(expected.chunks(3)) {
+        let difference = (0..3).map(|i| a[i].abs_diff(e[i])).max().unwrap();
+        max_difference = max_difference.max(difference);
+
+        if difference > tolerance {
+            mismatched += 1;
+            diff.extend_from_slice(&[255, 0, 0]);
+        } else {
+            let luma = (e[0] as u32 * 3 + e[1] as u32 * 6 + e[2] as u32) / 10;
+            let faded = (luma / 4) as u8;
+            diff.extend_from_slice(&[faded, faded, faded]);
+        }
+    }
+
+    Comparison { mismatched, max_difference, diff }
+}
+
+/// Compare a rendered RGB image against the reference image called `name`. On failure the
+/// rendered and diff images are saved with `label` added to their names, so that failures from
+/// different renderers checked against the same reference don't overwrite each other.
+pub fn check(name: &str, label: &str, width: u32, height: u32, actual: &[u8])
+             -> Result<(), String> {
+    let reference_path = Path::new(GOLDEN_DIR).join(format!("{}.png", name));
+
+    if env::var_os("GOLDEN_BLESS").is_some() {
+        return write_png(&reference_path, width, height, actual);
+    }
+
+    let expected = imagefmt::read(&reference_path, ColFmt::RGB).map_err(|e| {
+        format!("{}: failed to read {} ({}); run with GOLDEN_BLESS=1 to create it",
+                name, reference_path.display(), e)
+    })?;
+
+    let actual_path = Path::new(OUTPUT_DIR).join(format!("{}-{}-actual.png", name, label));
+
+    if expected.w != width as usize || expected.h != height as usize {
+        write_png(&actual_path, width, height, actual)?;
+        return Err(format!("{}: rendered {}x{} but the reference is {}x{}; see {}",
+                           name, width, height, expected.w, expected.h, actual_path.display()));
+    }
+
+    let comparison = compare(actual, &expected.buf, TOLERANCE);
+    if comparison.mismatched == 0 {
+        return Ok(());
+    }
+
+    let diff_path = Path::new(OUTPUT_DIR).join(format!("{}-{}-diff.png", name, label));
+    write_png(&actual_path, width, height, actual)?;
+    write_png(&diff_path, width, height, &comparison.diff)?;
+
+    Err(format!("{}: {} pixels differ by more than {} (at most {}); see {} and {}",
+                name, comparison.mismatched, TOLERANCE, comparison.max_difference,
+                actual_path.display(), diff_path.display()))
+}
+
+fn write_png(path: &Path, width: u32, height: u32, pixels: &[u8]) -> Result<(), String> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)
+            .map_err(|e| format!("failed to create {}: {}", dir.display(), e))?;
+    }
+
+    imagefmt::write(path, width as usize, height as usize, ColFmt::RGB, pixels, ColType::Color)
+        .map_err(|e| format!("failed to write {}: {}", path.display(), e))
+}
+
+#[test]
+fn test_compare() {
+    let expected = [100, 100, 100, 200, 200, 200];
+    let actual = [102, 100, 99, 200, 190, 200];
+    let comparison = compare(&actual, &expected, 3);
+
+    assert_eq!(1, comparison.mismatched);
+    assert_eq!(10, comparison.max_difference);
+    assert_eq!([25, 25, 25, 255, 0, 0], &comparison.diff[..]);
+}
+
+#[test]
+fn test_golden_demo() {
+    use assets::{self, Assets};
+    use demo::{self, default_view, Demo};
+    use headless::{Framebuffer, HeadlessContext};
+    use reload;
+    use screenshot;
+
+    let context = match HeadlessContext::for_test(WIDTH, HEIGHT) {
+        Some(context) => context,
+        None => return,
+    };
+    let mut failures = Vec::new();
+
+    unsafe {
+        let framebuffer = Framebuffer::new(WIDTH, HEIGHT).unwrap();
+        framebuffer.bind();
+        let shader_dir = reload::default_shader_dir();
+        let assets = Assets::new(context.gl(), &assets::default_asset_dir(), &shader_dir);
+
+        for &(prefix, state) in &variants() {
+            let mut shaders = demo::shader_files(&shader_dir, None, state);
+            let demo = Demo::new(&assets, &mut shaders, state, None)
+                .unwrap_or_else(|e| panic!("{}", e));
+            assets.finish_loading();
+
+            for &time in &TIMES {
+                demo.draw(time, default_view(), WIDTH as f32 / HEIGHT as f32);
+                let pixels = screenshot::read_pixels(WIDTH, HEIGHT);
+
+                let name = format!("{}-{:.2}", prefix, time);
+                if let Err(e) = check(&name, "gl", WIDTH, HEIGHT, &pixels) {
+                    failures.push(e);
+                }
+            }
+        }
+
+        framebuffer.delete();
+    }
+
+    assert!(failures.is_empty(), "golden image mismatches:\n{}", failures.join("\n"));
+}
+
+/// The software rasterizer must reproduce the GL reference images too, which keeps it honest as a
+/// stand-in for GL on machines without one.
+#[test]
+fn test_golden_software() {
+    use assets;
+    use demo::SoftwareDemo;
+    use raster;
+
+    // Blessing only ever records the GL output.
+    if env::var_os("GOLDEN_BLESS").is_some() {
+        return;
+    }
+
+    let mut framebuffer = raster::Framebuffer::new(WIDTH, HEIGHT);
+    let mut failures = Vec::new();
+
+    for &(prefix, state) in &variants() {
+        let demo = SoftwareDemo::new(&assets::default_asset_dir(), state);
+
+        for &time in &TIMES {
+            demo.draw(&mut framebuffer, time);
+
+            let name = format!("{}-{:.2}", prefix, time);
+            if let Err(e) = check(&name, "software", WIDTH, HEIGHT, &framebuffer.pixels) {
+                failures.push(e);
+            }
+        }
+    }
+
+    assert!(failures.is_empty(), "golden image mismatches:\n{}", failures.join("\n"));
+}
@@ -0,0 +1,154 @@
+//! Rendering without a window, for machines with no display and no GPU.
+//!
+//! The context is created through OSMesa, which Mesa's llvmpipe driver implements entirely in
+//! software. Frames are drawn into a framebuffer object rather than OSMesa's own buffer so that
+//! the rest of the program doesn't need to know where its pixels end up.
+
+use context::GlContext;
+use gl;
+use gl::types::*;
+use osmesa_sys;
+use std::ffi::CString;
+use std::ptr;
+
+/// An OpenGL 3.2 core context with no window attached.
+pub struct HeadlessContext {
+    context: osmesa_sys::OSMesaContext,
+
+    // OSMesa insists on having a buffer to make the context current with, even though we never
+    // draw to it.
+    _buffer: Vec<u8>,
+
+    gl: GlContext,
+}
+
+impl HeadlessContext {
+    /// Create a context, make it current on this thread, and load the OpenGL function pointers.
+    pub fn new(width: u32, height: u32) -> Result<HeadlessContext, String> {
+        if let Err(e) = osmesa_sys::OsMesa::try_loading() {
+            return Err(format!("Failed to load libOSMesa: {:?}", e));
+        }
+
+        let attribs = [
+            osmesa_sys::OSMESA_FORMAT, osmesa_sys::OSMESA_RGBA as i32,
+            osmesa_sys::OSMESA_DEPTH_BITS, 24,
+            osmesa_sys::OSMESA_STENCIL_BITS, 8,
+            osmesa_sys::OSMESA_PROFILE, osmesa_sys::OSMESA_CORE_PROFILE,
+            osmesa_sys::OSMESA_CONTEXT_MAJOR_VERSION, 3,
+            osmesa_sys::OSMESA_CONTEXT_MINOR_VERSION, 2,
+            0,
+        ];
+
+        unsafe {
+            let context = osmesa_sys::OSMesaCreateContextAttribs(attribs.as_ptr(), ptr::null_mut());
+            if context.is_null() {
+                return Err("Failed to create an OSMesa OpenGL 3.2 core context.".to_string());
+            }
+
+            let mut buffer = vec![0u8; width as usize * height as usize * 4];
+            let made_current = osmesa_sys::OSMesaMakeCurrent(
+                context, buffer.as_mut_ptr() as *mut _, gl::UNSIGNED_BYTE,
+                width as i32, height as i32);
+            if made_current == 0 {
+                osmesa_sys::OSMesaDestroyContext(context);
+                return Err("Failed to make the OSMesa context current.".to_string());
+            }
+
+            // Load OpenGL function pointers.
+            gl::load_with(|symbol| {
+                let symbol = CString::new(symbol).unwrap();
+                match osmesa_sys::OSMesaGetProcAddress(symbol.as_ptr()) {
+                    Some(f) => f as *const _,
+                    None => ptr::null(),
+                }
+            });
+
+            Ok(HeadlessContext { context, _buffer: buffer, gl: GlContext::new() })
+        }
+    }
+
+    /// Create a context for a test, or return `None` after saying so if libOSMesa isn't
+    /// installed, so that tests needing GL are skipped rather than failed on machines without
+    /// Mesa. Any other failure to create the context panics.
+    #[cfg(test)]
+    pub fn for_test(width: u32, height: u32) -> Option<HeadlessContext> {
+        if let Err(e) = osmesa_sys::OsMesa::try_loading() {
+            eprintln!("Skipping a test that needs libOSMesa: {:?}", e);
+            return None;
+        }
+        Some(HeadlessContext::new(width, height).unwrap_or_else(|e| panic!("{}", e)))
+    }
+
+    /// The token that GL objects created in this context borrow.
+    pub fn gl(&self) -> &GlContext {
+        &self.gl
+    }
+}
+
+impl Drop for HeadlessContext {
+    fn drop(&mut self) {
+        unsafe { osmesa_sys::OSMesaDestroyContext(self.context); }
+    }
+}
+
+/// A framebuffer object with an RGBA color attachment and a depth/stencil attachment.
+pub struct Framebuffer {
+    fbo: GLuint,
+    color: GLuint,
+    depth_stencil: GLuint,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Framebuffer {
+    /// Create a framebuffer of the given size in the current context.
+    pub unsafe fn new(width: u32, height: u32) -> Result<Framebuffer, String> {
+        let mut fbo = 0;
+        let mut renderbuffers = [0; 2];
+
+        gl::GenFramebuffers(1, &mut fbo);
+        gl::BindFramebuffer(gl::FRAMEBUFFER, fbo);
+        gl::GenRenderbuffers(2, renderbuffers.as_mut_ptr());
+
+        gl::BindRenderbuffer(gl::RENDERBUFFER, renderbuffers[0]);
+        gl::RenderbufferStorage(gl::RENDERBUFFER, gl::RGBA8, width as i32, height as i32);
+        gl::FramebufferRenderbuffer(gl::FRAMEBUFFER, gl::COLOR_ATTACHMENT0, gl::RENDERBUFFER,
+                                    renderbuffers[0]);
+
+        gl::BindRenderbuffer(gl::RENDERBUFFER, renderbuffers[1]);
+        gl::RenderbufferStorage(gl::RENDERBUFFER, gl::DEPTH24_STENCIL8,
+                                width as i32, height as i32);
+        gl::FramebufferRenderbuffer(gl::FRAMEBUFFER, gl::DEPTH_STENCIL_ATTACHMENT,
+                                    gl::RENDERBUFFER, renderbuffers[1]);
+
+        let framebuffer = Framebuffer {
+            fbo,
+            color: renderbuffers[0],
+            depth_stencil: renderbuffers[1],
+            width,
+            height,
+        };
+
+        let status = gl::CheckFramebufferStatus(gl::FRAMEBUFFER);
+        if status != gl::FRAMEBUFFER_COMPLETE {
+            framebuffer.delete();
+            return Err(format!("Framebuffer is incomplete (status 0x{:x}).", status));
+        }
+
+        Ok(framebuffer)
+    }
+
+    /// Direct drawing and reading to this framebuffer and set the viewport to cover it.
+    pub unsafe fn bind(&self) {
+        gl::BindFramebuffer(gl::FRAMEBUFFER, self.fbo);
+        gl::Viewport(0, 0, self.width as i32, self.height as i32);
+    }
+
+    /// Delete the framebuffer and its attachments.
+    pub unsafe fn delete(self) {
+        gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
+        gl::DeleteFramebuffers(1, &self.fbo);
+        gl::DeleteRenderbuffers(1, &self.color);
+        gl::DeleteRenderbuffers(1, &self.depth_stencil);
+    }
+}
@@ -0,0 +1,357 @@
+//! Keyboard and mouse state, and named actions bound to keys and buttons.
+//!
+//! The window's events are turned into `Event`s, which `Input` collects into the state of the
+//! current frame. The rest of the program asks about actions like `"screenshot"` rather than
+//! particular keys, so that the keys can be changed in a bindings file:
+//!
+//! ```text
+//! # An action, then the keys and mouse buttons bound to it.
+//! screenshot = F12 PrintScreen
+//!
+//! # Inputs with a `-` count as negative when the action is read as an axis.
+//! move_forward = W -S
+//! ```
+//!
+//! Keys are named like the variants of `glfw::Key`, and mouse buttons are `Mouse1` to `Mouse8`.
+
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// The bindings used when no bindings file is given.
+pub const DEFAULT_BINDINGS: &str = "\
+quit = Escape
+screenshot = F12
+fullscreen = F11
+switch_camera = C
+scene_camera = V
+orbit = Mouse1
+move_forward = W -S
+move_right = D -A
+move_up = Space -LeftShift
+pause = P
+single_step = Period
+slow_down = Minus
+speed_up = Equal
+toggle_overlay = F3
+report_stats = F4
+";
+
+/// The names of the keys, as in `glfw::Key`.
+const KEYS: &[&str] = &[
+    "Space", "Apostrophe", "Comma", "Minus", "Period", "Slash", "Num0", "Num1", "Num2", "Num3",
+    "Num4", "Num5", "Num6", "Num7", "Num8", "Num9", "Semicolon", "Equal", "A", "B", "C", "D", "E",
+    "F", "G", "H", "I", "J", "K", "L", "M", "N", "O", "P", "Q", "R", "S", "T", "U", "V", "W", "X",
+    "Y", "Z", "LeftBracket", "Backslash", "RightBracket", "GraveAccent", "World1", "World2",
+    "Escape", "Enter", "Tab", "Backspace", "Insert", "Delete", "Right", "Left", "Down", "Up",
+    "PageUp", "PageDown", "Home", "End", "CapsLock", "ScrollLock", "NumLock", "PrintScreen",
+    "Pause", "F1", "F2", "F3", "F4", "F5", "F6", "F7", "F8", "F9", "F10", "F11", "F12", "F13",
+    "F14", "F15", "F16", "F17", "F18", "F19", "F20", "F21", "F22", "F23", "F24", "F25", "Kp0",
+    "Kp1", "Kp2", "Kp3", "Kp4", "Kp5", "Kp6", "Kp7", "Kp8", "Kp9", "KpDecimal", "KpDivide",
+    "KpMultiply", "KpSubtract", "KpAdd", "KpEnter", "KpEqual", "LeftShift", "LeftControl",
+    "LeftAlt", "LeftSuper", "RightShift", "RightControl", "RightAlt", "RightSuper", "Menu",
+];
+
+/// The number of mouse buttons GLFW reports.
+const MOUSE_BUTTONS: u32 = 8;
+
+/// A key or mouse button, by the name it has in bindings files.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Button(String);
+
+impl Button {
+    /// The button with the given name, or `None` if there is no such key or mouse button.
+    pub fn named(name: &str) -> Option<Button> {
+        let is_mouse = name.strip_prefix("Mouse").and_then(|number| number.parse().ok())
+            .is_some_and(|number| (1..=MOUSE_BUTTONS).contains(&number));
+
+        if is_mouse || KEYS.contains(&name) {
+            Some(Button(name.to_string()))
+        } else {
+            None
+        }
+    }
+
+    /// The mouse button with the given number, counting from 1.
+    pub fn mouse(number: u32) -> Button {
+        Button(format!("Mouse{}", number))
+    }
+
+    pub fn name(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Display for Button {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// A change in the keyboard or mouse.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Event {
+    Press(Button),
+    Release(Button),
+
+    /// The cursor moved to the given position, in screen coordinates from the top left of the
+    /// window.
+    CursorMoved(f64, f64),
+
+    /// The mouse wheel or touchpad scrolled by the given horizontal and vertical offsets.
+    Scrolled(f64, f64),
+}
+
+/// An error from loading a bindings file.
+#[derive(Debug)]
+pub enum BindingsError {
+    Io(PathBuf, io::Error),
+
+    /// A line that couldn't be parsed, with the file and line it is on.
+    Parse { path: PathBuf, line: usize, message: String },
+}
+
+impl fmt::Display for BindingsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            BindingsError::Io(ref path, ref e) =>
+                write!(f, "failed to read {}: {}", path.display(), e),
+            BindingsError::Parse { ref path, line, ref message } =>
+                write!(f, "{}:{}: {}", path.display(), line, message),
+        }
+    }
+}
+
+impl Error for BindingsError {}
+
+/// The buttons bound to each action, each with the value it gives the action's axis.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Bindings {
+    actions: HashMap<String, Vec<(Button, f32)>>,
+}
+
+impl Default for Bindings {
+    fn default() -> Bindings {
+        Bindings::parse(Path::new("default bindings"), DEFAULT_BINDINGS)
+            .expect("the default bindings are valid")
+    }
+}
+
+impl Bindings {
+    /// Load the bindings file at `path`.
+    pub fn load(path: &Path) -> Result<Bindings, BindingsError> {
+        let source = fs::read_to_string(path)
+            .map_err(|e| BindingsError::Io(path.to_owned(), e))?;
+        Bindings::parse(path, &source)
+    }
+
+    /// Parse the bindings in `source`, which was read from `path`.
+    pub fn parse(path: &Path, source: &str) -> Result<Bindings, BindingsError> {
+        let mut actions = HashMap::new();
+
+        for (i, line) in source.lines().enumerate() {
+            let error = |message: String| {
+                BindingsError::Parse { path: path.to_owned(), line: i + 1, message }
+            };
+
+            let line = line.split('#').next().unwrap().trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let (action, inputs) = line.split_once('=')
+                .ok_or_else(|| error("expected `action = inputs`".to_string()))?;
+            let action = action.trim();
+            if action.is_empty() || action.contains(char::is_whitespace) {
+                return Err(error(format!("invalid action name `{}`", action)));
+            }
+
+            let buttons = inputs.split_whitespace().map(|input| {
+                let (name, value) = match input.strip_prefix('-') {
+                    Some(name) => (name, -1.0),
+                    None => (input, 1.0),
+                };
+                let button = Button::named(name)
+                    .ok_or_else(|| error(format!("unknown key or mouse button `{}`", name)))?;
+                Ok((button, value))
+            }).collect::<Result<Vec<_>, _>>()?;
+
+            if actions.insert(action.to_string(), buttons).is_some() {
+                return Err(error(format!("`{}` is bound more than once", action)));
+            }
+        }
+
+        Ok(Bindings { actions })
+    }
+
+    /// The buttons bound to `action`, which are none if it isn't in the bindings.
+    pub fn buttons(&self, action: &str) -> &[(Button, f32)] {
+        self.actions.get(action).map_or(&[], |buttons| &buttons[..])
+    }
+}
+
+/// The state of the keyboard and mouse, as of the events of the current frame.
+pub struct Input {
+    bindings: Bindings,
+    held: HashSet<Button>,
+    pressed: HashSet<Button>,
+    released: HashSet<Button>,
+    cursor: Option<(f64, f64)>,
+    cursor_delta: (f64, f64),
+    scroll: (f64, f64),
+}
+
+impl Input {
+    pub fn new(bindings: Bindings) -> Input {
+        Input {
+            bindings,
+            held: HashSet::new(),
+            pressed: HashSet::new(),
+            released: HashSet::new(),
+            cursor: None,
+            cursor_delta: (0.0, 0.0),
+            scroll: (0.0, 0.0),
+        }
+    }
+
+    /// Forget the presses, releases and movement of the last frame. Call this before handling the
+    /// events of each frame.
+    pub fn begin_frame(&mut self) {
+        self.pressed.clear();
+        self.released.clear();
+        self.cursor_delta = (0.0, 0.0);
+        self.scroll = (0.0, 0.0);
+    }
+
+    pub fn handle(&mut self, event: &Event) {
+        match *event {
+            Event::Press(ref button) => {
+                self.held.insert(button.clone());
+                self.pressed.insert(button.clone());
+            }
+            Event::Release(ref button) => {
+                self.held.remove(button);
+                self.released.insert(button.clone());
+            }
+            Event::CursorMoved(x, y) => {
+                // The first position only says where later movements are measured from.
+                if let Some((last_x, last_y)) = self.cursor {
+                    self.cursor_delta.0 += x - last_x;
+                    self.cursor_delta.1 += y - last_y;
+                }
+                self.cursor = Some((x, y));
+            }
+            Event::Scrolled(x, y) => {
+                self.scroll.0 += x;
+                self.scroll.1 += y;
+            }
+        }
+    }
+
+    /// Whether a button bound to `action` went down this frame.
+    pub fn pressed(&self, action: &str) -> bool {
+        self.bindings.buttons(action).iter().any(|(button, _)| self.pressed.contains(button))
+    }
+
+    /// Whether a button bound to `action` went up this frame.
+    pub fn released(&self, action: &str) -> bool {
+        self.bindings.buttons(action).iter().any(|(button, _)| self.released.contains(button))
+    }
+
+    /// Whether a button bound to `action` is down.
+    pub fn held(&self, action: &str) -> bool {
+        self.bindings.buttons(action).iter().any(|(button, _)| self.held.contains(button))
+    }
+
+    /// The sum of the values of the buttons bound to `action` that are down, between -1 and 1.
+    pub fn axis(&self, action: &str) -> f32 {
+        self.bindings.buttons(action).iter()
+            .filter(|(button, _)| self.held.contains(button))
+            .map(|&(_, value)| value)
+            .sum::<f32>()
+            .clamp(-1.0, 1.0)
+    }
+
+    /// Where the cursor is, or `None` if it hasn't moved over the window yet.
+    pub fn cursor(&self) -> Option<(f64, f64)> {
+        self.cursor
+    }
+
+    /// How far the cursor moved this frame.
+    pub fn cursor_delta(&self) -> (f64, f64) {
+        self.cursor_delta
+    }
+
+    /// How far the mouse wheel scrolled this frame.
+    pub fn scroll(&self) -> (f64, f64) {
+        self.scroll
+    }
+}
+
+#[test]
+fn test_bindings() {
+    let bindings = Bindings::parse(Path::new("bindings.txt"), "\
+        # Comments and blank lines are skipped.\n\
+        \n\
+        jump = Space Mouse2  # trailing comment\n\
+        move_right = D -A Right -Left\n").unwrap();
+    assert_eq!(&[(Button::named("Space").unwrap(), 1.0), (Button::mouse(2), 1.0)],
+               bindings.buttons("jump"));
+    assert_eq!(-1.0, bindings.buttons("move_right")[1].1);
+    assert!(bindings.buttons("unbound").is_empty());
+
+    let errors = [
+        ("jump Space", 1, "expected `action = inputs`"),
+        ("jump = Spacebar", 1, "unknown key or mouse button `Spacebar`"),
+        ("\njump = Mouse9", 2, "unknown key or mouse button `Mouse9`"),
+        ("jump = Space\njump = W", 2, "`jump` is bound more than once"),
+    ];
+    for &(source, expected_line, expected_message) in &errors {
+        match Bindings::parse(Path::new("bindings.txt"), source) {
+            Err(BindingsError::Parse { line, message, .. }) => {
+                assert_eq!((expected_line, expected_message), (line, &*message));
+            }
+            result => panic!("expected a parse error for {:?}, got {:?}", source, result),
+        }
+    }
+
+    Bindings::default();
+}
+
+#[test]
+fn test_input() {
+    let bindings = Bindings::parse(Path::new("bindings.txt"), "fire = Mouse1\nmove = D -A\n")
+        .unwrap();
+    let mut input = Input::new(bindings);
+    let (a, d) = (Button::named("A").unwrap(), Button::named("D").unwrap());
+
+    input.handle(&Event::Press(Button::mouse(1)));
+    input.handle(&Event::Press(a.clone()));
+    input.handle(&Event::CursorMoved(10.0, 10.0));
+    input.handle(&Event::CursorMoved(15.0, 5.0));
+    input.handle(&Event::Scrolled(0.0, 1.0));
+    assert!(input.pressed("fire") && input.held("fire") && !input.released("fire"));
+    assert_eq!(-1.0, input.axis("move"));
+    assert_eq!((5.0, -5.0), input.cursor_delta());
+    assert_eq!((0.0, 1.0), input.scroll());
+
+    // Presses only count for the frame they happen in, but buttons stay held.
+    input.begin_frame();
+    input.handle(&Event::Press(d.clone()));
+    assert!(!input.pressed("fire") && input.held("fire"));
+    assert_eq!(0.0, input.axis("move"));
+    assert_eq!((0.0, 0.0), input.cursor_delta());
+    assert_eq!(Some((15.0, 5.0)), input.cursor());
+
+    // A press and release in the same frame is still a press.
+    input.begin_frame();
+    input.handle(&Event::Release(a));
+    input.handle(&Event::Release(Button::mouse(1)));
+    input.handle(&Event::Press(Button::mouse(1)));
+    input.handle(&Event::Release(Button::mouse(1)));
+    assert!(input.pressed("fire") && input.released("fire") && !input.held("fire"));
+    assert_eq!(1.0, input.axis("move"));
+}
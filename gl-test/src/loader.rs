@@ -0,0 +1,326 @@
+//! Decoding assets on worker threads and uploading them to GL a little at a time.
+//!
+//! Work is split in two: a `WorkerPool` runs the CPU-heavy part (reading and decoding files) off
+//! the main thread, and an `UploadQueue` hands the results to GL on the main thread, which is the
+//! only one the context is current on. The queue uploads at most a budget of bytes per frame, so
+//! that a burst of finished assets doesn't stall a frame.
+
+use std::cell::Cell;
+use std::collections::VecDeque;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+
+type Job = Box<dyn FnOnce() + Send>;
+
+/// A fixed set of threads running decoding jobs in the order they were submitted.
+pub struct WorkerPool {
+    jobs: Option<Sender<Job>>,
+    workers: Vec<JoinHandle<()>>,
+}
+
+impl WorkerPool {
+    pub fn new(threads: usize) -> WorkerPool {
+        let (sender, receiver) = mpsc::channel::<Job>();
+        let receiver = Arc::new(Mutex::new(receiver));
+
+        let workers = (0..threads.max(1)).map(|i| {
+            let receiver = receiver.clone();
+            thread::Builder::new().name(format!("asset loader {}", i)).spawn(move || loop {
+                // The lock is released as soon as a job has been taken.
+                let job = match receiver.lock().unwrap().recv() {
+                    Ok(job) => job,
+                    Err(_) => return,
+                };
+
+                // A job that panics only loses its own result; its `Pending` sees the sender go
+                // away without a value.
+                let _ = panic::catch_unwind(AssertUnwindSafe(job));
+            }).expect("failed to spawn an asset loader thread")
+        }).collect();
+
+        WorkerPool { jobs: Some(sender), workers }
+    }
+
+    /// A pool with a thread for each CPU, up to four.
+    pub fn with_default_threads() -> WorkerPool {
+        let cpus = thread::available_parallelism().map(|n| n.get()).unwrap_or(1);
+        WorkerPool::new(cpus.min(4))
+    }
+
+    /// Run `decode` on one of the pool's threads.
+    pub fn decode<T, F>(&self, decode: F) -> Pending<T>
+        where T: Send + 'static, F: FnOnce() -> T + Send + 'static
+    {
+        let (sender, receiver) = mpsc::channel();
+        let job = Box::new(move || {
+            let _ = sender.send(decode());
+        });
+        self.jobs.as_ref().unwrap().send(job).expect("asset loader threads have exited");
+        Pending { receiver, taken: Cell::new(false) }
+    }
+}
+
+impl Drop for WorkerPool {
+    /// Let the workers finish the jobs already submitted, then wait for them to exit.
+    fn drop(&mut self) {
+        self.jobs = None;
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
+
+/// The state of a job submitted to a `WorkerPool`.
+#[derive(Debug, PartialEq)]
+pub enum Poll<T> {
+    Ready(T),
+    Waiting,
+
+    /// The job panicked, or its value has already been taken, so there will never be a value.
+    Lost,
+}
+
+/// The result of a job submitted to a `WorkerPool`, once it finishes.
+pub struct Pending<T> {
+    receiver: Receiver<T>,
+
+    /// Whether the value has been taken. The job's sender can outlive the send by a moment, so
+    /// the channel alone can't tell that there is nothing more to come.
+    taken: Cell<bool>,
+}
+
+impl<T> Pending<T> {
+    /// Take the result if the job has finished.
+    pub fn poll(&self) -> Poll<T> {
+        if self.taken.get() {
+            return Poll::Lost;
+        }
+        match self.receiver.try_recv() {
+            Ok(value) => self.take(value),
+            Err(TryRecvError::Empty) => Poll::Waiting,
+            Err(TryRecvError::Disconnected) => Poll::Lost,
+        }
+    }
+
+    /// Block until the job has finished and take the result.
+    pub fn wait(&self) -> Poll<T> {
+        if self.taken.get() {
+            return Poll::Lost;
+        }
+        match self.receiver.recv() {
+            Ok(value) => self.take(value),
+            Err(_) => Poll::Lost,
+        }
+    }
+
+    fn take(&self, value: T) -> Poll<T> {
+        self.taken.set(true);
+        Poll::Ready(value)
+    }
+}
+
+/// Decoded data waiting for the main thread.
+trait Task {
+    /// Take the decoded data if the job has finished, waiting for it if `wait` is set, and
+    /// return the bytes uploading it will cost. Returns `None` if the job is still running. A job
+    /// that panicked costs nothing and uploads nothing.
+    fn poll(&mut self, wait: bool) -> Option<usize>;
+
+    /// Upload the data taken by `poll`.
+    fn upload(self: Box<Self>);
+}
+
+struct UploadTask<T, C, U> {
+    pending: Pending<T>,
+    decoded: Option<T>,
+    lost: bool,
+    cost: C,
+    upload: U,
+}
+
+impl<T, C: Fn(&T) -> usize, U: FnOnce(T)> Task for UploadTask<T, C, U> {
+    fn poll(&mut self, wait: bool) -> Option<usize> {
+        if self.decoded.is_none() && !self.lost {
+            let poll = if wait { self.pending.wait() } else { self.pending.poll() };
+            match poll {
+                Poll::Ready(value) => self.decoded = Some(value),
+                Poll::Waiting => return None,
+                Poll::Lost => self.lost = true,
+            }
+        }
+
+        Some(self.decoded.as_ref().map_or(0, &self.cost))
+    }
+
+    fn upload(self: Box<Self>) {
+        let task = *self;
+        if let Some(decoded) = task.decoded {
+            (task.upload)(decoded);
+        }
+    }
+}
+
+/// Results taken out of an `UploadQueue` to be uploaded.
+///
+/// Uploading is a separate step so that it can happen after whatever holds the queue has let go
+/// of it: an upload may well queue more jobs, like a model queueing its textures.
+pub struct Ready<'a> {
+    tasks: Vec<Box<dyn Task + 'a>>,
+    bytes: usize,
+}
+
+impl<'a> Ready<'a> {
+    /// The number of bytes uploading the results will transfer.
+    pub fn bytes(&self) -> usize {
+        self.bytes
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tasks.is_empty()
+    }
+
+    /// Upload the results, in the order they were queued.
+    pub fn upload(self) {
+        for task in self.tasks {
+            task.upload();
+        }
+    }
+}
+
+/// Jobs whose results are to be uploaded on the main thread, in the order they were queued.
+pub struct UploadQueue<'a> {
+    tasks: VecDeque<Box<dyn Task + 'a>>,
+}
+
+impl<'a> UploadQueue<'a> {
+    pub fn new() -> UploadQueue<'a> {
+        UploadQueue { tasks: VecDeque::new() }
+    }
+
+    /// Call `upload` with the result of `pending` once it is ready and there's room in the
+    /// budget for `cost` of it. Nothing is uploaded if the job panicked.
+    pub fn push<T, C, U>(&mut self, pending: Pending<T>, cost: C, upload: U)
+        where T: 'a, C: Fn(&T) -> usize + 'a, U: FnOnce(T) + 'a
+    {
+        self.tasks.push_back(Box::new(UploadTask {
+            pending,
+            decoded: None,
+            lost: false,
+            cost,
+            upload,
+        }));
+    }
+
+    /// Take finished results, up to `budget` bytes in total. The first result that is ready is
+    /// always taken, however big it is, so that large assets still get through.
+    pub fn take_ready(&mut self, budget: usize) -> Ready<'a> {
+        let mut ready = Ready { tasks: Vec::new(), bytes: 0 };
+        let mut waiting = VecDeque::new();
+
+        for mut task in self.tasks.drain(..) {
+            let limit = if ready.bytes == 0 {
+                usize::MAX
+            } else {
+                budget.saturating_sub(ready.bytes)
+            };
+            match task.poll(false) {
+                Some(cost) if cost <= limit => {
+                    ready.bytes += cost;
+                    ready.tasks.push(task);
+                }
+                _ => waiting.push_back(task),
+            }
+        }
+
+        self.tasks = waiting;
+        ready
+    }
+
+    /// Wait for every queued job and take all the results, ignoring the budget.
+    pub fn take_all(&mut self) -> Ready<'a> {
+        let mut ready = Ready { tasks: Vec::new(), bytes: 0 };
+        for mut task in self.tasks.drain(..) {
+            ready.bytes += task.poll(true).unwrap_or(0);
+            ready.tasks.push(task);
+        }
+        ready
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tasks.is_empty()
+    }
+}
+
+impl<'a> Default for UploadQueue<'a> {
+    fn default() -> UploadQueue<'a> {
+        UploadQueue::new()
+    }
+}
+
+#[test]
+fn test_upload_budget() {
+    use std::cell::RefCell;
+
+    // With one thread, the jobs finish in order.
+    let pool = WorkerPool::new(1);
+    let uploaded = RefCell::new(Vec::new());
+    let mut queue = UploadQueue::new();
+
+    // Three results of 10 bytes each, and one job that panics.
+    for i in 0..3 {
+        queue.push(pool.decode(move || vec![i as u8; 10]), |data: &Vec<u8>| data.len(),
+                   |data: Vec<u8>| uploaded.borrow_mut().push(data[0]));
+    }
+    queue.push(pool.decode(|| -> Vec<u8> { panic!("corrupt asset") }),
+               |data: &Vec<u8>| data.len(), |_| panic!("uploaded a lost result"));
+
+    // Wait for the jobs, without uploading anything.
+    assert_eq!(Poll::Ready(()), pool.decode(|| ()).wait());
+
+    // The first result always goes through, leaving 5 bytes of the budget, which is too little
+    // for the others. The lost job is dropped.
+    let ready = queue.take_ready(15);
+    assert_eq!(10, ready.bytes());
+    ready.upload();
+    assert_eq!(vec![0], *uploaded.borrow());
+    let ready = queue.take_ready(25);
+    assert_eq!(20, ready.bytes());
+    ready.upload();
+    assert_eq!(vec![0, 1, 2], *uploaded.borrow());
+    assert!(queue.is_empty());
+
+    let pending = pool.decode(|| 42);
+    assert_eq!(Poll::Ready(42), pending.wait());
+    assert_eq!(Poll::Lost, pending.poll());
+    assert_eq!(Poll::Lost, pending.wait());
+}
+
+#[test]
+fn test_upload_queues_more() {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    // An upload that queues another job, as a model does for its textures, can only run once
+    // the queue is no longer borrowed.
+    let pool = Rc::new(WorkerPool::new(1));
+    let queue = Rc::new(RefCell::new(UploadQueue::new()));
+    let uploaded = Rc::new(RefCell::new(Vec::new()));
+
+    let (inner_pool, inner_queue, inner_uploaded) = (pool.clone(), queue.clone(), uploaded.clone());
+    queue.borrow_mut().push(pool.decode(|| 1), |_: &i32| 0, move |value: i32| {
+        inner_uploaded.borrow_mut().push(value);
+        inner_queue.borrow_mut().push(inner_pool.decode(|| 2), |_: &i32| 0,
+                                      move |value: i32| inner_uploaded.borrow_mut().push(value));
+    });
+
+    loop {
+        let ready = queue.borrow_mut().take_all();
+        if ready.is_empty() {
+            break;
+        }
+        ready.upload();
+    }
+    assert_eq!(vec![1, 2], *uploaded.borrow());
+}
@@ -1,124 +1,113 @@
 extern crate gl;
 extern crate glfw;
 extern crate imagefmt;
+extern crate osmesa_sys;
+extern crate serde_json;
 extern crate time;
+#[macro_use]
+extern crate vertex_layout_derive;
 
-mod math;
-
-use gl::types::*;
 use glfw::{Context, OpenGlProfileHint, WindowHint, WindowMode};
 use std::mem;
-use std::ptr;
-
-macro_rules! gl_str {
-    ($string_literal:expr) => (
-        concat!($string_literal, '\0').as_bytes().as_ptr() as *const GLchar
-    )
-}
-
-const VERTEX_SHADER_SOURCE: &'static str = "
-    #version 150
-
-    in vec2 position;
-    in vec3 color;
-    in vec2 texcoord;
-
-    out vec3 Color;
-    out vec2 Texcoord;
-
-    uniform mat4 trans;
-
-    void main() {
-        Color = color;
-        Texcoord = texcoord;
-        gl_Position = trans * vec4(position, 0.0, 1.0);
-    }
-";
-
-const FRAGMENT_SHADER_SOURCE: &'static str = "
-    #version 150
-
-    in vec3 Color;
-    in vec2 Texcoord;
-
-    out vec4 out_color;
-
-    uniform sampler2D tex_kitten;
-    uniform sampler2D tex_puppy;
-    uniform float time;
-
-    void main() {
-        float mix_factor = (sin(time * 3.0) + 1.0) / 2.0;
-        vec4 col_kitten = texture(tex_kitten, Texcoord);
-        vec4 col_puppy = texture(tex_puppy, Texcoord);
-        vec4 mixed_texture = mix(col_kitten, col_puppy, mix_factor);
-        out_color = mix(vec4(Color, 1.0), mixed_texture, 0.25);
-    }
-";
-
-#[derive(Copy, Clone, Debug, PartialEq)]
-#[repr(C, packed)]
-struct Vertex {
-    // Position.
-    x: f32, y: f32,
-
-    // Color.
-    r: f32, g: f32, b: f32,
+use std::process;
+
+mod assets;
+mod camera;
+mod context;
+#[macro_use]
+mod debug;
+mod demo;
+mod gltf;
+#[cfg(test)]
+mod golden;
+mod headless;
+mod input;
+mod loader;
+mod math;
+mod mesh;
+mod model;
+mod obj;
+mod object;
+mod options;
+mod preprocess;
+mod raster;
+mod reload;
+mod render_state;
+mod replay;
+mod screenshot;
+mod shader;
+mod stats;
+mod texture;
+mod timestep;
+mod uniform;
+mod vertex;
+
+use assets::Assets;
+use camera::{Camera, OrbitCamera};
+use context::GlContext;
+use debug::Reporting;
+use demo::{Demo, SoftwareDemo};
+use headless::{Framebuffer, HeadlessContext};
+use input::{Bindings, Input};
+use loader::{Pending, Poll};
+use math::Vec3;
+use options::Options;
+use reload::LoadError;
+use replay::{Frame, Recorder};
+use stats::{FrameStats, GpuTimer, Overlay};
+use timestep::Timestep;
+
+/// How many bytes of decoded assets to upload to GL each frame.
+const UPLOAD_BUDGET: usize = 8 << 20;
+
+/// The number of frames the timing statistics cover.
+const STATS_FRAMES: usize = 600;
+
+/// The most simulation updates to run for one frame before letting the simulation fall behind.
+const MAX_UPDATES_PER_FRAME: u32 = 10;
 
-    // Texture.
-    s: f32, t: f32,
-}
+fn main() {
+    let options = match Options::from_env() {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!("{}\n\n{}", e, options::USAGE);
+            process::exit(1);
+        }
+    };
 
-static VERTICES: [Vertex; 4] = [
-    Vertex { x: -0.5, y:  0.5, r: 1.0, g: 0.0, b: 0.0, s: 0.0, t: 0.0 }, // Top-left
-    Vertex { x:  0.5, y:  0.5, r: 0.0, g: 1.0, b: 0.0, s: 1.0, t: 0.0 }, // Top-right
-    Vertex { x:  0.5, y: -0.5, r: 0.0, g: 0.0, b: 1.0, s: 1.0, t: 1.0 }, // Bottom-right
-    Vertex { x: -0.5, y: -0.5, r: 1.0, g: 1.0, b: 1.0, s: 0.0, t: 1.0 }, // Bottom-left
-];
-
-static ELEMENTS: [u32; 6] = [
-    0, 1, 2, // Top-right triangle
-    2, 3, 0, // Bottom-left triangle
-];
-
-unsafe fn compile_shader(shader_type: GLenum, source: &str) -> Result<u32, String> {
-    let shader = gl::CreateShader(shader_type);
-    let source_ptr = source.as_bytes().as_ptr() as *const GLchar;
-    let source_len = source.len() as i32;
-    gl::ShaderSource(shader, 1, &source_ptr, &source_len);
-    gl::CompileShader(shader);
-
-    let mut status = gl::FALSE as i32;
-    gl::GetShaderiv(shader, gl::COMPILE_STATUS, &mut status);
-
-    if status == gl::TRUE as i32 {
-        Ok(shader)
+    if options.software {
+        run_software(&options);
+    } else if options.headless {
+        run_headless(&options);
     } else {
-        let mut log_len = 0;
-        gl::GetShaderiv(shader, gl::INFO_LOG_LENGTH, &mut log_len);
-        if log_len == 0 { return Err(String::new()) }
-
-        let mut buf = Vec::with_capacity(log_len as usize);
-        buf.set_len(log_len as usize - 1); // Subtract 1 to ignore the trailing null.
-        gl::GetShaderInfoLog(shader, log_len, ptr::null_mut(), buf.as_mut_ptr() as *mut GLchar);
-
-        Err(String::from_utf8_lossy(&buf).into_owned())
+        run_windowed(&options);
     }
 }
 
-fn main() {
+fn run_windowed(options: &Options) {
+    let bindings = match options.bindings {
+        Some(ref path) => Bindings::load(path).unwrap_or_else(|e| panic!("{}", e)),
+        None => Bindings::default(),
+    };
+
     let mut glfw = glfw::init(glfw::FAIL_ON_ERRORS).unwrap();
 
     glfw.window_hint(WindowHint::ContextVersion(3, 2));
     glfw.window_hint(WindowHint::OpenGlProfile(OpenGlProfileHint::Core));
     glfw.window_hint(WindowHint::OpenGlForwardCompat(true));
-    glfw.window_hint(WindowHint::Resizable(false));
+    glfw.window_hint(WindowHint::Resizable(true));
+    glfw.window_hint(WindowHint::DepthBits(24));
+    glfw.window_hint(WindowHint::OpenGlDebugContext(options.gl_debug.is_some()));
 
     let (mut window, events) = glfw.create_window(800, 600, "OpenGL", WindowMode::Windowed)
         .expect("Failed to create GLFW window.");
 
-    // Listen for keyboard events on this window.
+    // Listen for keyboard and mouse events and framebuffer resizes on this window.
     window.set_key_polling(true);
+    window.set_mouse_button_polling(true);
+    window.set_cursor_pos_polling(true);
+    window.set_scroll_polling(true);
+    window.set_framebuffer_size_polling(true);
 
     // Make this window's OpenGL context the current context. This must be done before calling
     // `gl::load_with`.
@@ -127,155 +116,368 @@ fn main() {
     // Load OpenGL function pointers.
     gl::load_with(|symbol| window.get_proc_address(symbol));
 
-    let vertex_shader;
-    let fragment_shader;
-    let shader_program;
-    let mut vao = 0;
-    let mut vbo = 0;
-    let mut ebo = 0;
-    let mut textures = [0; 2];
-
-    unsafe {
-        // Create a vertex array object.
-        gl::GenVertexArrays(1, &mut vao);
-        gl::BindVertexArray(vao);
-
-        // Create a vertex buffer object and copy the vertex data to it.
-        gl::GenBuffers(1, &mut vbo);
-        gl::BindBuffer(gl::ARRAY_BUFFER, vbo);
-        gl::BufferData(gl::ARRAY_BUFFER,
-                       mem::size_of_val(&VERTICES) as usize,
-                       VERTICES.as_ptr() as *const (),
-                       gl::STATIC_DRAW);
-
-        // Create an element buffer object and copy the element data to it.
-        gl::GenBuffers(1, &mut ebo);
-        gl::BindBuffer(gl::ELEMENT_ARRAY_BUFFER, ebo);
-        gl::BufferData(gl::ELEMENT_ARRAY_BUFFER,
-                       mem::size_of_val(&ELEMENTS) as usize,
-                       ELEMENTS.as_ptr() as *const (),
-                       gl::STATIC_DRAW);
-
-        // Compile the vertex and fragment shaders.
-        vertex_shader = compile_shader(gl::VERTEX_SHADER, VERTEX_SHADER_SOURCE).unwrap();
-        fragment_shader = compile_shader(gl::FRAGMENT_SHADER, FRAGMENT_SHADER_SOURCE).unwrap();
-
-        // Link the vertex and fragment shaders into a shader program.
-        shader_program = gl::CreateProgram();
-        gl::AttachShader(shader_program, vertex_shader);
-        gl::AttachShader(shader_program, fragment_shader);
-        gl::BindFragDataLocation(shader_program, 0, gl_str!("out_color"));
-        gl::LinkProgram(shader_program);
-        gl::UseProgram(shader_program);
-
-        // Specify the layout of the vertex data.
-        let position_attrib = gl::GetAttribLocation(shader_program, gl_str!("position"));
-        gl::EnableVertexAttribArray(position_attrib as u32);
-        gl::VertexAttribPointer(position_attrib as u32, 2, gl::FLOAT, gl::FALSE,
-                                mem::size_of::<Vertex>() as i32, ptr::null());
-
-        let position_attrib = gl::GetAttribLocation(shader_program, gl_str!("color"));
-        gl::EnableVertexAttribArray(position_attrib as u32);
-        gl::VertexAttribPointer(position_attrib as u32, 3, gl::FLOAT, gl::FALSE,
-                                mem::size_of::<Vertex>() as i32,
-                                (2 * mem::size_of::<f32>()) as *const ());
-
-        let position_attrib = gl::GetAttribLocation(shader_program, gl_str!("texcoord"));
-        gl::EnableVertexAttribArray(position_attrib as u32);
-        gl::VertexAttribPointer(position_attrib as u32, 2, gl::FLOAT, gl::FALSE,
-                                mem::size_of::<Vertex>() as i32,
-                                (5 * mem::size_of::<f32>()) as *const ());
-
-        // Create and load textures.
-        gl::GenTextures(2, textures.as_mut_ptr());
-
-        gl::ActiveTexture(gl::TEXTURE0);
-        gl::BindTexture(gl::TEXTURE_2D, textures[0]);
-        let image = imagefmt::read("sample.png", imagefmt::ColFmt::RGB).unwrap();
-        gl::TexImage2D(gl::TEXTURE_2D, 0, gl::RGB as i32, image.w as i32, image.h as i32,
-                       0, gl::RGB, gl::UNSIGNED_BYTE, image.buf.as_ptr() as *const ());
-        gl::Uniform1i(gl::GetUniformLocation(shader_program, gl_str!("tex_kitten")), 0);
-
-        gl::GenerateMipmap(gl::TEXTURE_2D);
-        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, gl::REPEAT as i32);
-        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, gl::REPEAT as i32);
-        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::LINEAR_MIPMAP_LINEAR as i32);
-        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::LINEAR_MIPMAP_LINEAR as i32);
-
-        gl::ActiveTexture(gl::TEXTURE1);
-        gl::BindTexture(gl::TEXTURE_2D, textures[1]);
-        let image = imagefmt::read("sample2.png", imagefmt::ColFmt::RGB).unwrap();
-        gl::TexImage2D(gl::TEXTURE_2D, 0, gl::RGB as i32, image.w as i32, image.h as i32,
-                       0, gl::RGB, gl::UNSIGNED_BYTE, image.buf.as_ptr() as *const ());
-        gl::Uniform1i(gl::GetUniformLocation(shader_program, gl_str!("tex_puppy")), 1);
-
-        gl::GenerateMipmap(gl::TEXTURE_2D);
-        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, gl::REPEAT as i32);
-        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, gl::REPEAT as i32);
-        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::LINEAR_MIPMAP_LINEAR as i32);
-        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::LINEAR_MIPMAP_LINEAR as i32);
+    // The window's context stays current on this thread until the window is destroyed, after
+    // everything borrowing `context` has been dropped.
+    let context = unsafe { GlContext::new() };
+    enable_debug(&context, options);
+
+    let assets = Assets::new(&context, &options.asset_dir, &options.shader_dir);
+    let scene = options.scene.as_deref();
+    let mut shaders = demo::shader_files(&options.shader_dir, scene, options.render_state);
+    let mut demo = Demo::new(&assets, &mut shaders, options.render_state, scene)
+        .unwrap_or_else(|e| panic!("{}", e));
+    let mut shader_reload = None;
+    let mut recorder = options.record.as_ref().map(|path| {
+        Recorder::create(path)
+            .unwrap_or_else(|e| panic!("Failed to create {}: {}", path.display(), e))
+    });
+    let mut replay = options.replay.as_ref().map(|path| {
+        replay::read(path).unwrap_or_else(|e| panic!("{}", e)).into_iter()
+    });
+    if recorder.is_some() || replay.is_some() {
+        // Assets that finish loading at different times would make the frames of a replay differ
+        // from those recorded, so both start with everything loaded.
+        assets.finish_loading();
     }
 
-    let view = math::Mat4::look_at(
-        math::Vec3([1.2, 1.2, 1.2]),
-        math::Vec3([0.0, 0.0, 0.0]),
-        math::Vec3([0.0, 0.0, 1.0]));
-    let proj = math::Mat4::perspective(math::TAU / 8.0, 800.0 / 600.0, 1.0, 10.0);
-
-    let trans_uniform = unsafe { gl::GetUniformLocation(shader_program, gl_str!("trans")) };
-    let time_uniform = unsafe { gl::GetUniformLocation(shader_program, gl_str!("time")) };
     let time_start = time::precise_time_ns();
+    let mut time_last = 0;
+    let mut pending_screenshot = options.screenshot;
+    let mut screenshot_count = 0;
+    let mut state = WindowState::new();
+    let mut input = Input::new(bindings);
+    let mut camera = Camera::Orbit(OrbitCamera::new(Vec3(demo::EYE), Vec3::zero()));
+    let mut timestep = Timestep::new(options.update_rate, MAX_UPDATES_PER_FRAME);
+    let mut stats = FrameStats::new(STATS_FRAMES);
+    let mut gpu_timer = GpuTimer::new(&context);
+    let overlay = Overlay::new(&assets).unwrap_or_else(|e| panic!("{}", e));
+    let mut show_overlay = options.stats;
+    let mut last_frame_start = None;
+
+    // The first frame starts with the framebuffer at its initial size.
+    let (width, height) = window.get_framebuffer_size();
+    let mut pending_events = vec![replay::Event::FramebufferSize(width, height)];
 
     while !window.should_close() {
+        let frame_start = time::precise_time_ns();
+        if let Some(last) = last_frame_start {
+            stats.frame.push((frame_start - last) as f64 / 1e9);
+        }
+        last_frame_start = Some(frame_start);
+
         glfw.poll_events();
-        for (_, event) in glfw::flush_messages(&events) {
-            handle_window_event(&mut window, event);
+        pending_events.extend(glfw::flush_messages(&events)
+            .filter_map(|(_, event)| window_event(event)));
+        let time_now = time::precise_time_ns() - time_start;
+
+        // A replay takes its events and times from the recording instead, and ends with it. Only
+        // closing the window still works.
+        let frame = match replay {
+            Some(ref mut frames) => match frames.next() {
+                Some(frame) => {
+                    pending_events.clear();
+                    frame
+                }
+                None => break,
+            },
+            None => Frame { time: time_now, events: mem::take(&mut pending_events) },
+        };
+
+        if let Some(ref mut recorder) = recorder {
+            if let Err(e) = recorder.record(&frame) {
+                eprintln!("Failed to record frame: {}", e);
+            }
+        }
+
+        input.begin_frame();
+        for event in &frame.events {
+            match *event {
+                replay::Event::FramebufferSize(width, height) => state.resize(width, height),
+                replay::Event::Input(ref event) => input.handle(event),
+            }
+        }
+
+        if input.pressed("quit") {
+            window.set_should_close(true);
+        }
+        if input.pressed("fullscreen") {
+            state.toggle_fullscreen(&mut window);
+        }
+        if input.pressed("pause") {
+            timestep.set_paused(!timestep.is_paused());
+        }
+        if input.pressed("single_step") {
+            timestep.single_step();
+        }
+        if input.pressed("toggle_overlay") {
+            show_overlay = !show_overlay;
+        }
+        if input.pressed("report_stats") {
+            print!("{}", stats.report());
+        }
+        if input.pressed("slow_down") || input.pressed("speed_up") {
+            let factor = if input.pressed("speed_up") { 2.0 } else { 0.5 };
+            timestep.set_time_scale(timestep.time_scale() * factor);
+            println!("Time scale {}.", timestep.time_scale());
+        }
+
+        // The fly camera turns with the cursor wherever it is, so it hides the cursor and keeps
+        // it in the window.
+        if input.pressed("switch_camera") {
+            camera.switch();
+            window.set_cursor_mode(match camera {
+                Camera::Orbit(_) => glfw::CursorMode::Normal,
+                Camera::Fly(_) => glfw::CursorMode::Disabled,
+            });
+        }
+        if input.pressed("scene_camera") {
+            demo.toggle_scene_camera();
+        }
+
+        // Pick up edits to the shader files, which are read in the background and built once
+        // they have been. A broken edit leaves the last good program running.
+        if shader_reload.is_none() && shaders.changed() {
+            shader_reload = Some(assets.decode(shaders.reader()));
+        }
+        match shader_reload.as_ref().map(Pending::poll) {
+            Some(Poll::Ready(sources)) => {
+                shader_reload = None;
+                match sources.map_err(LoadError::from)
+                    .and_then(|sources| demo.reload_shaders(&assets, &mut shaders, sources)) {
+                    Ok(()) => println!("Reloaded shaders."),
+                    Err(e) => eprintln!("{}\nKeeping the previous shader program.", e),
+                }
+            }
+            Some(Poll::Lost) => {
+                shader_reload = None;
+                eprintln!("Reading the shaders failed.\nKeeping the previous shader program.");
+            }
+            Some(Poll::Waiting) | None => {}
+        }
+
+        // Upload textures that have finished decoding in the background.
+        assets.upload(UPLOAD_BUDGET);
+
+        // The camera moves in real time, so it can look around a paused or slowed scene.
+        let frame_seconds = (frame.time - time_last) as f64 / 1e9;
+        time_last = frame.time;
+        camera.update(&input, frame_seconds as f32);
+
+        // The demo's animation is a function of the simulated time, so there is no state to
+        // update in each step, only the time to draw at.
+        timestep.advance(frame_seconds);
+        let mut elapsed_seconds = timestep.render_time() as f32;
+
+        // Draw the frame requested on the command line at exactly the requested time, rather than
+        // whenever the wall clock happens to pass it.
+        let mut screenshot_path = None;
+        if let Some(screenshot_time) = pending_screenshot {
+            if elapsed_seconds >= screenshot_time {
+                elapsed_seconds = screenshot_time;
+                assets.finish_loading();
+                screenshot_path = Some(options.output.clone());
+                pending_screenshot = None;
+            }
+        }
+        if input.pressed("screenshot") && screenshot_path.is_none() {
+            screenshot_count += 1;
+            screenshot_path = Some(format!("screenshot-{}.png", screenshot_count));
+        }
+
+        if let Some(ref mut timer) = gpu_timer {
+            timer.begin();
+        }
+
+        demo.draw(elapsed_seconds, camera.view(), state.aspect);
+
+        if let Some(path) = screenshot_path {
+            let (width, height) = state.framebuffer_size;
+            match unsafe { screenshot::save(&path, width as u32, height as u32) } {
+                Ok(()) => println!("Saved screenshot to {}.", path),
+                Err(e) => eprintln!("Failed to save screenshot to {}: {}", path, e),
+            }
         }
 
-        unsafe {
-            // Update the `time` uniform.
-            let time_now = time::precise_time_ns();
-            let elapsed_seconds = (time_now - time_start) as f32 / 1e9;
-            gl::Uniform1f(time_uniform, elapsed_seconds);
-
-            // Vary the model matrix over time.
-            let scale = (elapsed_seconds * 5.0).sin() * 0.25 + 0.75;
-            let model =
-                math::Mat4::rotate_z(math::TAU / 2.0 * elapsed_seconds) *
-                math::Mat4::scale(scale, scale, scale);
-            let trans = proj * view * model;
-            gl::UniformMatrix4fv(trans_uniform, 1, gl::FALSE, &trans[0][0]);
-
-            // Clear the screen to black.
-            gl::ClearColor(0.0, 0.0, 0.0, 1.0);
-            gl::Clear(gl::COLOR_BUFFER_BIT);
-
-            // Draw the triangles described by the elements array.
-            gl::DrawElements(gl::TRIANGLES, ELEMENTS.len() as i32, gl::UNSIGNED_INT,
-                             ptr::null());
+        // The overlay is drawn after any screenshot, so that it doesn't show up in them.
+        if show_overlay {
+            overlay.draw(&stats);
         }
 
+        if let Some(ref mut timer) = gpu_timer {
+            timer.end();
+            for seconds in timer.finished() {
+                stats.gpu.push(seconds);
+            }
+        }
+
+        let swap_start = time::precise_time_ns();
+        stats.cpu.push((swap_start - frame_start) as f64 / 1e9);
         window.swap_buffers();
+        stats.swap.push((time::precise_time_ns() - swap_start) as f64 / 1e9);
+    }
+
+    if options.stats {
+        print!("{}", stats.report());
     }
+}
+
+/// Report GL errors and driver messages if `--gl-debug` was given.
+fn enable_debug(context: &GlContext, options: &Options) {
+    if let Some(severity) = options.gl_debug {
+        if debug::enable(context, severity) == Reporting::ErrorChecks {
+            eprintln!("GL_KHR_debug is not supported; only errors from checked calls will be \
+                       reported.");
+        }
+    }
+}
+
+/// Render `options.frames` frames into an offscreen framebuffer, advancing the simulated time by
+/// a fixed step each frame instead of following the wall clock.
+fn run_headless(options: &Options) {
+    let context = HeadlessContext::new(options.width, options.height)
+        .unwrap_or_else(|e| panic!("Failed to create headless context: {}", e));
+    enable_debug(context.gl(), options);
 
     unsafe {
-        gl::DeleteTextures(2, textures.as_ptr());
-        gl::DeleteProgram(shader_program);
-        gl::DeleteShader(fragment_shader);
-        gl::DeleteShader(vertex_shader);
-        gl::DeleteBuffers(1, &ebo);
-        gl::DeleteBuffers(1, &vbo);
-        gl::DeleteVertexArrays(1, &vao);
+        let framebuffer = Framebuffer::new(options.width, options.height).unwrap();
+        framebuffer.bind();
+
+        let assets = Assets::new(context.gl(), &options.asset_dir, &options.shader_dir);
+        let scene = options.scene.as_deref();
+        let mut shaders = demo::shader_files(&options.shader_dir, scene, options.render_state);
+        let demo = Demo::new(&assets, &mut shaders, options.render_state, scene)
+            .unwrap_or_else(|e| panic!("{}", e));
+        assets.finish_loading();
+        let aspect = options.width as f32 / options.height as f32;
+
+        for frame in 0..options.frames {
+            demo.draw(options.time + frame as f32 * options.time_step, demo::default_view(),
+                      aspect);
+        }
+
+        if let Some(screenshot_time) = options.screenshot {
+            demo.draw(screenshot_time, demo::default_view(), aspect);
+            screenshot::save(&options.output, options.width, options.height)
+                .unwrap_or_else(|e| panic!("Failed to save screenshot: {}", e));
+        }
+
+        gl::Finish();
+
+        framebuffer.delete();
     }
 }
 
-fn handle_window_event(window: &mut glfw::Window, event: glfw::WindowEvent) {
-    use glfw::{Action, Key, WindowEvent};
+/// Like `run_headless`, but drawing with the software rasterizer, so no OpenGL implementation is
+/// needed at all.
+fn run_software(options: &Options) {
+    let demo = SoftwareDemo::new(&options.asset_dir, options.render_state);
+    let mut framebuffer = raster::Framebuffer::new(options.width, options.height);
 
-    match event {
-        WindowEvent::Key(Key::Escape, _, Action::Press, _) => {
-            window.set_should_close(true);
-        },
-        _ => {},
+    for frame in 0..options.frames {
+        demo.draw(&mut framebuffer, options.time + frame as f32 * options.time_step);
+    }
+
+    if let Some(screenshot_time) = options.screenshot {
+        demo.draw(&mut framebuffer, screenshot_time);
+        screenshot::write_png(&options.output, options.width, options.height, &framebuffer.pixels)
+            .unwrap_or_else(|e| panic!("Failed to save screenshot: {}", e));
     }
 }
+
+/// The size and placement of the window.
+struct WindowState {
+    /// The size of the framebuffer in pixels. On HiDPI displays this is larger than the window
+    /// size, which is in screen coordinates.
+    framebuffer_size: (i32, i32),
+
+    /// The aspect ratio of the framebuffer the last time it had any pixels, so that minimizing the
+    /// window doesn't divide by zero.
+    aspect: f32,
+
+    /// The position and size of the window before it was made fullscreen, or `None` if it is
+    /// windowed.
+    windowed: Option<((i32, i32), (i32, i32))>,
+}
+
+impl WindowState {
+    fn new() -> WindowState {
+        WindowState {
+            framebuffer_size: (0, 0),
+            aspect: 1.0,
+            windowed: None,
+        }
+    }
+
+    /// Draw to the whole framebuffer after it has been resized.
+    fn resize(&mut self, width: i32, height: i32) {
+        unsafe { gl_check!(gl::Viewport(0, 0, width, height)) };
+        self.framebuffer_size = (width, height);
+        if width > 0 && height > 0 {
+            self.aspect = width as f32 / height as f32;
+        }
+    }
+
+    /// Make the window cover the primary monitor, or put it back where it was.
+    ///
+    /// GLFW 3.1 can only give a window a monitor when it is created, and recreating the window
+    /// would lose the vertex arrays, which aren't shared between contexts. So fullscreen here is
+    /// a window the size of the monitor's video mode, placed over it.
+    fn toggle_fullscreen(&mut self, window: &mut glfw::Window) {
+        if let Some(((x, y), (width, height))) = self.windowed.take() {
+            window.set_pos(x, y);
+            window.set_size(width, height);
+            return;
+        }
+
+        let bounds = window.glfw.with_primary_monitor(|_, monitor| {
+            let monitor = monitor?;
+            let mode = monitor.get_video_mode()?;
+            Some((monitor.get_pos(), (mode.width as i32, mode.height as i32)))
+        });
+
+        match bounds {
+            Some(((x, y), (width, height))) => {
+                self.windowed = Some((window.get_pos(), window.get_size()));
+                window.set_pos(x, y);
+                window.set_size(width, height);
+            }
+            None => eprintln!("Can't go fullscreen: no monitor found."),
+        }
+    }
+}
+
+/// The event a window event is recorded as, or `None` if it doesn't change what is drawn.
+fn window_event(event: glfw::WindowEvent) -> Option<replay::Event> {
+    use glfw::{Action, WindowEvent};
+    use input::{Button, Event};
+
+    let event = match event {
+        // The framebuffer size, not the window size, because the viewport is in pixels.
+        WindowEvent::FramebufferSize(width, height) => {
+            return Some(replay::Event::FramebufferSize(width, height));
+        }
+
+        // Bindings use the names of the keys, which are their names in GLFW. Repeats don't change
+        // whether a key is held.
+        WindowEvent::Key(key, _, action, _) => {
+            let button = Button::named(&format!("{:?}", key))?;
+            match action {
+                Action::Press => Event::Press(button),
+                Action::Release => Event::Release(button),
+                Action::Repeat => return None,
+            }
+        }
+        WindowEvent::MouseButton(button, action, _) => {
+            let button = Button::mouse(button as u32 + 1);
+            match action {
+                Action::Press | Action::Repeat => Event::Press(button),
+                Action::Release => Event::Release(button),
+            }
+        }
+        WindowEvent::CursorPos(x, y) => Event::CursorMoved(x, y),
+        WindowEvent::Scroll(x, y) => Event::Scrolled(x, y),
+        _ => return None,
+    };
+
+    Some(replay::Event::Input(event))
+}
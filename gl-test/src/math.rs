@@ -88,6 +88,20 @@ macro_rules! define_vec {
                 result
             }
         }
+
+        impl Mul<f32> for $name {
+            type Output = Self;
+
+            fn mul(self, factor: f32) -> Self {
+                let mut result = self;
+
+                for i in 0..$size {
+                    result[i] *= factor;
+                }
+
+                result
+            }
+        }
     );
 }
 
@@ -189,6 +203,21 @@ impl Mat4 {
         ])
     }
 
+    /// Build a matrix representing the rotation described by the unit quaternion `[x, y, z, w]`.
+    pub fn rotate(quaternion: [f32; 4]) -> Self {
+        let [x, y, z, w] = quaternion;
+        let (xx, yy, zz) = (x * x, y * y, z * z);
+        let (xy, xz, yz) = (x * y, x * z, y * z);
+        let (wx, wy, wz) = (w * x, w * y, w * z);
+
+        Mat4([
+            [1.0 - 2.0 * (yy + zz), 2.0 * (xy + wz),       2.0 * (xz - wy),       0.0],
+            [2.0 * (xy - wz),       1.0 - 2.0 * (xx + zz), 2.0 * (yz + wx),       0.0],
+            [2.0 * (xz + wy),       2.0 * (yz - wx),       1.0 - 2.0 * (xx + yy), 0.0],
+            [0.0,                   0.0,                   0.0,                   1.0],
+        ])
+    }
+
     /// Build a camera view matrix with the camera at `eye` looking toward `center` with `up` as
     /// the vertical direction.
     pub fn look_at(eye: Vec3, center: Vec3, up: Vec3) -> Self {
@@ -237,6 +266,37 @@ impl Mat4 {
         result[3][2] = (2.0 * z_near * z_far) / z_diff;
         result
     }
+
+    /// Build a perspective projection matrix like `perspective`, but with no far clipping plane.
+    pub fn perspective_infinite(fov_y: f32, aspect: f32, z_near: f32) -> Self {
+        assert!(aspect != 0.0);
+
+        let f = 1.0 / (fov_y / 2.0).tan();
+
+        let mut result = Mat4::zero();
+        result[0][0] = f / aspect;
+        result[1][1] = f;
+        result[2][2] = -1.0;
+        result[2][3] = -1.0;
+        result[3][2] = -2.0 * z_near;
+        result
+    }
+
+    /// Build an orthographic projection matrix with the given half width and half height of the
+    /// view volume, and Z-axis clipping distances.
+    pub fn orthographic(x_mag: f32, y_mag: f32, z_near: f32, z_far: f32) -> Self {
+        assert!(x_mag != 0.0 && y_mag != 0.0);
+        assert!(z_near != z_far);
+
+        let z_diff = z_near - z_far;
+
+        let mut result = Mat4::identity();
+        result[0][0] = 1.0 / x_mag;
+        result[1][1] = 1.0 / y_mag;
+        result[2][2] = 2.0 / z_diff;
+        result[3][2] = (z_near + z_far) / z_diff;
+        result
+    }
 }
 
 impl Index<usize> for Mat4 {
@@ -298,3 +358,46 @@ fn test_math() {
 
     assert_eq!(expected, combined * original);
 }
+
+#[test]
+fn test_rotate() {
+    // A quarter turn around the Z-axis takes the X-axis to the Y-axis.
+    let half = (0.5f32).sqrt();
+    let rotated = Mat4::rotate([0.0, 0.0, half, half]) * Vec4([1.0, 0.0, 0.0, 1.0]);
+
+    for (expected, actual) in [0.0, 1.0, 0.0, 1.0].iter().zip(&rotated.0) {
+        assert!((expected - actual).abs() < 1e-6, "{:?}", rotated);
+    }
+}
+
+#[test]
+fn test_projections() {
+    let assert_projects = |projection: Mat4, point: [f32; 3], expected: [f32; 3]| {
+        let clip = projection * Vec4([point[0], point[1], point[2], 1.0]);
+        for i in 0..3 {
+            assert!((expected[i] - clip[i] / clip[3]).abs() < 1e-4,
+                    "{:?} projects to {:?}, not {:?}", point, clip, expected);
+        }
+    };
+
+    // The near plane maps to -1, and points far away approach the +1 a far plane would have.
+    let infinite = Mat4::perspective_infinite(TAU / 4.0, 2.0, 0.5);
+    assert_projects(infinite, [1.0, 0.5, -0.5], [1.0, 1.0, -1.0]);
+    assert_projects(infinite, [2.0, 1.0, -2.0], [0.5, 0.5, 0.5]);
+    assert_projects(infinite, [0.0, 0.0, -1e5], [0.0, 0.0, 1.0]);
+
+    // Up to the far plane, it matches a projection with the far plane a long way off.
+    let far = Mat4::perspective(TAU / 4.0, 2.0, 0.5, 1e6);
+    for i in 0..4 {
+        for j in 0..4 {
+            assert!((far[i][j] - infinite[i][j]).abs() < 1e-5, "{:?} {:?}", far, infinite);
+        }
+    }
+
+    // The view volume of an orthographic projection maps to the cube from -1 to 1, and sizes
+    // don't depend on the distance.
+    let orthographic = Mat4::orthographic(2.0, 1.0, 0.5, 10.5);
+    assert_projects(orthographic, [2.0, 1.0, -0.5], [1.0, 1.0, -1.0]);
+    assert_projects(orthographic, [-2.0, -1.0, -10.5], [-1.0, -1.0, 1.0]);
+    assert_projects(orthographic, [1.0, 0.5, -5.5], [0.5, 0.5, 0.0]);
+}
@@ -0,0 +1,227 @@
+//! Vertex data uploaded to GL buffers and drawn as a unit.
+
+use context::GlContext;
+use gl;
+use gl::types::*;
+use object::{Buffer, Usage, VertexArray};
+use std::marker::PhantomData;
+use std::mem;
+use std::ptr;
+use vertex::{AttributeBindings, VertexLayout};
+
+/// How the vertices of a mesh are assembled into primitives.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Primitive {
+    Points,
+    Lines,
+    LineStrip,
+    LineLoop,
+    Triangles,
+    TriangleStrip,
+    TriangleFan,
+}
+
+impl Primitive {
+    pub fn gl_enum(self) -> GLenum {
+        match self {
+            Primitive::Points => gl::POINTS,
+            Primitive::Lines => gl::LINES,
+            Primitive::LineStrip => gl::LINE_STRIP,
+            Primitive::LineLoop => gl::LINE_LOOP,
+            Primitive::Triangles => gl::TRIANGLES,
+            Primitive::TriangleStrip => gl::TRIANGLE_STRIP,
+            Primitive::TriangleFan => gl::TRIANGLE_FAN,
+        }
+    }
+}
+
+/// A type that can be stored in an element buffer.
+pub trait Index: Copy {
+    const GL_TYPE: GLenum;
+}
+
+impl Index for u16 {
+    const GL_TYPE: GLenum = gl::UNSIGNED_SHORT;
+}
+
+impl Index for u32 {
+    const GL_TYPE: GLenum = gl::UNSIGNED_INT;
+}
+
+/// The element buffer of an indexed mesh.
+struct Indices<'a> {
+    buffer: Buffer<'a>,
+    gl_type: GLenum,
+    count: usize,
+}
+
+/// A vertex buffer of `V`s, optionally with an element buffer, and the vertex array recording
+/// how a program's attributes read them.
+pub struct Mesh<'a, V> {
+    vao: VertexArray<'a>,
+    vertices: Buffer<'a>,
+    vertex_count: usize,
+    indices: Option<Indices<'a>>,
+    attributes: AttributeBindings,
+    primitive: Primitive,
+    _vertex: PhantomData<V>,
+}
+
+impl<'a, V: VertexLayout + Copy> Mesh<'a, V> {
+    /// Upload `vertices` to be drawn in order. `attributes` must have been matched against `V`,
+    /// as by `AttributeBindings::new::<V>`.
+    pub fn new(context: &'a GlContext, attributes: AttributeBindings, vertices: &[V],
+               usage: Usage, primitive: Primitive) -> Mesh<'a, V> {
+        let vao = VertexArray::new(context);
+        vao.bind();
+
+        let vertex_buffer = Buffer::new(context, gl::ARRAY_BUFFER);
+        vertex_buffer.data(vertices, usage);
+        attributes.enable();
+
+        Mesh {
+            vao,
+            vertices: vertex_buffer,
+            vertex_count: vertices.len(),
+            indices: None,
+            attributes,
+            primitive,
+            _vertex: PhantomData,
+        }
+    }
+
+    /// Upload `vertices` to be drawn in the order given by `indices`.
+    pub fn indexed<I: Index>(context: &'a GlContext, attributes: AttributeBindings,
+                             vertices: &[V], indices: &[I], usage: Usage, primitive: Primitive)
+                             -> Mesh<'a, V> {
+        let mut mesh = Mesh::new(context, attributes, vertices, usage, primitive);
+
+        // The element buffer binding is recorded in the vertex array, which is still bound.
+        let buffer = Buffer::new(context, gl::ELEMENT_ARRAY_BUFFER);
+        buffer.data(indices, usage);
+        mesh.indices = Some(Indices { buffer, gl_type: I::GL_TYPE, count: indices.len() });
+        mesh
+    }
+
+    /// Point the vertex array at the attribute locations of another program, as after reloading
+    /// shaders.
+    pub fn set_attributes(&mut self, attributes: AttributeBindings) {
+        self.vao.bind();
+        self.attributes.disable();
+        self.vertices.bind();
+        attributes.enable();
+        self.attributes = attributes;
+    }
+
+    /// Overwrite the vertices starting at index `start` with `vertices`.
+    ///
+    /// Panics if that would write past the end of the mesh's vertices.
+    pub fn update_vertices(&self, start: usize, vertices: &[V]) {
+        assert!(start + vertices.len() <= self.vertex_count,
+                "updating vertices {}..{} of a mesh with {}",
+                start, start + vertices.len(), self.vertex_count);
+        self.vertices.sub_data(start * mem::size_of::<V>(), vertices);
+    }
+
+    /// Overwrite the indices starting at position `start` with `indices`.
+    ///
+    /// Panics if the mesh isn't indexed, has indices of another type, or if that would write past
+    /// the end of its indices.
+    pub fn update_indices<I: Index>(&self, start: usize, indices: &[I]) {
+        let current = self.indices.as_ref().expect("updating indices of a mesh without any");
+        assert_eq!(current.gl_type, I::GL_TYPE, "updating indices with a different index type");
+        assert!(start + indices.len() <= current.count,
+                "updating indices {}..{} of a mesh with {}",
+                start, start + indices.len(), current.count);
+
+        // Binding the element buffer changes the binding of whatever vertex array is bound.
+        self.vao.bind();
+        current.buffer.sub_data(start * mem::size_of::<I>(), indices);
+    }
+
+    pub fn primitive(&self) -> Primitive {
+        self.primitive
+    }
+
+    /// Draw the whole mesh with the bound program.
+    pub fn draw(&self) {
+        self.vao.bind();
+        unsafe {
+            match self.indices {
+                Some(ref indices) => gl_check!(gl::DrawElements(self.primitive.gl_enum(),
+                                                                indices.count as GLsizei,
+                                                                indices.gl_type, ptr::null())),
+                None => gl_check!(gl::DrawArrays(self.primitive.gl_enum(), 0,
+                                                 self.vertex_count as GLsizei)),
+            }
+        }
+    }
+}
+
+#[test]
+fn test_mesh() {
+    use headless::{Framebuffer, HeadlessContext};
+    use screenshot;
+    use shader::Program;
+
+    #[derive(Copy, Clone, VertexLayout)]
+    #[repr(C, packed)]
+    struct TestVertex {
+        position: [f32; 2],
+        color: [f32; 3],
+    }
+
+    let vertex_source = "#version 150\n\
+                         in vec2 position;\n\
+                         in vec3 color;\n\
+                         out vec3 Color;\n\
+                         void main() {\n\
+                             Color = color;\n\
+                             gl_Position = vec4(position, 0.0, 1.0);\n\
+                         }\n";
+    let fragment_source = "#version 150\n\
+                           in vec3 Color;\n\
+                           out vec4 out_color;\n\
+                           void main() { out_color = vec4(Color, 1.0); }\n";
+
+    let context = match HeadlessContext::for_test(2, 2) {
+        Some(context) => context,
+        None => return,
+    };
+    let framebuffer = unsafe { Framebuffer::new(2, 2).unwrap() };
+    unsafe { framebuffer.bind(); }
+
+    let program = Program::new(context.gl(), vertex_source, fragment_source, &["out_color"])
+        .unwrap();
+    program.bind();
+
+    // One point at the center of each pixel, so that there are no edges to rasterize.
+    let red = [1.0, 0.0, 0.0];
+    let vertices = [
+        TestVertex { position: [-0.5, -0.5], color: red },
+        TestVertex { position: [0.5, -0.5], color: red },
+        TestVertex { position: [0.5, 0.5], color: red },
+        TestVertex { position: [-0.5, 0.5], color: red },
+    ];
+    let attributes = AttributeBindings::new::<TestVertex>(&program).unwrap();
+    let mesh = Mesh::indexed(context.gl(), attributes, &vertices, &[0u16, 1, 1],
+                             Usage::Dynamic, Primitive::Points);
+    let read = || unsafe {
+        gl::Clear(gl::COLOR_BUFFER_BIT);
+        mesh.draw();
+        screenshot::read_pixels(2, 2)
+    };
+    assert_eq!(vec![0, 0, 0, 0, 0, 0,
+                    255, 0, 0, 255, 0, 0], read());
+
+    // Swap the bottom right point for the top two, and turn those green.
+    let green = [0.0, 1.0, 0.0];
+    mesh.update_indices(1, &[2u16, 3]);
+    mesh.update_vertices(2, &[TestVertex { position: [0.5, 0.5], color: green },
+                              TestVertex { position: [-0.5, 0.5], color: green }]);
+    assert_eq!(vec![0, 255, 0, 0, 255, 0,
+                    255, 0, 0, 0, 0, 0], read());
+
+    drop(mesh);
+    unsafe { framebuffer.delete(); }
+}
@@ -0,0 +1,227 @@
+//! Models loaded from files: meshes of a common vertex type, each drawn with its own material.
+
+use assets::Assets;
+use context::GlContext;
+use gl;
+use math::{Mat4, Vec3};
+use mesh::{Mesh, Primitive};
+use object::{Texture2d, Usage};
+use shader::{Program, ReflectionError};
+use std::collections::HashMap;
+use std::mem;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use texture::{self, Image, SamplerDesc, TextureError};
+use uniform::TextureUnit;
+use vertex::AttributeBindings;
+
+/// The vertex type of every model, whatever file format it came from.
+#[derive(Copy, Clone, Debug, PartialEq, VertexLayout)]
+#[repr(C, packed)]
+pub struct ModelVertex {
+    pub position: [f32; 3],
+    pub normal: [f32; 3],
+    pub texcoord: [f32; 2],
+}
+
+impl ModelVertex {
+    /// The bits of every component, so that identical vertices can be found with a hash map.
+    fn bits(&self) -> [u32; 8] {
+        let (p, n, t) = (self.position, self.normal, self.texcoord);
+        [p[0].to_bits(), p[1].to_bits(), p[2].to_bits(), n[0].to_bits(), n[1].to_bits(),
+         n[2].to_bits(), t[0].to_bits(), t[1].to_bits()]
+    }
+}
+
+/// Vertices and the indices of the triangles made from them, with each distinct vertex stored
+/// only once.
+#[derive(Clone, Debug, Default)]
+pub struct IndexedVertices {
+    pub vertices: Vec<ModelVertex>,
+    pub indices: Vec<u32>,
+    lookup: HashMap<[u32; 8], u32>,
+}
+
+impl IndexedVertices {
+    pub fn new() -> IndexedVertices {
+        IndexedVertices::default()
+    }
+
+    /// Append the index of `vertex`, adding the vertex if it isn't there yet.
+    pub fn push(&mut self, vertex: ModelVertex) {
+        let vertices = &mut self.vertices;
+        let index = *self.lookup.entry(vertex.bits()).or_insert_with(|| {
+            vertices.push(vertex);
+            vertices.len() as u32 - 1
+        });
+        self.indices.push(index);
+    }
+}
+
+/// The unit normal of the triangle `abc`, facing the side it is counter-clockwise from, or zero
+/// if the triangle is degenerate. For formats that leave normals out, which mean flat shading.
+pub fn plane_normal(a: [f32; 3], b: [f32; 3], c: [f32; 3]) -> [f32; 3] {
+    let (a, b, c) = (Vec3(a), Vec3(b), Vec3(c));
+    let mut normal = (b - a).cross(c - a);
+    if normal.length_squared() > 0.0 {
+        normal.normalize();
+    }
+    normal.0
+}
+
+/// The surface properties of a part of a model. Texture paths are relative to the asset root.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Material {
+    pub name: String,
+    pub diffuse_color: [f32; 3],
+    pub diffuse_texture: Option<PathBuf>,
+}
+
+impl Default for Material {
+    fn default() -> Material {
+        Material { name: String::new(), diffuse_color: [1.0; 3], diffuse_texture: None }
+    }
+}
+
+/// Check that a program has the uniforms `Model::draw` sets, so that setting them can't fail, and
+/// match its attributes to the vertices of models.
+pub fn check_program(program: &Program) -> Result<AttributeBindings, ReflectionError> {
+    program.uniform("trans", gl::FLOAT_MAT4)?;
+    program.uniform("model", gl::FLOAT_MAT4)?;
+    program.uniform("diffuse_color", gl::FLOAT_VEC3)?;
+    program.uniform("diffuse_texture", gl::SAMPLER_2D)?;
+    AttributeBindings::new::<ModelVertex>(program)
+}
+
+/// A mesh of a model together with what it's drawn with.
+pub struct ModelPart<'a> {
+    pub mesh: Mesh<'a, ModelVertex>,
+    pub diffuse_color: [f32; 3],
+    pub diffuse_texture: Option<Rc<Texture2d<'a>>>,
+}
+
+impl<'a> ModelPart<'a> {
+    /// Upload the triangles of a part.
+    pub fn new(context: &'a GlContext, attributes: &AttributeBindings, part: &IndexedVertices,
+               diffuse_color: [f32; 3], diffuse_texture: Option<Rc<Texture2d<'a>>>)
+               -> ModelPart<'a> {
+        ModelPart {
+            mesh: Mesh::indexed(context, attributes.clone(), &part.vertices, &part.indices,
+                                Usage::Static, Primitive::Triangles),
+            diffuse_color,
+            diffuse_texture,
+        }
+    }
+
+    /// Draw the part with `program`, which must be bound. A part without a texture is drawn with
+    /// `blank` in its place.
+    pub fn draw(&self, program: &Program, blank: &Texture2d) {
+        program.set("diffuse_color", Vec3(self.diffuse_color))
+            .expect("checked by check_program");
+        program.set("diffuse_texture", TextureUnit(0)).expect("checked by check_program");
+        self.diffuse_texture.as_ref().map_or(blank, |texture| &**texture).bind(0);
+        self.mesh.draw();
+    }
+}
+
+/// A model uploaded to GL.
+pub struct Model<'a> {
+    pub parts: Vec<ModelPart<'a>>,
+}
+
+impl<'a> Model<'a> {
+    /// Upload the triangles of each part and load the textures of their materials. The textures
+    /// are flipped, because model formats put texture coordinate 0 at the bottom of the image.
+    pub fn new(assets: &Assets<'a>, attributes: &AttributeBindings,
+               parts: &[(IndexedVertices, Material)]) -> Result<Model<'a>, TextureError> {
+        let sampler = SamplerDesc::default();
+
+        let parts = parts.iter().map(|(part, material)| {
+            let diffuse_texture = match material.diffuse_texture {
+                Some(ref path) => Some(assets.texture(path, &sampler, true)?),
+                None => None,
+            };
+            Ok(ModelPart::new(assets.context(), attributes, part, material.diffuse_color,
+                              diffuse_texture))
+        }).collect::<Result<_, _>>()?;
+
+        Ok(Model { parts })
+    }
+
+    /// Upload a model read by `DecodedModel::decode`. Each texture is uploaded once, however
+    /// many parts use it.
+    pub fn upload(context: &'a GlContext, attributes: &AttributeBindings, decoded: DecodedModel)
+                  -> Result<Model<'a>, TextureError> {
+        let sampler = SamplerDesc::default();
+        let textures = decoded.images.iter().map(|(path, image)| {
+            Ok((path, Rc::new(texture::upload(context, image, &sampler)?)))
+        }).collect::<Result<HashMap<_, _>, TextureError>>()?;
+
+        let parts = decoded.parts.iter().map(|(part, material)| {
+            let texture = material.diffuse_texture.as_ref().map(|path| textures[path].clone());
+            ModelPart::new(context, attributes, part, material.diffuse_color, texture)
+        }).collect();
+
+        Ok(Model { parts })
+    }
+
+    /// Draw every part with `program`, which must have been checked by `check_program`, placed in
+    /// the world by `world` and seen through `view_projection`.
+    pub fn draw(&self, program: &Program, view_projection: Mat4, world: Mat4, blank: &Texture2d) {
+        program.bind();
+        program.set("trans", view_projection * world).expect("checked by check_program");
+        program.set("model", world).expect("checked by check_program");
+        for part in &self.parts {
+            part.draw(program, blank);
+        }
+    }
+}
+
+/// The parts of a model along with the decoded images of their textures, which can be read on
+/// another thread ahead of uploading them with `Model::upload`.
+pub struct DecodedModel {
+    parts: Vec<(IndexedVertices, Material)>,
+    images: HashMap<PathBuf, Image>,
+}
+
+impl DecodedModel {
+    /// Read the textures of `parts`, whose paths are relative to `root`. The images are flipped,
+    /// like those loaded by `Model::new`.
+    pub fn decode(root: &Path, parts: Vec<(IndexedVertices, Material)>)
+                  -> Result<DecodedModel, TextureError> {
+        let mut images = HashMap::new();
+        for (_, material) in &parts {
+            if let Some(ref path) = material.diffuse_texture {
+                if !images.contains_key(path) {
+                    let mut image = Image::read(root.join(path))?;
+                    image.flip_vertically();
+                    images.insert(path.clone(), image);
+                }
+            }
+        }
+
+        Ok(DecodedModel { parts, images })
+    }
+
+    /// The number of bytes uploading the model will transfer.
+    pub fn bytes(&self) -> usize {
+        let meshes = self.parts.iter().map(|(part, _)| {
+            part.vertices.len() * mem::size_of::<ModelVertex>() + part.indices.len() * 4
+        });
+        meshes.chain(self.images.values().map(|image| image.pixels.len())).sum()
+    }
+}
+
+#[test]
+fn test_indexed_vertices() {
+    let vertex = |x| ModelVertex { position: [x, 0.0, 0.0], normal: [0.0, 0.0, 1.0],
+                                   texcoord: [0.0, 0.0] };
+
+    let mut indexed = IndexedVertices::new();
+    for &x in &[0.0, 1.0, 0.0, 2.0, 1.0] {
+        indexed.push(vertex(x));
+    }
+
+    assert_eq!(vec![vertex(0.0), vertex(1.0), vertex(2.0)], indexed.vertices);
+    assert_eq!(vec![0, 1, 0, 2, 1], indexed.indices);
+}
@@ -0,0 +1,291 @@
+//! Reading Wavefront OBJ models and their MTL material libraries.
+//!
+//! Only the geometry and the diffuse color and texture of materials are read. Polygons are
+//! triangulated as fans, which is right for the convex polygons exporters write. Faces without
+//! normals get the normal of their plane.
+
+use assets::Assets;
+use model::{self, IndexedVertices, Material, Model, ModelVertex};
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use texture::TextureError;
+use vertex::AttributeBindings;
+
+/// An error from loading an OBJ model.
+#[derive(Debug)]
+pub enum ObjError {
+    Io(PathBuf, io::Error),
+
+    /// A statement in an OBJ or MTL file that couldn't be parsed, with the file and line it is
+    /// on.
+    Parse { path: PathBuf, line: usize, message: String },
+
+    Texture(TextureError),
+}
+
+impl fmt::Display for ObjError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ObjError::Io(ref path, ref e) => write!(f, "failed to read {}: {}", path.display(), e),
+            ObjError::Parse { ref path, line, ref message } =>
+                write!(f, "{}:{}: {}", path.display(), line, message),
+            ObjError::Texture(ref e) => e.fmt(f),
+        }
+    }
+}
+
+impl Error for ObjError {}
+
+impl From<TextureError> for ObjError {
+    fn from(e: TextureError) -> ObjError {
+        ObjError::Texture(e)
+    }
+}
+
+/// Load the OBJ model at `path`, relative to the asset root, along with its textures.
+pub fn load<'a>(assets: &Assets<'a>, path: &Path, attributes: &AttributeBindings)
+                -> Result<Model<'a>, ObjError> {
+    // Reading through the asset root keeps the texture paths relative to it.
+    let parts = read_with(path, &mut |path| fs::read_to_string(assets.resolve(path)))?;
+    Ok(Model::new(assets, attributes, &parts)?)
+}
+
+/// Read the OBJ file at `path` and the material libraries it uses. Returns the parts of the model,
+/// one for each run of faces with the same material.
+pub fn read(path: &Path) -> Result<Vec<(IndexedVertices, Material)>, ObjError> {
+    read_with(path, &mut |path| fs::read_to_string(path))
+}
+
+/// Like `read`, but reading files with the given function.
+pub fn read_with<F>(path: &Path, read: &mut F)
+                    -> Result<Vec<(IndexedVertices, Material)>, ObjError>
+    where F: FnMut(&Path) -> io::Result<String>
+{
+    let source = read(path).map_err(|e| ObjError::Io(path.to_owned(), e))?;
+    let dir = path.parent().unwrap_or_else(|| Path::new(""));
+
+    let mut positions = Vec::new();
+    let mut texcoords = Vec::new();
+    let mut normals = Vec::new();
+    let mut materials = Vec::new();
+    let mut parts = vec![(IndexedVertices::new(), None)];
+
+    for (i, line) in source.lines().enumerate() {
+        let error = |message: String| {
+            ObjError::Parse { path: path.to_owned(), line: i + 1, message }
+        };
+
+        let mut words = line.split('#').next().unwrap().split_whitespace();
+        match words.next() {
+            Some("v") => positions.push(parse_floats(words, 3).map_err(error)?),
+            Some("vt") => texcoords.push(parse_floats(words, 1).map_err(error)?),
+            Some("vn") => normals.push(parse_floats(words, 3).map_err(error)?),
+            Some("f") => {
+                let part = &mut parts.last_mut().unwrap().0;
+                let corners = words.map(|word| corner(word, &positions, &texcoords, &normals))
+                    .collect::<Result<Vec<_>, _>>().map_err(&error)?;
+                add_face(part, &corners).map_err(error)?;
+            }
+            Some("usemtl") => {
+                let name = words.next().map(str::to_string);
+                if parts.last().unwrap().0.indices.is_empty() {
+                    parts.last_mut().unwrap().1 = name;
+                } else {
+                    parts.push((IndexedVertices::new(), name));
+                }
+            }
+            Some("mtllib") => {
+                for name in words {
+                    materials.extend(read_mtl(&dir.join(name), read)?);
+                }
+            }
+            // Objects, groups, smoothing groups and anything else don't affect the geometry.
+            _ => {}
+        }
+    }
+
+    // Materials that aren't in any library are drawn with the default properties.
+    Ok(parts.into_iter().filter(|part| !part.0.indices.is_empty()).map(|(part, name)| {
+        let material = name.map(|name| {
+            materials.iter().find(|material| material.name == name).cloned()
+                .unwrap_or(Material { name, ..Material::default() })
+        });
+        (part, material.unwrap_or_default())
+    }).collect())
+}
+
+/// Read the materials in the MTL file at `path`.
+fn read_mtl<F>(path: &Path, read: &mut F) -> Result<Vec<Material>, ObjError>
+    where F: FnMut(&Path) -> io::Result<String>
+{
+    let source = read(path).map_err(|e| ObjError::Io(path.to_owned(), e))?;
+    let dir = path.parent().unwrap_or_else(|| Path::new(""));
+    let mut materials: Vec<Material> = Vec::new();
+
+    for (i, line) in source.lines().enumerate() {
+        let error = |message: String| {
+            ObjError::Parse { path: path.to_owned(), line: i + 1, message }
+        };
+
+        let mut words = line.split('#').next().unwrap().split_whitespace();
+        let statement = match words.next() {
+            Some(statement) => statement,
+            None => continue,
+        };
+
+        if statement == "newmtl" {
+            let name = words.next()
+                .ok_or_else(|| error("expected a material name".to_string()))?;
+            materials.push(Material { name: name.to_string(), ..Material::default() });
+            continue;
+        }
+
+        let material = match materials.last_mut() {
+            Some(material) => material,
+            None => return Err(error(format!("`{}` before any `newmtl`", statement))),
+        };
+        match statement {
+            "Kd" => material.diffuse_color = parse_floats(words, 3).map_err(error)?,
+            "map_Kd" => {
+                // The file name comes after any options. Exporters on Windows write backslashes.
+                let name = words.last()
+                    .ok_or_else(|| error("expected a file name".to_string()))?;
+                material.diffuse_texture = Some(dir.join(name.replace('\\', "/")));
+            }
+            _ => {}
+        }
+    }
+
+    Ok(materials)
+}
+
+/// Parse up to `N` numbers, of which at least `required` must be present. Missing ones are zero.
+fn parse_floats<'a, I, const N: usize>(words: I, required: usize) -> Result<[f32; N], String>
+    where I: Iterator<Item = &'a str>
+{
+    let mut values = [0.0; N];
+    let mut count = 0;
+
+    for (value, word) in values.iter_mut().zip(words) {
+        *value = word.parse().map_err(|_| format!("invalid number `{}`", word))?;
+        count += 1;
+    }
+
+    if count < required {
+        return Err(format!("expected at least {} numbers", required));
+    }
+    Ok(values)
+}
+
+/// The position, texture coordinate and normal of a face corner. The normal is `None` if the
+/// corner doesn't have one.
+type Corner = ([f32; 3], [f32; 2], Option<[f32; 3]>);
+
+/// Look up a face corner written like `1/2/3`, `1//3`, `1/2` or `1`. An empty index, as in
+/// `1/2/`, is the same as a missing one.
+fn corner(word: &str, positions: &[[f32; 3]], texcoords: &[[f32; 2]], normals: &[[f32; 3]])
+          -> Result<Corner, String> {
+    let mut indices = word.split('/');
+    let position = positions[index(indices.next().unwrap(), positions.len())?];
+
+    let texcoord = match indices.next() {
+        Some(index_word) if !index_word.is_empty() =>
+            texcoords[index(index_word, texcoords.len())?],
+        _ => [0.0, 0.0],
+    };
+
+    let normal = match indices.next() {
+        Some(index_word) if !index_word.is_empty() =>
+            Some(normals[index(index_word, normals.len())?]),
+        _ => None,
+    };
+
+    Ok((position, texcoord, normal))
+}
+
+/// Turn a 1-based index, or a negative one counting back from the end, into a 0-based index.
+fn index(word: &str, count: usize) -> Result<usize, String> {
+    let index: i64 = word.parse().map_err(|_| format!("invalid index `{}`", word))?;
+    let resolved = if index < 0 { count as i64 + index } else { index - 1 };
+
+    if index == 0 || resolved < 0 || resolved >= count as i64 {
+        return Err(format!("index {} out of range", index));
+    }
+    Ok(resolved as usize)
+}
+
+/// Add the triangles of a convex polygon.
+fn add_face(part: &mut IndexedVertices, corners: &[Corner]) -> Result<(), String> {
+    if corners.len() < 3 {
+        return Err("a face needs at least 3 corners".to_string());
+    }
+
+    let plane_normal = || model::plane_normal(corners[0].0, corners[1].0, corners[2].0);
+
+    let vertices = corners.iter().map(|&(position, texcoord, normal)| {
+        ModelVertex { position, normal: normal.unwrap_or_else(plane_normal), texcoord }
+    }).collect::<Vec<_>>();
+
+    for i in 1..vertices.len() - 1 {
+        part.push(vertices[0]);
+        part.push(vertices[i]);
+        part.push(vertices[i + 1]);
+    }
+    Ok(())
+}
+
+#[test]
+fn test_read_obj() {
+    use preprocess::read_from;
+
+    let files = [
+        ("models/box.obj", "mtllib materials/box.mtl\n\
+                            v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\n\
+                            vt 0 0\nvt 1 0\nvt 1 1\nvt 0 1\n\
+                            vn 0 0 1\n\
+                            o front # a comment\n\
+                            usemtl red\n\
+                            f 1/1/1 2/2/1 3/3/1 4/4/1\n\
+                            usemtl unknown\n\
+                            f -4 -3 -1\n\
+                            f 1/1/ 2/2/ 4/4/\n"),
+        ("models/materials/box.mtl", "newmtl red\nKd 1 0 0\nmap_Kd -bm 1 textures\\red.png\n"),
+    ];
+    let parts = read_with(Path::new("models/box.obj"), &mut read_from(&files)).unwrap();
+    assert_eq!(2, parts.len());
+
+    // The quad is split into two triangles sharing two vertices.
+    let (ref quad, ref red) = parts[0];
+    assert_eq!(4, quad.vertices.len());
+    assert_eq!(vec![0, 1, 2, 0, 2, 3], quad.indices);
+    assert_eq!(ModelVertex { position: [1.0, 1.0, 0.0], normal: [0.0, 0.0, 1.0],
+                             texcoord: [1.0, 1.0] }, quad.vertices[2]);
+    assert_eq!(Material {
+        name: "red".to_string(),
+        diffuse_color: [1.0, 0.0, 0.0],
+        diffuse_texture: Some(PathBuf::from("models/materials/textures/red.png")),
+    }, *red);
+
+    // Without normals or texture coordinates in the file, the triangle gets the normal of its
+    // plane and texture coordinate 0. An empty normal index is the same as none, so the second
+    // triangle shares its first vertex with the first one.
+    let (ref triangles, ref unknown) = parts[1];
+    assert_eq!(vec![[0.0, 0.0, 1.0]; 5],
+               triangles.vertices.iter().map(|vertex| vertex.normal).collect::<Vec<_>>());
+    assert_eq!([0.0, 1.0, 0.0], { triangles.vertices[2].position });
+    assert_eq!([0.0, 0.0], { triangles.vertices[2].texcoord });
+    assert_eq!([0.0, 1.0], { triangles.vertices[4].texcoord });
+    assert_eq!(Material { name: "unknown".to_string(), ..Material::default() }, *unknown);
+
+    let files = [("bad.obj", "v 0 0 0\nv 1 0 0\nf 1 2 3\n")];
+    let error = read_with(Path::new("bad.obj"), &mut read_from(&files)).unwrap_err();
+    match error {
+        ObjError::Parse { line, message, .. } => {
+            assert_eq!((3, "index 3 out of range"), (line, &*message));
+        }
+        e => panic!("{}", e),
+    }
+}
@@ -0,0 +1,165 @@
+//! Owning wrappers for GL buffers, vertex arrays, textures and queries, deleted when dropped.
+
+use context::GlContext;
+use gl;
+use gl::types::*;
+use std::marker::PhantomData;
+use std::mem;
+
+/// How often a buffer's contents are expected to change, as a hint to the driver.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Usage {
+    /// Written once and drawn many times.
+    Static,
+    /// Rewritten now and then, and drawn many times in between.
+    Dynamic,
+    /// Rewritten for about every draw.
+    Stream,
+}
+
+impl Usage {
+    pub fn gl_enum(self) -> GLenum {
+        match self {
+            Usage::Static => gl::STATIC_DRAW,
+            Usage::Dynamic => gl::DYNAMIC_DRAW,
+            Usage::Stream => gl::STREAM_DRAW,
+        }
+    }
+}
+
+/// A buffer object, always bound to the same target.
+pub struct Buffer<'a> {
+    id: GLuint,
+    target: GLenum,
+    _context: PhantomData<&'a GlContext>,
+}
+
+impl<'a> Buffer<'a> {
+    /// Create an empty buffer for the given target, like `gl::ARRAY_BUFFER`.
+    pub fn new(_context: &'a GlContext, target: GLenum) -> Buffer<'a> {
+        let mut id = 0;
+        unsafe { gl::GenBuffers(1, &mut id); }
+        Buffer { id, target, _context: PhantomData }
+    }
+
+    pub fn id(&self) -> GLuint {
+        self.id
+    }
+
+    pub fn target(&self) -> GLenum {
+        self.target
+    }
+
+    pub fn bind(&self) {
+        unsafe { gl::BindBuffer(self.target, self.id); }
+    }
+
+    /// Bind the buffer and replace its contents with a copy of `data`.
+    pub fn data<T: Copy>(&self, data: &[T], usage: Usage) {
+        self.bind();
+        unsafe {
+            gl::BufferData(self.target, mem::size_of_val(data), data.as_ptr() as *const (),
+                           usage.gl_enum());
+        }
+    }
+
+    /// Bind the buffer and overwrite part of its contents, starting `offset` bytes in, with a copy
+    /// of `data`. The buffer keeps its size, so the range must lie within it.
+    pub fn sub_data<T: Copy>(&self, offset: usize, data: &[T]) {
+        self.bind();
+        unsafe {
+            gl::BufferSubData(self.target, offset as GLintptr, mem::size_of_val(data),
+                              data.as_ptr() as *const ());
+        }
+    }
+}
+
+impl<'a> Drop for Buffer<'a> {
+    fn drop(&mut self) {
+        unsafe { gl::DeleteBuffers(1, &self.id); }
+    }
+}
+
+/// A vertex array object, which records the attribute layout and element buffer used to draw.
+pub struct VertexArray<'a> {
+    id: GLuint,
+    _context: PhantomData<&'a GlContext>,
+}
+
+impl<'a> VertexArray<'a> {
+    pub fn new(_context: &'a GlContext) -> VertexArray<'a> {
+        let mut id = 0;
+        unsafe { gl::GenVertexArrays(1, &mut id); }
+        VertexArray { id, _context: PhantomData }
+    }
+
+    pub fn id(&self) -> GLuint {
+        self.id
+    }
+
+    pub fn bind(&self) {
+        unsafe { gl::BindVertexArray(self.id); }
+    }
+}
+
+impl<'a> Drop for VertexArray<'a> {
+    fn drop(&mut self) {
+        unsafe { gl::DeleteVertexArrays(1, &self.id); }
+    }
+}
+
+/// A two-dimensional texture.
+pub struct Texture2d<'a> {
+    id: GLuint,
+    _context: PhantomData<&'a GlContext>,
+}
+
+impl<'a> Texture2d<'a> {
+    pub fn new(_context: &'a GlContext) -> Texture2d<'a> {
+        let mut id = 0;
+        unsafe { gl::GenTextures(1, &mut id); }
+        Texture2d { id, _context: PhantomData }
+    }
+
+    pub fn id(&self) -> GLuint {
+        self.id
+    }
+
+    /// Make `unit` the active texture unit and bind the texture to it.
+    pub fn bind(&self, unit: GLuint) {
+        unsafe {
+            gl::ActiveTexture(gl::TEXTURE0 + unit);
+            gl::BindTexture(gl::TEXTURE_2D, self.id);
+        }
+    }
+}
+
+impl<'a> Drop for Texture2d<'a> {
+    fn drop(&mut self) {
+        unsafe { gl::DeleteTextures(1, &self.id); }
+    }
+}
+
+/// A query object, which measures something about the commands between beginning and ending it.
+pub struct Query<'a> {
+    id: GLuint,
+    _context: PhantomData<&'a GlContext>,
+}
+
+impl<'a> Query<'a> {
+    pub fn new(_context: &'a GlContext) -> Query<'a> {
+        let mut id = 0;
+        unsafe { gl::GenQueries(1, &mut id); }
+        Query { id, _context: PhantomData }
+    }
+
+    pub fn id(&self) -> GLuint {
+        self.id
+    }
+}
+
+impl<'a> Drop for Query<'a> {
+    fn drop(&mut self) {
+        unsafe { gl::DeleteQueries(1, &self.id); }
+    }
+}
@@ -0,0 +1,213 @@
+use assets;
+use debug::Severity;
+use reload;
+use render_state::{CullFace, RenderState};
+use std::env;
+use std::path::PathBuf;
+
+pub const USAGE: &str = "\
+Usage: gl-test [options]
+
+Options:
+    --headless            Render offscreen without opening a window.
+    --software            Render offscreen with the CPU rasterizer instead of OpenGL.
+    --frames N            Number of frames to render offscreen (default 1).
+    --time SECONDS        Simulated time of the first offscreen frame (default 0).
+    --time-step SECONDS   Simulated time between offscreen frames (default 1/60).
+    --size WxH            Size of the offscreen framebuffer (default 800x600).
+    --screenshot SECONDS  Save the frame drawn at the given simulated time.
+    --output PATH         Where to save the `--screenshot` frame (default screenshot.png).
+    --shader-dir DIR      Where to load the shaders from (default the `shaders` directory).
+    --asset-dir DIR       Where to load images from (default the `assets` directory).
+    --scene PATH          Draw the OBJ model or glTF scene at PATH in the asset directory instead
+                          of the cube. Not with `--software`.
+    --cull-face FACES     Which faces to cull: back, front or none (default back).
+    --no-depth-test       Draw every fragment, whatever is in front of it.
+    --update-rate HZ      Simulation updates per second in the window (default 60).
+    --stats               Show the frame timing graph, and print frame timings on exit.
+    --bindings PATH       Load the key and mouse bindings of the window from a file.
+    --record PATH         Record the window's events and frame times to a file.
+    --replay PATH         Draw the frames of a recording instead of following live input.
+    --gl-debug SEVERITY   Report GL errors, and driver messages of at least the given severity:
+                          high, medium, low or notification.
+";
+
+/// Command-line options.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Options {
+    pub headless: bool,
+    pub software: bool,
+    pub frames: u32,
+    pub time: f32,
+    pub time_step: f32,
+    pub width: u32,
+    pub height: u32,
+    pub screenshot: Option<f32>,
+    pub output: String,
+    pub shader_dir: PathBuf,
+    pub asset_dir: PathBuf,
+    pub scene: Option<PathBuf>,
+    pub render_state: RenderState,
+    pub update_rate: f64,
+    pub stats: bool,
+    pub bindings: Option<PathBuf>,
+    pub record: Option<PathBuf>,
+    pub replay: Option<PathBuf>,
+    pub gl_debug: Option<Severity>,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            headless: false,
+            software: false,
+            frames: 1,
+            time: 0.0,
+            time_step: 1.0 / 60.0,
+            width: 800,
+            height: 600,
+            screenshot: None,
+            output: "screenshot.png".to_string(),
+            shader_dir: reload::default_shader_dir(),
+            asset_dir: assets::default_asset_dir(),
+            scene: None,
+            render_state: RenderState::default(),
+            update_rate: 60.0,
+            stats: false,
+            bindings: None,
+            record: None,
+            replay: None,
+            gl_debug: None,
+        }
+    }
+}
+
+impl Options {
+    /// Parse the options given to this process.
+    pub fn from_env() -> Result<Options, String> {
+        Options::parse(env::args().skip(1))
+    }
+
+    /// Parse options from a list of arguments, not including the program name.
+    pub fn parse<I: Iterator<Item = String>>(mut args: I) -> Result<Options, String> {
+        let mut options = Options::default();
+
+        while let Some(arg) = args.next() {
+            match &arg[..] {
+                "--headless" => options.headless = true,
+                "--software" => options.software = true,
+                "--frames" => options.frames = parse_value(&arg, args.next())?,
+                "--time" => options.time = parse_value(&arg, args.next())?,
+                "--time-step" => options.time_step = parse_value(&arg, args.next())?,
+                "--screenshot" => options.screenshot = Some(parse_value(&arg, args.next())?),
+                "--output" => options.output = parse_value(&arg, args.next())?,
+                "--shader-dir" => options.shader_dir = parse_value(&arg, args.next())?,
+                "--asset-dir" => options.asset_dir = parse_value(&arg, args.next())?,
+                "--scene" => options.scene = Some(parse_value(&arg, args.next())?),
+                "--cull-face" => {
+                    options.render_state.cull_face = parse_cull_face(&arg, args.next())?;
+                }
+                "--no-depth-test" => options.render_state.depth_test = None,
+                "--update-rate" => {
+                    options.update_rate = parse_value(&arg, args.next())?;
+                    if options.update_rate.is_nan() || options.update_rate <= 0.0 {
+                        return Err(format!("Option `{}` must be positive.", arg));
+                    }
+                }
+                "--stats" => options.stats = true,
+                "--bindings" => options.bindings = Some(parse_value(&arg, args.next())?),
+                "--record" => options.record = Some(parse_value(&arg, args.next())?),
+                "--replay" => options.replay = Some(parse_value(&arg, args.next())?),
+                "--gl-debug" => options.gl_debug = Some(parse_value(&arg, args.next())?),
+                "--size" => {
+                    let (width, height) = parse_size(&arg, args.next())?;
+                    options.width = width;
+                    options.height = height;
+                }
+                _ => return Err(format!("Unrecognized option `{}`.", arg)),
+            }
+        }
+
+        // The software rasterizer only implements the cube's pipeline.
+        if options.software && options.scene.is_some() {
+            return Err("Option `--scene` can't be used with `--software`.".to_string());
+        }
+
+        Ok(options)
+    }
+}
+
+fn parse_value<T: ::std::str::FromStr>(option: &str, value: Option<String>) -> Result<T, String> {
+    match value {
+        Some(value) => value.parse().map_err(|_| {
+            format!("Invalid value `{}` for option `{}`.", value, option)
+        }),
+        None => Err(format!("Option `{}` requires a value.", option)),
+    }
+}
+
+fn parse_size(option: &str, value: Option<String>) -> Result<(u32, u32), String> {
+    let value = match value {
+        Some(value) => value,
+        None => return Err(format!("Option `{}` requires a value.", option)),
+    };
+
+    let mut parts = value.splitn(2, 'x');
+    let width = parts.next().and_then(|w| w.parse().ok());
+    let height = parts.next().and_then(|h| h.parse().ok());
+
+    match (width, height) {
+        (Some(width), Some(height)) if width > 0 && height > 0 => Ok((width, height)),
+        _ => Err(format!("Invalid size `{}` for option `{}`; expected WIDTHxHEIGHT.",
+                         value, option)),
+    }
+}
+
+fn parse_cull_face(option: &str, value: Option<String>) -> Result<Option<CullFace>, String> {
+    let value: String = parse_value(option, value)?;
+
+    match &value[..] {
+        "back" => Ok(Some(CullFace::Back)),
+        "front" => Ok(Some(CullFace::Front)),
+        "none" => Ok(None),
+        _ => Err(format!("Invalid value `{}` for option `{}`; expected back, front or none.",
+                         value, option)),
+    }
+}
+
+#[test]
+fn test_parse_options() {
+    let args = ["--headless", "--frames", "3", "--time", "1.5", "--size", "64x32",
+                "--shader-dir", "glsl", "--asset-dir", "data", "--scene", "models/box.obj",
+                "--cull-face", "none",
+                "--no-depth-test", "--bindings", "keys.txt", "--record", "session.txt",
+                "--update-rate", "30", "--stats", "--gl-debug", "medium"];
+    let options = Options::parse(args.iter().map(|s| s.to_string())).unwrap();
+
+    assert_eq!(Options {
+        headless: true,
+        frames: 3,
+        time: 1.5,
+        width: 64,
+        height: 32,
+        shader_dir: PathBuf::from("glsl"),
+        asset_dir: PathBuf::from("data"),
+        scene: Some(PathBuf::from("models/box.obj")),
+        render_state: RenderState { depth_test: None, cull_face: None, ..RenderState::default() },
+        bindings: Some(PathBuf::from("keys.txt")),
+        record: Some(PathBuf::from("session.txt")),
+        update_rate: 30.0,
+        stats: true,
+        gl_debug: Some(Severity::Medium),
+        ..Options::default()
+    }, options);
+
+    assert!(Options::parse(vec!["--size".to_string(), "64".to_string()].into_iter()).is_err());
+    assert!(Options::parse(vec!["--frames".to_string()].into_iter()).is_err());
+    assert!(Options::parse(vec!["--update-rate".to_string(), "0".to_string()].into_iter())
+        .is_err());
+    assert!(Options::parse(vec!["--gl-debug".to_string(), "loud".to_string()].into_iter())
+        .is_err());
+    let args = ["--software", "--scene", "models/box.obj"];
+    assert!(Options::parse(args.iter().map(|s| s.to_string())).is_err());
+}
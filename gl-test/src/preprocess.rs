@@ -0,0 +1,312 @@
+//! A small GLSL preprocessor run before sources are handed to the driver.
+//!
+//! It only does what the driver's own preprocessor can't: `#include "file"` directives are
+//! replaced by the contents of the file (relative to the including file), and `#define`s given
+//! from Rust are inserted after the `#version` line. Everything else, including `#ifdef`s on the
+//! injected defines, is left to the driver. Since the driver only ever sees the combined source,
+//! every line of it remembers which file and line it came from so info log messages can be traced
+//! back.
+//!
+//! Directives inside `/* */` comments are ignored, but conditionals aren't evaluated here, so an
+//! `#include` inside an `#if 0` block is still read and spliced in. A file containing
+//! `#pragma once` is only spliced in the first time it is included.
+
+use std::error::Error;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+
+/// A preprocessed shader source.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Preprocessed {
+    pub source: String,
+
+    /// Every file that was read, starting with the one that was preprocessed.
+    pub files: Vec<PathBuf>,
+
+    /// The index into `files` and the 1-based line number each line of `source` came from, or
+    /// `None` for the injected defines.
+    origins: Vec<Option<(usize, usize)>>,
+}
+
+impl Preprocessed {
+    /// The file and line that the given 1-based line of `source` came from.
+    pub fn origin(&self, line: usize) -> Option<(&Path, usize)> {
+        match self.origins.get(line.wrapping_sub(1)) {
+            Some(&Some((file, line))) => Some((&self.files[file], line)),
+            _ => None,
+        }
+    }
+}
+
+/// An error from preprocessing a shader source.
+#[derive(Debug)]
+pub enum PreprocessError {
+    Io(PathBuf, io::Error),
+
+    /// An `#include` directive that couldn't be parsed, with the file and line it is on.
+    BadInclude(PathBuf, usize),
+
+    /// A chain of includes that leads back to a file already being included. The first and last
+    /// paths are the same.
+    IncludeCycle(Vec<PathBuf>),
+}
+
+impl fmt::Display for PreprocessError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            PreprocessError::Io(ref path, ref e) =>
+                write!(f, "failed to read {}: {}", path.display(), e),
+            PreprocessError::BadInclude(ref path, line) =>
+                write!(f, "{}:{}: expected `#include \"FILE\"`", path.display(), line),
+            PreprocessError::IncludeCycle(ref chain) => {
+                write!(f, "include cycle: ")?;
+                for (i, path) in chain.iter().enumerate() {
+                    if i > 0 { write!(f, " -> ")?; }
+                    write!(f, "{}", path.display())?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl Error for PreprocessError {}
+
+/// Preprocess the shader source in the file at `path`.
+pub fn preprocess(path: &Path, defines: &[(String, String)])
+                  -> Result<Preprocessed, PreprocessError> {
+    preprocess_with(path, defines, &mut |path| {
+        let mut source = String::new();
+        File::open(path).and_then(|mut file| file.read_to_string(&mut source))?;
+        Ok(source)
+    })
+}
+
+/// Like `preprocess`, but reading files with the given function.
+pub fn preprocess_with<F>(path: &Path, defines: &[(String, String)], read: &mut F)
+                          -> Result<Preprocessed, PreprocessError>
+    where F: FnMut(&Path) -> io::Result<String>
+{
+    let mut preprocessed = Preprocessed {
+        source: String::new(),
+        files: Vec::new(),
+        origins: Vec::new(),
+    };
+    let mut stack = Vec::new();
+    let mut once = Vec::new();
+    include(path, defines, read, &mut stack, &mut once, &mut preprocessed)?;
+    Ok(preprocessed)
+}
+
+/// Append the preprocessed contents of a file. `stack` holds the files currently being included,
+/// outermost first, and `once` the files that have been included with `#pragma once`. Defines
+/// are only injected into the outermost file.
+fn include<F>(path: &Path, defines: &[(String, String)], read: &mut F, stack: &mut Vec<PathBuf>,
+              once: &mut Vec<PathBuf>, out: &mut Preprocessed) -> Result<(), PreprocessError>
+    where F: FnMut(&Path) -> io::Result<String>
+{
+    // Compare canonical paths so that `a.glsl` and `lib/../a.glsl` are recognised as the same
+    // file. Paths that don't exist are caught by `read` below.
+    let canonical = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    if stack.contains(&canonical) {
+        let mut chain: Vec<_> = stack.iter()
+            .skip_while(|p| **p != canonical)
+            .cloned()
+            .collect();
+        chain.push(canonical);
+        return Err(PreprocessError::IncludeCycle(chain));
+    }
+    if once.contains(&canonical) {
+        return Ok(());
+    }
+
+    let text = read(path).map_err(|e| PreprocessError::Io(path.to_path_buf(), e))?;
+    let file = out.files.len();
+    out.files.push(path.to_path_buf());
+    stack.push(canonical.clone());
+
+    let directives = directives(&text);
+
+    // The defines go after `#version`, which must come before anything else but comments, or
+    // at the very top if there isn't one.
+    let mut pending_defines = stack.len() == 1 && !defines.is_empty();
+    if pending_defines && !directives.contains(&Some("version")) {
+        push_defines(defines, out);
+        pending_defines = false;
+    }
+
+    for ((i, line), directive) in text.lines().enumerate().zip(directives) {
+        match directive {
+            Some("include") => {
+                let name = include_name(line)
+                    .ok_or_else(|| PreprocessError::BadInclude(path.to_path_buf(), i + 1))?;
+                let dir = path.parent().unwrap_or_else(|| Path::new(""));
+                include(&dir.join(name), defines, read, stack, once, out)?;
+            }
+            Some("pragma") if is_pragma_once(line) => {
+                if !once.contains(&canonical) {
+                    once.push(canonical.clone());
+                }
+            }
+            Some("version") if pending_defines => {
+                push_line(line, Some((file, i + 1)), out);
+                push_defines(defines, out);
+                pending_defines = false;
+            }
+            _ => push_line(line, Some((file, i + 1)), out),
+        }
+    }
+
+    stack.pop();
+    Ok(())
+}
+
+fn push_line(line: &str, origin: Option<(usize, usize)>, out: &mut Preprocessed) {
+    out.source.push_str(line);
+    out.source.push('\n');
+    out.origins.push(origin);
+}
+
+fn push_defines(defines: &[(String, String)], out: &mut Preprocessed) {
+    for (name, value) in defines {
+        push_line(&format!("#define {} {}", name, value), None, out);
+    }
+}
+
+/// The name of the preprocessor directive on a line, if it has one.
+fn directive(line: &str) -> Option<&str> {
+    let rest = line.trim_start().strip_prefix('#')?.trim_start();
+    let end = rest.find(|c: char| !c.is_alphanumeric() && c != '_').unwrap_or(rest.len());
+    Some(&rest[..end])
+}
+
+/// The directive on each line of `text`, or `None` for lines that start inside a `/* */` comment.
+fn directives(text: &str) -> Vec<Option<&str>> {
+    let mut in_comment = false;
+    text.lines()
+        .map(|line| {
+            let found = if in_comment { None } else { directive(line) };
+            in_comment = ends_in_comment(line, in_comment);
+            found
+        })
+        .collect()
+}
+
+/// Whether a line that starts inside a `/* */` comment or not ends inside one.
+fn ends_in_comment(line: &str, mut in_comment: bool) -> bool {
+    let mut rest = line;
+    loop {
+        if in_comment {
+            match rest.find("*/") {
+                Some(end) => { rest = &rest[end + 2..]; in_comment = false; }
+                None => return true,
+            }
+        } else {
+            match (rest.find("/*"), rest.find("//")) {
+                (Some(start), None) => { rest = &rest[start + 2..]; in_comment = true; }
+                (Some(start), Some(line_comment)) if start < line_comment => {
+                    rest = &rest[start + 2..];
+                    in_comment = true;
+                }
+                _ => return false,
+            }
+        }
+    }
+}
+
+/// The quoted file name in an `#include "file"` line, which may end with a comment.
+fn include_name(line: &str) -> Option<&str> {
+    let rest = line.trim().strip_prefix('#')?.trim_start().strip_prefix("include")?.trim_start();
+    let rest = rest.strip_prefix('"')?;
+    let end = rest.find('"')?;
+    let (name, after) = (&rest[..end], rest[end + 1..].trim());
+    let comment = after.starts_with("//") || after.starts_with("/*");
+    if name.is_empty() || !(after.is_empty() || comment) { None } else { Some(name) }
+}
+
+fn is_pragma_once(line: &str) -> bool {
+    line.trim().strip_prefix('#')
+        .and_then(|rest| rest.trim_start().strip_prefix("pragma"))
+        .and_then(|rest| rest.split_whitespace().next()) == Some("once")
+}
+
+#[cfg(test)]
+pub fn read_from<'a>(files: &'a [(&'a str, &'a str)])
+                     -> impl FnMut(&Path) -> io::Result<String> + 'a {
+    move |path| {
+        files.iter().find(|&&(name, _)| Path::new(name) == path)
+            .map(|&(_, text)| text.to_string())
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no such file"))
+    }
+}
+
+#[test]
+fn test_preprocess() {
+    let files = [
+        ("shaders/main.frag", "#version 150\n#include \"lib/light.glsl\"\nvoid main() {}\n"),
+        ("shaders/lib/light.glsl", "float light() {\n    return LEVEL;\n}\n"),
+    ];
+    let defines = [("LEVEL".to_string(), "0.5".to_string())];
+    let preprocessed = preprocess_with(Path::new("shaders/main.frag"), &defines,
+                                       &mut read_from(&files)).unwrap();
+
+    assert_eq!("#version 150\n#define LEVEL 0.5\nfloat light() {\n    return LEVEL;\n}\n\
+                void main() {}\n", preprocessed.source);
+    assert_eq!(vec![PathBuf::from("shaders/main.frag"), PathBuf::from("shaders/lib/light.glsl")],
+               preprocessed.files);
+    assert_eq!(Some((Path::new("shaders/main.frag"), 1)), preprocessed.origin(1));
+    assert_eq!(None, preprocessed.origin(2));
+    assert_eq!(Some((Path::new("shaders/lib/light.glsl"), 2)), preprocessed.origin(4));
+    assert_eq!(Some((Path::new("shaders/main.frag"), 3)), preprocessed.origin(6));
+}
+
+#[test]
+fn test_include_cycle() {
+    let files = [
+        ("main.frag", "#include \"a.glsl\"\n"),
+        ("a.glsl", "#include \"b.glsl\"\n"),
+        ("b.glsl", "#include \"a.glsl\"\n"),
+    ];
+    let error = preprocess_with(Path::new("main.frag"), &[], &mut read_from(&files)).unwrap_err();
+
+    match error {
+        PreprocessError::IncludeCycle(chain) => assert_eq!(
+            vec![PathBuf::from("a.glsl"), PathBuf::from("b.glsl"), PathBuf::from("a.glsl")],
+            chain),
+        e => panic!("unexpected error: {}", e),
+    }
+}
+
+#[test]
+fn test_include_in_comment() {
+    let files = [
+        ("main.frag", "// #include \"missing.glsl\"\n\
+                       /* Not this one:\n\
+                       #include \"missing.glsl\"\n\
+                       */ #include \"missing.glsl\"\n\
+                       #include \"a.glsl\" // but this one\n"),
+        ("a.glsl", "float a;\n"),
+    ];
+    let preprocessed = preprocess_with(Path::new("main.frag"), &[], &mut read_from(&files))
+        .unwrap_or_else(|e| panic!("{}", e));
+
+    assert_eq!("// #include \"missing.glsl\"\n/* Not this one:\n#include \"missing.glsl\"\n\
+                */ #include \"missing.glsl\"\nfloat a;\n", preprocessed.source);
+}
+
+#[test]
+fn test_pragma_once() {
+    let files = [
+        ("main.frag", "#include \"a.glsl\"\n#include \"b.glsl\"\n#include \"a.glsl\"\n"),
+        ("a.glsl", "#pragma once\nfloat a;\n"),
+        ("b.glsl", "#include \"a.glsl\"\nfloat b;\n"),
+    ];
+    let preprocessed = preprocess_with(Path::new("main.frag"), &[], &mut read_from(&files))
+        .unwrap_or_else(|e| panic!("{}", e));
+
+    assert_eq!("float a;\nfloat b;\n", preprocessed.source);
+    assert_eq!(vec![PathBuf::from("main.frag"), PathBuf::from("a.glsl"), PathBuf::from("b.glsl")],
+               preprocessed.files);
+}
@@ -0,0 +1,452 @@
+//! A software rasterizer implementing the demo's pipeline without OpenGL.
+//!
+//! It follows the GL rules closely enough to serve as a reference for the GL output: vertices are
+//! clipped against the near and far planes, triangles are culled and filled using the top-left
+//! rule with pixel centers at half-integer coordinates, depth is interpolated linearly in window
+//! space and tested against a depth buffer, varyings are interpolated perspective-correctly, and
+//! textures are sampled with `LINEAR_MIPMAP_LINEAR` minification, `LINEAR` magnification and
+//! `REPEAT` wrapping.
+
+use demo::Vertex;
+use math::{Mat4, Vec4};
+use render_state::{CullFace, FrontFace, RenderState};
+
+/// An RGB texture with a full chain of mipmaps.
+pub struct Texture {
+    levels: Vec<Level>,
+}
+
+struct Level {
+    width: usize,
+    height: usize,
+    texels: Vec<[f32; 3]>,
+}
+
+impl Texture {
+    /// Create a texture from tightly packed RGB rows, generating mipmaps by averaging each 2x2
+    /// block of the level above.
+    pub fn new(width: usize, height: usize, rgb: &[u8]) -> Texture {
+        assert_eq!(width * height * 3, rgb.len());
+
+        let texels = rgb.chunks(3).map(|c| {
+            [c[0] as f32 / 255.0, c[1] as f32 / 255.0, c[2] as f32 / 255.0]
+        }).collect();
+        let mut levels = vec![Level { width, height, texels }];
+
+        while levels.last().is_some_and(|l| l.width > 1 || l.height > 1) {
+            let next = {
+                let level = levels.last().unwrap();
+                let width = (level.width / 2).max(1);
+                let height = (level.height / 2).max(1);
+                let mut texels = Vec::with_capacity(width * height);
+
+                for y in 0..height {
+                    for x in 0..width {
+                        let mut sum = [0.0; 3];
+                        for &(dx, dy) in &[(0, 0), (1, 0), (0, 1), (1, 1)] {
+                            let sx = (x * 2 + dx).min(level.width - 1);
+                            let sy = (y * 2 + dy).min(level.height - 1);
+                            let texel = level.texels[sy * level.width + sx];
+                            for i in 0..3 {
+                                sum[i] += texel[i] / 4.0;
+                            }
+                        }
+                        texels.push(sum);
+                    }
+                }
+
+                Level { width, height, texels }
+            };
+            levels.push(next);
+        }
+
+        Texture { levels }
+    }
+
+    /// Sample the texture at the given coordinates with the given level of detail, as computed
+    /// from the screen-space derivatives of the coordinates.
+    pub fn sample(&self, s: f32, t: f32, lod: f32) -> Vec4 {
+        let color = if lod <= 0.0 {
+            self.levels[0].bilinear(s, t)
+        } else {
+            let max_level = (self.levels.len() - 1) as f32;
+            let lod = lod.min(max_level);
+            let lower = lod.floor();
+            let upper = (lower + 1.0).min(max_level);
+            let a = self.levels[lower as usize].bilinear(s, t);
+            let b = self.levels[upper as usize].bilinear(s, t);
+            mix3(a, b, lod - lower)
+        };
+
+        Vec4([color[0], color[1], color[2], 1.0])
+    }
+
+    fn width(&self) -> f32 {
+        self.levels[0].width as f32
+    }
+
+    fn height(&self) -> f32 {
+        self.levels[0].height as f32
+    }
+}
+
+impl Level {
+    fn bilinear(&self, s: f32, t: f32) -> [f32; 3] {
+        let u = s * self.width as f32 - 0.5;
+        let v = t * self.height as f32 - 0.5;
+        let (x0, y0) = (u.floor(), v.floor());
+        let (fx, fy) = (u - x0, v - y0);
+
+        let top = mix3(self.texel(x0, y0), self.texel(x0 + 1.0, y0), fx);
+        let bottom = mix3(self.texel(x0, y0 + 1.0), self.texel(x0 + 1.0, y0 + 1.0), fx);
+        mix3(top, bottom, fy)
+    }
+
+    /// Fetch a texel, wrapping the coordinates with `REPEAT` semantics.
+    fn texel(&self, x: f32, y: f32) -> [f32; 3] {
+        let x = (x as i64).rem_euclid(self.width as i64) as usize;
+        let y = (y as i64).rem_euclid(self.height as i64) as usize;
+        self.texels[y * self.width + x]
+    }
+}
+
+fn mix3(a: [f32; 3], b: [f32; 3], t: f32) -> [f32; 3] {
+    [a[0] + (b[0] - a[0]) * t, a[1] + (b[1] - a[1]) * t, a[2] + (b[2] - a[2]) * t]
+}
+
+/// An RGB color buffer, stored top row first like the images `screenshot::read_pixels` returns,
+/// and a depth buffer in the same order.
+pub struct Framebuffer {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<u8>,
+    pub depth: Vec<f32>,
+}
+
+impl Framebuffer {
+    pub fn new(width: u32, height: u32) -> Framebuffer {
+        let size = width as usize * height as usize;
+        Framebuffer {
+            width,
+            height,
+            pixels: vec![0; size * 3],
+            depth: vec![1.0; size],
+        }
+    }
+
+    /// Fill the whole color buffer with one color and the whole depth buffer with one depth.
+    pub fn clear(&mut self, r: f32, g: f32, b: f32, depth: f32) {
+        let color = [to_unorm8(r), to_unorm8(g), to_unorm8(b)];
+        for pixel in self.pixels.chunks_mut(3) {
+            pixel.copy_from_slice(&color);
+        }
+        for stored in &mut self.depth {
+            *stored = depth.clamp(0.0, 1.0);
+        }
+    }
+
+    /// The index of a pixel given in window coordinates, where the bottom row is `y = 0`.
+    fn index(&self, x: u32, y: u32) -> usize {
+        let row = (self.height - 1 - y) as usize;
+        row * self.width as usize + x as usize
+    }
+
+    fn put(&mut self, x: u32, y: u32, color: Vec4) {
+        let i = self.index(x, y) * 3;
+        self.pixels[i] = to_unorm8(color[0]);
+        self.pixels[i + 1] = to_unorm8(color[1]);
+        self.pixels[i + 2] = to_unorm8(color[2]);
+    }
+}
+
+fn to_unorm8(c: f32) -> u8 {
+    (c.clamp(0.0, 1.0) * 255.0).round() as u8
+}
+
+/// The values of the demo shaders' uniforms.
+pub struct Uniforms<'a> {
+    pub trans: Mat4,
+    pub time: f32,
+    pub tex_kitten: &'a Texture,
+    pub tex_puppy: &'a Texture,
+}
+
+/// The vertex shader's outputs for one vertex.
+#[derive(Copy, Clone, Debug)]
+struct ShadedVertex {
+    position: Vec4,
+    color: [f32; 3],
+    texcoord: [f32; 2],
+}
+
+impl ShadedVertex {
+    fn lerp(a: ShadedVertex, b: ShadedVertex, t: f32) -> ShadedVertex {
+        ShadedVertex {
+            position: a.position + Vec4([
+                (b.position[0] - a.position[0]) * t,
+                (b.position[1] - a.position[1]) * t,
+                (b.position[2] - a.position[2]) * t,
+                (b.position[3] - a.position[3]) * t,
+            ]),
+            color: mix3(a.color, b.color, t),
+            texcoord: [
+                a.texcoord[0] + (b.texcoord[0] - a.texcoord[0]) * t,
+                a.texcoord[1] + (b.texcoord[1] - a.texcoord[1]) * t,
+            ],
+        }
+    }
+}
+
+/// The demo's vertex shader.
+fn shade_vertex(vertex: &Vertex, uniforms: &Uniforms) -> ShadedVertex {
+    // Copy the fields out, since the packed struct can't lend references to them.
+    let (position, color, texcoord) = (vertex.position, vertex.color, vertex.texcoord);
+    ShadedVertex {
+        position: uniforms.trans * Vec4([position[0], position[1], position[2], 1.0]),
+        color,
+        texcoord,
+    }
+}
+
+/// The demo's fragment shader. `lod` gives the texture level of detail for each texture.
+/// `darken` is set for back faces when the shader is built with `DOUBLE_SIDED`.
+fn shade_fragment(color: [f32; 3], texcoord: [f32; 2], lod: [f32; 2], darken: bool,
+                  uniforms: &Uniforms) -> Vec4 {
+    let mix_factor = ((uniforms.time * 3.0).sin() + 1.0) / 2.0;
+    let col_kitten = uniforms.tex_kitten.sample(texcoord[0], texcoord[1], lod[0]);
+    let col_puppy = uniforms.tex_puppy.sample(texcoord[0], texcoord[1], lod[1]);
+    let mixed_texture = mix4(col_kitten, col_puppy, mix_factor);
+    let mut result = mix4(Vec4([color[0], color[1], color[2], 1.0]), mixed_texture, 0.25);
+    if darken {
+        for c in &mut result.0[..3] {
+            *c *= 0.5;
+        }
+    }
+    result
+}
+
+fn mix4(a: Vec4, b: Vec4, t: f32) -> Vec4 {
+    let mut result = Vec4::zero();
+    for i in 0..4 {
+        result[i] = a[i] + (b[i] - a[i]) * t;
+    }
+    result
+}
+
+/// Draw indexed triangles into the framebuffer, like `gl::DrawElements(gl::TRIANGLES, ...)` with
+/// `state` applied.
+pub fn draw_elements(framebuffer: &mut Framebuffer, vertices: &[Vertex], elements: &[u32],
+                     uniforms: &Uniforms, state: &RenderState) {
+    let shaded: Vec<ShadedVertex> = vertices.iter().map(|v| shade_vertex(v, uniforms)).collect();
+
+    for triangle in elements.chunks(3) {
+        let polygon = clip_depth(&[
+            shaded[triangle[0] as usize],
+            shaded[triangle[1] as usize],
+            shaded[triangle[2] as usize],
+        ]);
+
+        // The clipped polygon is convex, so fan it back out into triangles.
+        for i in 1..polygon.len().saturating_sub(1) {
+            rasterize(framebuffer, [polygon[0], polygon[i], polygon[i + 1]], uniforms, state);
+        }
+    }
+}
+
+/// Clip a triangle against the near and far planes (`-w <= z <= w` in clip space). Triangles
+/// crossing the other planes are handled by limiting rasterization to the framebuffer.
+fn clip_depth(triangle: &[ShadedVertex; 3]) -> Vec<ShadedVertex> {
+    let near = clip(triangle, |v| v.position[2] + v.position[3]);
+    clip(&near, |v| v.position[3] - v.position[2])
+}
+
+/// Clip a convex polygon to the side of a plane where `distance` isn't negative.
+fn clip<F>(polygon: &[ShadedVertex], distance: F) -> Vec<ShadedVertex>
+    where F: Fn(&ShadedVertex) -> f32
+{
+    let mut result = Vec::with_capacity(polygon.len() + 1);
+
+    for i in 0..polygon.len() {
+        let a = polygon[i];
+        let b = polygon[(i + 1) % polygon.len()];
+        let (da, db) = (distance(&a), distance(&b));
+
+        if da >= 0.0 {
+            result.push(a);
+        }
+        if (da >= 0.0) != (db >= 0.0) {
+            result.push(ShadedVertex::lerp(a, b, da / (da - db)));
+        }
+    }
+
+    result
+}
+
+fn rasterize(framebuffer: &mut Framebuffer, triangle: [ShadedVertex; 3], uniforms: &Uniforms,
+             state: &RenderState) {
+    let width = framebuffer.width as f32;
+    let height = framebuffer.height as f32;
+
+    // Transform to window coordinates, with the default depth range of 0 to 1.
+    let mut window = [[0.0; 2]; 3];
+    let mut window_z = [0.0; 3];
+    let mut inv_w = [0.0; 3];
+    for i in 0..3 {
+        let p = triangle[i].position;
+        inv_w[i] = 1.0 / p[3];
+        window[i] = [
+            (p[0] * inv_w[i] + 1.0) / 2.0 * width,
+            (p[1] * inv_w[i] + 1.0) / 2.0 * height,
+        ];
+        window_z[i] = (p[2] * inv_w[i] + 1.0) / 2.0;
+    }
+
+    let edge = |a: [f32; 2], b: [f32; 2], x: f32, y: f32| {
+        (b[0] - a[0]) * (y - a[1]) - (b[1] - a[1]) * (x - a[0])
+    };
+
+    let area = edge(window[0], window[1], window[2][0], window[2][1]);
+    if area == 0.0 {
+        return;
+    }
+
+    // Counter-clockwise triangles have a positive area.
+    let front = (area > 0.0) == (state.front_face == FrontFace::CounterClockwise);
+    match state.cull_face {
+        Some(CullFace::FrontAndBack) => return,
+        Some(CullFace::Front) if front => return,
+        Some(CullFace::Back) if !front => return,
+        _ => {}
+    }
+
+    // `demo::shader_files` defines `DOUBLE_SIDED` when nothing is culled.
+    let darken = state.cull_face.is_none() && !front;
+
+    // Flip the edges of clockwise triangles so that points inside always have positive edge
+    // values.
+    let sign = area.signum();
+    let edges = [(1, 2), (2, 0), (0, 1)];
+
+    // An edge exactly through a pixel center only owns it if it is a top or left edge.
+    let mut owns_boundary = [false; 3];
+    for (i, &(a, b)) in edges.iter().enumerate() {
+        let dx = (window[b][0] - window[a][0]) * sign;
+        let dy = (window[b][1] - window[a][1]) * sign;
+        owns_boundary[i] = (dy == 0.0 && dx < 0.0) || dy > 0.0;
+    }
+
+    let min_x = window.iter().fold(width, |m, p| m.min(p[0])).max(0.0) as u32;
+    let min_y = window.iter().fold(height, |m, p| m.min(p[1])).max(0.0) as u32;
+    let max_x = window.iter().fold(0.0, |m: f32, p| m.max(p[0])).min(width - 1.0) as u32;
+    let max_y = window.iter().fold(0.0, |m: f32, p| m.max(p[1])).min(height - 1.0) as u32;
+
+    // Perspective-correct barycentric coordinates of a point in window coordinates.
+    let barycentric = |x: f32, y: f32| {
+        let mut b = [0.0; 3];
+        let mut sum = 0.0;
+        for (i, &(e0, e1)) in edges.iter().enumerate() {
+            b[i] = edge(window[e0], window[e1], x, y) / area * inv_w[i];
+            sum += b[i];
+        }
+        [b[0] / sum, b[1] / sum, b[2] / sum]
+    };
+
+    let texcoord_at = |b: [f32; 3]| {
+        let mut t = [0.0; 2];
+        for i in 0..3 {
+            t[0] += b[i] * triangle[i].texcoord[0];
+            t[1] += b[i] * triangle[i].texcoord[1];
+        }
+        t
+    };
+
+    for y in min_y..max_y + 1 {
+        for x in min_x..max_x + 1 {
+            let (px, py) = (x as f32 + 0.5, y as f32 + 0.5);
+
+            let inside = edges.iter().enumerate().all(|(i, &(e0, e1))| {
+                let e = edge(window[e0], window[e1], px, py) * sign;
+                e > 0.0 || (e == 0.0 && owns_boundary[i])
+            });
+            if !inside {
+                continue;
+            }
+
+            // Depth is affine in window coordinates, so it isn't perspective-corrected.
+            let mut depth = 0.0;
+            for (i, &(e0, e1)) in edges.iter().enumerate() {
+                depth += edge(window[e0], window[e1], px, py) / area * window_z[i];
+            }
+            let depth = depth.clamp(0.0, 1.0);
+            let depth_index = framebuffer.index(x, y);
+            if let Some(func) = state.depth_test {
+                if !func.passes(depth, framebuffer.depth[depth_index]) {
+                    continue;
+                }
+                if state.depth_write {
+                    framebuffer.depth[depth_index] = depth;
+                }
+            }
+
+            let b = barycentric(px, py);
+            let texcoord = texcoord_at(b);
+            let mut color = [0.0; 3];
+            for i in 0..3 {
+                for (c, out) in color.iter_mut().enumerate() {
+                    *out += b[i] * triangle[i].color[c];
+                }
+            }
+
+            // Derive the level of detail from the change in texture coordinates over one pixel.
+            let ddx = texcoord_at(barycentric(px + 1.0, py));
+            let ddy = texcoord_at(barycentric(px, py + 1.0));
+            let lod_for = |texture: &Texture| {
+                let dx = [(ddx[0] - texcoord[0]) * texture.width(),
+                          (ddx[1] - texcoord[1]) * texture.height()];
+                let dy = [(ddy[0] - texcoord[0]) * texture.width(),
+                          (ddy[1] - texcoord[1]) * texture.height()];
+                let rho = (dx[0] * dx[0] + dx[1] * dx[1]).max(dy[0] * dy[0] + dy[1] * dy[1]);
+                rho.sqrt().log2()
+            };
+            let lod = [lod_for(uniforms.tex_kitten), lod_for(uniforms.tex_puppy)];
+
+            let fragment = shade_fragment(color, texcoord, lod, darken, uniforms);
+            framebuffer.put(x, y, fragment);
+        }
+    }
+}
+
+#[test]
+fn test_clip_far() {
+    use math::Mat4;
+    use render_state::CullFace;
+
+    // A triangle covering the framebuffer whose depth grows to the right, crossing the far plane
+    // between the third and the last column. Without a depth test, nothing else hides what's
+    // past it.
+    let vertex = |x: f32, y: f32| Vertex {
+        position: [x, y, x * 1.5 + 0.25],
+        color: [1.0, 1.0, 1.0],
+        texcoord: [0.0, 0.0],
+    };
+    let black = Texture::new(1, 1, &[0, 0, 0]);
+    let uniforms = Uniforms {
+        trans: Mat4::identity(),
+        time: 0.0,
+        tex_kitten: &black,
+        tex_puppy: &black,
+    };
+    let state = RenderState {
+        depth_test: None,
+        cull_face: Some(CullFace::Back),
+        ..RenderState::default()
+    };
+    let mut framebuffer = Framebuffer::new(4, 4);
+    draw_elements(&mut framebuffer, &[vertex(-1.0, -1.0), vertex(3.0, -1.0), vertex(-1.0, 3.0)],
+                  &[0, 1, 2], &uniforms, &state);
+
+    for (i, pixel) in framebuffer.pixels.chunks(3).enumerate() {
+        let expected = if i % 4 < 3 { [191, 191, 191] } else { [0, 0, 0] };
+        assert_eq!(expected, pixel, "pixel {}", i);
+    }
+}
@@ -0,0 +1,258 @@
+//! Loading shader programs from files on disk and noticing when those files change.
+
+use preprocess::{preprocess, Preprocessed, PreprocessError};
+use context::GlContext;
+use gltf::GltfError;
+use shader::{Program, ReflectionError, ShaderError, Stage};
+use std::env;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+use texture::TextureError;
+use time;
+
+/// How often to check the files for changes, in nanoseconds.
+const POLL_INTERVAL_NS: u64 = 250_000_000;
+
+/// The directory to load shaders from when none is given on the command line: the `shaders`
+/// directory next to the binary if there is one, and otherwise the one in the source tree.
+pub fn default_shader_dir() -> PathBuf {
+    let next_to_binary = env::current_exe().ok()
+        .and_then(|exe| exe.parent().map(|dir| dir.join("shaders")));
+
+    match next_to_binary {
+        Some(ref dir) if dir.is_dir() => dir.clone(),
+        _ => PathBuf::from(concat!(env!("CARGO_MANIFEST_DIR"), "/shaders")),
+    }
+}
+
+/// An error from loading a shader program, or the textures and scenes it draws, from disk.
+#[derive(Debug)]
+pub enum LoadError {
+    Preprocess(PreprocessError),
+    Shader(ShaderError),
+
+    /// The program built, but doesn't have a variable the Rust code needs.
+    Reflection(ReflectionError),
+
+    Texture(TextureError),
+    Gltf(GltfError),
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            LoadError::Preprocess(ref e) => e.fmt(f),
+            LoadError::Shader(ref e) => e.fmt(f),
+            LoadError::Reflection(ref e) => e.fmt(f),
+            LoadError::Texture(ref e) => e.fmt(f),
+            LoadError::Gltf(ref e) => e.fmt(f),
+        }
+    }
+}
+
+impl Error for LoadError {}
+
+impl From<PreprocessError> for LoadError {
+    fn from(e: PreprocessError) -> LoadError {
+        LoadError::Preprocess(e)
+    }
+}
+
+impl From<ShaderError> for LoadError {
+    fn from(e: ShaderError) -> LoadError {
+        LoadError::Shader(e)
+    }
+}
+
+impl From<ReflectionError> for LoadError {
+    fn from(e: ReflectionError) -> LoadError {
+        LoadError::Reflection(e)
+    }
+}
+
+impl From<TextureError> for LoadError {
+    fn from(e: TextureError) -> LoadError {
+        LoadError::Texture(e)
+    }
+}
+
+impl From<GltfError> for LoadError {
+    fn from(e: GltfError) -> LoadError {
+        LoadError::Gltf(e)
+    }
+}
+
+/// The preprocessed sources of a program, ready to be built.
+#[derive(Clone, Debug)]
+pub struct ShaderSources {
+    vertex: Preprocessed,
+    fragment: Preprocessed,
+}
+
+/// The vertex and fragment shader files of one program, `NAME.vert` and `NAME.frag`, along with
+/// the files they include.
+pub struct ShaderFiles {
+    vertex: PathBuf,
+    fragment: PathBuf,
+    defines: Vec<(String, String)>,
+
+    /// The files read by the last load and their modification times at that point.
+    watched: Vec<(PathBuf, Option<SystemTime>)>,
+    next_poll: u64,
+}
+
+impl ShaderFiles {
+    pub fn new(dir: &Path, name: &str) -> ShaderFiles {
+        let vertex = dir.join(format!("{}.vert", name));
+        let fragment = dir.join(format!("{}.frag", name));
+        let watched = vec![watch(&vertex), watch(&fragment)];
+
+        ShaderFiles {
+            vertex,
+            fragment,
+            defines: Vec::new(),
+            watched,
+            next_poll: 0,
+        }
+    }
+
+    /// Define a preprocessor macro in both shaders, replacing any earlier definition of `name`.
+    /// Takes effect on the next load.
+    pub fn define(&mut self, name: &str, value: &str) {
+        self.defines.retain(|(n, _)| n != name);
+        self.defines.push((name.to_string(), value.to_string()));
+    }
+
+    /// Read, preprocess, compile and link the shaders. Info log messages in a returned
+    /// `ShaderError` are annotated with the files and lines they refer to.
+    pub fn load<'a>(&mut self, context: &'a GlContext) -> Result<Program<'a>, LoadError> {
+        let sources = self.reader()()?;
+        self.build(context, sources)
+    }
+
+    /// A job reading and preprocessing the shaders as they are now, which can be run on another
+    /// thread so that reloading doesn't wait on the disk. Build the result with `build`.
+    pub fn reader(&self) -> impl FnOnce() -> Result<ShaderSources, PreprocessError> + Send {
+        let (vertex, fragment) = (self.vertex.clone(), self.fragment.clone());
+        let defines = self.defines.clone();
+        move || {
+            Ok(ShaderSources {
+                vertex: preprocess(&vertex, &defines)?,
+                fragment: preprocess(&fragment, &defines)?,
+            })
+        }
+    }
+
+    /// Compile and link sources read by a `reader` job, and watch the files they were read from.
+    pub fn build<'a>(&mut self, context: &'a GlContext, sources: ShaderSources)
+                     -> Result<Program<'a>, LoadError> {
+        let ShaderSources { vertex, fragment } = sources;
+
+        let mut files: Vec<&PathBuf> = vertex.files.iter().chain(&fragment.files).collect();
+        files.sort();
+        files.dedup();
+        self.watched = files.into_iter().map(|path| watch(path)).collect();
+
+        let outputs = ["out_color"];
+        Program::new(context, &vertex.source, &fragment.source, &outputs).map_err(|mut e| {
+            for annotation in &mut e.annotations {
+                let preprocessed = match annotation.stage {
+                    Stage::Vertex => &vertex,
+                    Stage::Fragment => &fragment,
+                };
+                annotation.origin = preprocessed.origin(annotation.line)
+                    .map(|(path, line)| (path.to_path_buf(), line));
+            }
+            LoadError::Shader(e)
+        })
+    }
+
+    /// Whether any of the files has been modified since the last call (or since the last load, or
+    /// since the files were created). The file system is only checked a few times per second, so
+    /// this is cheap enough to call every frame.
+    pub fn changed(&mut self) -> bool {
+        let now = time::precise_time_ns();
+        if now < self.next_poll {
+            return false;
+        }
+        self.next_poll = now + POLL_INTERVAL_NS;
+
+        let current: Vec<_> = self.watched.iter().map(|(path, _)| watch(path)).collect();
+
+        // Editors often replace a file by deleting and recreating it, so wait until the main files
+        // are there again. An include going missing is reported by the reload instead, since the
+        // file may simply no longer be included.
+        let main_missing = current.iter()
+            .any(|&(ref path, modified)| modified.is_none() &&
+                                         (*path == self.vertex || *path == self.fragment));
+        if main_missing || current == self.watched {
+            return false;
+        }
+
+        self.watched = current;
+        true
+    }
+}
+
+fn watch(path: &Path) -> (PathBuf, Option<SystemTime>) {
+    let modified = fs::metadata(path).and_then(|m| m.modified()).ok();
+    (path.to_path_buf(), modified)
+}
+
+#[test]
+fn test_define_survives_reload() {
+    use gl;
+    use headless::HeadlessContext;
+    use std::fs::File;
+    use std::process;
+    use std::time::Duration;
+
+    let dir = env::temp_dir().join(format!("gl-test-reload-{}", process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let vertex = "#version 150\n\
+                  in vec3 position;\n\
+                  void main() { gl_Position = vec4(position, 1.0); }\n";
+    let fragment = |uniform: &str| {
+        format!("#version 150\n\
+                 out vec4 out_color;\n\
+                 #ifdef DOUBLE_SIDED\n\
+                 uniform float {};\n\
+                 #else\n\
+                 const float {0} = 1.0;\n\
+                 #endif\n\
+                 void main() {{ out_color = vec4({0}); }}\n", uniform)
+    };
+    fs::write(dir.join("sided.vert"), vertex).unwrap();
+    fs::write(dir.join("sided.frag"), fragment("front")).unwrap();
+
+    let context = match HeadlessContext::for_test(1, 1) {
+        Some(context) => context,
+        None => return,
+    };
+    let mut shaders = ShaderFiles::new(&dir, "sided");
+    shaders.define("DOUBLE_SIDED", "1");
+
+    // The define comes right after `#version`, and decides which variables the program has.
+    let sources = shaders.reader()().unwrap();
+    assert!(sources.fragment.source.starts_with("#version 150\n#define DOUBLE_SIDED 1\n"));
+    let program = shaders.build(context.gl(), sources).unwrap();
+    assert!(program.uniform("front", gl::FLOAT).is_ok());
+
+    // An edit is picked up, and the reloaded program is built with the define again. The
+    // modification time is moved on explicitly, in case the file system's clock is coarse.
+    fs::write(dir.join("sided.frag"), fragment("back")).unwrap();
+    let file = File::options().write(true).open(dir.join("sided.frag")).unwrap();
+    file.set_modified(SystemTime::now() + Duration::from_secs(10)).unwrap();
+    assert!(shaders.changed());
+
+    let sources = shaders.reader()().unwrap();
+    assert!(sources.fragment.source.contains("#define DOUBLE_SIDED 1\n"));
+    let program = shaders.build(context.gl(), sources).unwrap();
+    assert!(program.uniform("back", gl::FLOAT).is_ok());
+    assert!(program.uniform("front", gl::FLOAT).is_err());
+
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,153 @@
+//! The fixed-function state that decides which fragments reach the framebuffer: the depth test
+//! and face culling.
+
+use gl;
+use gl::types::*;
+
+/// How a fragment's depth is compared against the depth already in the buffer.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum DepthFunc {
+    Never,
+    Less,
+    Equal,
+    LessEqual,
+    Greater,
+    NotEqual,
+    GreaterEqual,
+    Always,
+}
+
+impl DepthFunc {
+    pub fn gl_enum(self) -> GLenum {
+        match self {
+            DepthFunc::Never => gl::NEVER,
+            DepthFunc::Less => gl::LESS,
+            DepthFunc::Equal => gl::EQUAL,
+            DepthFunc::LessEqual => gl::LEQUAL,
+            DepthFunc::Greater => gl::GREATER,
+            DepthFunc::NotEqual => gl::NOTEQUAL,
+            DepthFunc::GreaterEqual => gl::GEQUAL,
+            DepthFunc::Always => gl::ALWAYS,
+        }
+    }
+
+    /// Whether a fragment at `depth` is drawn over one at `stored`.
+    pub fn passes(self, depth: f32, stored: f32) -> bool {
+        match self {
+            DepthFunc::Never => false,
+            DepthFunc::Less => depth < stored,
+            DepthFunc::Equal => depth == stored,
+            DepthFunc::LessEqual => depth <= stored,
+            DepthFunc::Greater => depth > stored,
+            DepthFunc::NotEqual => depth != stored,
+            DepthFunc::GreaterEqual => depth >= stored,
+            DepthFunc::Always => true,
+        }
+    }
+}
+
+/// Which faces of triangles are culled.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum CullFace {
+    Front,
+    Back,
+    FrontAndBack,
+}
+
+impl CullFace {
+    pub fn gl_enum(self) -> GLenum {
+        match self {
+            CullFace::Front => gl::FRONT,
+            CullFace::Back => gl::BACK,
+            CullFace::FrontAndBack => gl::FRONT_AND_BACK,
+        }
+    }
+}
+
+/// The winding, as seen in window coordinates, of triangles that face the viewer.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum FrontFace {
+    CounterClockwise,
+    Clockwise,
+}
+
+impl FrontFace {
+    pub fn gl_enum(self) -> GLenum {
+        match self {
+            FrontFace::CounterClockwise => gl::CCW,
+            FrontFace::Clockwise => gl::CW,
+        }
+    }
+}
+
+/// The depth and culling state to draw with.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct RenderState {
+    /// The depth test, or `None` to draw every fragment without reading or writing the depth
+    /// buffer.
+    pub depth_test: Option<DepthFunc>,
+
+    /// Whether fragments that pass the depth test write their depth to the buffer.
+    pub depth_write: bool,
+
+    /// The depth `clear` fills the depth buffer with.
+    pub clear_depth: f32,
+
+    /// The faces to cull, or `None` to draw both.
+    pub cull_face: Option<CullFace>,
+
+    pub front_face: FrontFace,
+}
+
+impl Default for RenderState {
+    /// Depth testing with `Less` against a buffer cleared to the far plane, and counter-clockwise
+    /// front faces with back faces culled.
+    fn default() -> RenderState {
+        RenderState {
+            depth_test: Some(DepthFunc::Less),
+            depth_write: true,
+            clear_depth: 1.0,
+            cull_face: Some(CullFace::Back),
+            front_face: FrontFace::CounterClockwise,
+        }
+    }
+}
+
+impl RenderState {
+    /// Set the GL state.
+    pub fn apply(&self) {
+        unsafe {
+            match self.depth_test {
+                Some(func) => {
+                    gl::Enable(gl::DEPTH_TEST);
+                    gl_check!(gl::DepthFunc(func.gl_enum()));
+                }
+                None => gl::Disable(gl::DEPTH_TEST),
+            }
+            gl::DepthMask(if self.depth_write { gl::TRUE } else { gl::FALSE });
+
+            match self.cull_face {
+                Some(face) => {
+                    gl::Enable(gl::CULL_FACE);
+                    gl_check!(gl::CullFace(face.gl_enum()));
+                }
+                None => gl::Disable(gl::CULL_FACE),
+            }
+            gl_check!(gl::FrontFace(self.front_face.gl_enum()));
+        }
+    }
+
+    /// Clear the color buffer to `color`, and the depth buffer to `clear_depth`.
+    pub fn clear(&self, color: [f32; 4]) {
+        unsafe {
+            gl::ClearColor(color[0], color[1], color[2], color[3]);
+            gl::ClearDepth(self.clear_depth as GLdouble);
+
+            // The depth mask applies to clearing too, so it has to be on for the clear even if
+            // fragments don't write depth.
+            gl::DepthMask(gl::TRUE);
+            gl::Clear(gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT);
+            gl::DepthMask(if self.depth_write { gl::TRUE } else { gl::FALSE });
+        }
+    }
+}
@@ -0,0 +1,224 @@
+//! Recording the window's events and frame times to a file, and reading them back to replay a
+//! session frame by frame.
+//!
+//! A recording is a text file with a `frame` line giving the time of each frame, in nanoseconds
+//! since the session started, followed by the events handled in that frame:
+//!
+//! ```text
+//! frame 2100000
+//! size 800 600
+//! frame 18770000
+//! press W
+//! cursor 412.5 300
+//! scroll 0 -1
+//! release Mouse1
+//! ```
+//!
+//! Numbers are written so that they read back exactly, so a replay sees the same values as the
+//! recorded session did.
+
+use input::{self, Button};
+use std::error::Error;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// A window event that changes what is drawn.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Event {
+    /// The framebuffer was resized to the given width and height in pixels.
+    FramebufferSize(i32, i32),
+
+    Input(input::Event),
+}
+
+/// The time a frame started and the events handled in it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Frame {
+    /// Nanoseconds since the session started.
+    pub time: u64,
+    pub events: Vec<Event>,
+}
+
+/// An error from reading a recording.
+#[derive(Debug)]
+pub enum ReplayError {
+    Io(PathBuf, io::Error),
+
+    /// A line that couldn't be parsed, with the file and line it is on.
+    Parse { path: PathBuf, line: usize, message: String },
+}
+
+impl fmt::Display for ReplayError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ReplayError::Io(ref path, ref e) =>
+                write!(f, "failed to read {}: {}", path.display(), e),
+            ReplayError::Parse { ref path, line, ref message } =>
+                write!(f, "{}:{}: {}", path.display(), line, message),
+        }
+    }
+}
+
+impl Error for ReplayError {}
+
+/// Writes frames to a recording as they happen.
+pub struct Recorder {
+    out: BufWriter<File>,
+}
+
+impl Recorder {
+    pub fn create(path: &Path) -> io::Result<Recorder> {
+        Ok(Recorder { out: BufWriter::new(File::create(path)?) })
+    }
+
+    /// Add a frame to the recording. The frame is flushed to the file, so that a session that
+    /// crashes is recorded up to the crash.
+    pub fn record(&mut self, frame: &Frame) -> io::Result<()> {
+        write_frame(&mut self.out, frame)?;
+        self.out.flush()
+    }
+}
+
+/// Write a frame in the recording format.
+pub fn write_frame<W: Write>(out: &mut W, frame: &Frame) -> io::Result<()> {
+    use input::Event::*;
+
+    writeln!(out, "frame {}", frame.time)?;
+    for event in &frame.events {
+        match *event {
+            Event::FramebufferSize(width, height) => writeln!(out, "size {} {}", width, height)?,
+            Event::Input(Press(ref button)) => writeln!(out, "press {}", button)?,
+            Event::Input(Release(ref button)) => writeln!(out, "release {}", button)?,
+            Event::Input(CursorMoved(x, y)) => writeln!(out, "cursor {} {}", x, y)?,
+            Event::Input(Scrolled(x, y)) => writeln!(out, "scroll {} {}", x, y)?,
+        }
+    }
+    Ok(())
+}
+
+/// Read the recording at `path`.
+pub fn read(path: &Path) -> Result<Vec<Frame>, ReplayError> {
+    let source = fs::read_to_string(path).map_err(|e| ReplayError::Io(path.to_owned(), e))?;
+    parse(path, &source)
+}
+
+/// Parse the recording in `source`, which was read from `path`.
+pub fn parse(path: &Path, source: &str) -> Result<Vec<Frame>, ReplayError> {
+    let mut frames: Vec<Frame> = Vec::new();
+
+    for (i, line) in source.lines().enumerate() {
+        let error = |message: String| {
+            ReplayError::Parse { path: path.to_owned(), line: i + 1, message }
+        };
+
+        let mut words = line.split_whitespace();
+        let statement = match words.next() {
+            Some(statement) => statement,
+            None => continue,
+        };
+        let mut arguments = words.collect::<Vec<_>>();
+
+        if statement == "frame" {
+            let [time] = parse_numbers(&arguments).map_err(error)?;
+            if frames.last().is_some_and(|last| time < last.time) {
+                return Err(error("frame times must not go backward".to_string()));
+            }
+            frames.push(Frame { time, events: Vec::new() });
+            continue;
+        }
+
+        let frame = match frames.last_mut() {
+            Some(frame) => frame,
+            None => return Err(error(format!("`{}` before any `frame`", statement))),
+        };
+        let event = match statement {
+            "size" => {
+                let [width, height] = parse_numbers(&arguments).map_err(error)?;
+                Event::FramebufferSize(width, height)
+            }
+            "press" | "release" => {
+                let name = match arguments.pop() {
+                    Some(name) if arguments.is_empty() => name,
+                    _ => return Err(error("expected a key or mouse button".to_string())),
+                };
+                let button = Button::named(name)
+                    .ok_or_else(|| error(format!("unknown key or mouse button `{}`", name)))?;
+                Event::Input(if statement == "press" {
+                    input::Event::Press(button)
+                } else {
+                    input::Event::Release(button)
+                })
+            }
+            "cursor" => {
+                let [x, y] = parse_numbers(&arguments).map_err(error)?;
+                Event::Input(input::Event::CursorMoved(x, y))
+            }
+            "scroll" => {
+                let [x, y] = parse_numbers(&arguments).map_err(error)?;
+                Event::Input(input::Event::Scrolled(x, y))
+            }
+            _ => return Err(error(format!("unknown event `{}`", statement))),
+        };
+        frame.events.push(event);
+    }
+
+    Ok(frames)
+}
+
+/// Parse exactly `N` numbers.
+fn parse_numbers<T: FromStr + Default + Copy, const N: usize>(words: &[&str])
+                                                            -> Result<[T; N], String> {
+    if words.len() != N {
+        return Err(format!("expected {} numbers", N));
+    }
+
+    let mut values = [T::default(); N];
+    for (value, word) in values.iter_mut().zip(words) {
+        *value = word.parse().map_err(|_| format!("invalid number `{}`", word))?;
+    }
+    Ok(values)
+}
+
+#[test]
+fn test_recording() {
+    let frames = vec![
+        Frame { time: 0, events: vec![Event::FramebufferSize(1600, 1200)] },
+        Frame { time: 16_670_000, events: vec![] },
+        Frame {
+            time: 33_340_000,
+            events: vec![
+                Event::Input(input::Event::Press(Button::named("LeftShift").unwrap())),
+                Event::Input(input::Event::CursorMoved(0.1 + 0.2, -1e-300)),
+                Event::Input(input::Event::Scrolled(0.0, -1.5)),
+                Event::Input(input::Event::Release(Button::mouse(3))),
+            ],
+        },
+    ];
+
+    // Writing and reading back gives exactly the same frames.
+    let mut written = Vec::new();
+    for frame in &frames {
+        write_frame(&mut written, frame).unwrap();
+    }
+    let source = String::from_utf8(written).unwrap();
+    assert_eq!(frames, parse(Path::new("session.txt"), &source).unwrap());
+
+    let errors = [
+        ("press W", 1, "`press` before any `frame`"),
+        ("frame 10\nframe 5", 2, "frame times must not go backward"),
+        ("frame 0\ncursor 1", 2, "expected 2 numbers"),
+        ("frame 0\n\npress Mouse0", 3, "unknown key or mouse button `Mouse0`"),
+        ("frame 0\nsize 800 tall", 2, "invalid number `tall`"),
+    ];
+    for &(source, expected_line, expected_message) in &errors {
+        match parse(Path::new("session.txt"), source) {
+            Err(ReplayError::Parse { line, message, .. }) => {
+                assert_eq!((expected_line, expected_message), (line, &*message));
+            }
+            result => panic!("expected a parse error for {:?}, got {:?}", source, result),
+        }
+    }
+}
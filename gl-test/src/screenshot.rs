@@ -0,0 +1,53 @@
+use gl;
+use imagefmt::{self, ColFmt, ColType};
+use std::io;
+use std::path::Path;
+
+/// Read the RGB contents of the bound read framebuffer, returning the rows in top-down order.
+///
+/// For the default framebuffer of a double-buffered window this reads the back buffer, so call it
+/// after drawing and before swapping.
+pub unsafe fn read_pixels(width: u32, height: u32) -> Vec<u8> {
+    let mut pixels = vec![0u8; width as usize * height as usize * 3];
+
+    // Rows of RGB pixels are only 4-byte aligned when the width happens to be a multiple of 4.
+    gl::PixelStorei(gl::PACK_ALIGNMENT, 1);
+    gl::ReadPixels(0, 0, width as i32, height as i32, gl::RGB, gl::UNSIGNED_BYTE,
+                   pixels.as_mut_ptr() as *mut _);
+
+    // OpenGL returns the bottom row first, but image files start with the top row.
+    flip_rows(&mut pixels, width as usize * 3);
+    pixels
+}
+
+/// Read the bound read framebuffer and write it to a PNG file.
+pub unsafe fn save<P: AsRef<Path>>(path: P, width: u32, height: u32) -> io::Result<()> {
+    write_png(path, width, height, &read_pixels(width, height))
+}
+
+/// Write top-down RGB rows to a PNG file.
+pub fn write_png<P: AsRef<Path>>(path: P, width: u32, height: u32, pixels: &[u8])
+                                 -> io::Result<()> {
+    imagefmt::write(path, width as usize, height as usize, ColFmt::RGB, pixels, ColType::Color)
+}
+
+/// Reverse the order of the rows in an image buffer in place.
+pub fn flip_rows(pixels: &mut [u8], row_len: usize) {
+    let rows = pixels.len() / row_len;
+
+    for row in 0..rows / 2 {
+        let (top, bottom) = pixels.split_at_mut((rows - row - 1) * row_len);
+        top[row * row_len..(row + 1) * row_len].swap_with_slice(&mut bottom[..row_len]);
+    }
+}
+
+#[test]
+fn test_flip_rows() {
+    let mut pixels = [1, 1, 2, 2, 3, 3];
+    flip_rows(&mut pixels, 2);
+    assert_eq!([3, 3, 2, 2, 1, 1], pixels);
+
+    let mut pixels = [1, 1, 2, 2, 3, 3, 4, 4];
+    flip_rows(&mut pixels, 2);
+    assert_eq!([4, 4, 3, 3, 2, 2, 1, 1], pixels);
+}
@@ -0,0 +1,629 @@
+use context::GlContext;
+use gl;
+use gl::types::*;
+use std::error::Error;
+use std::ffi::CString;
+use std::fmt;
+use std::marker::PhantomData;
+use std::path::PathBuf;
+use std::ptr;
+use uniform::Uniform;
+
+/// A programmable stage of the pipeline.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Stage {
+    Vertex,
+    Fragment,
+}
+
+impl Stage {
+    pub fn gl_enum(self) -> GLenum {
+        match self {
+            Stage::Vertex => gl::VERTEX_SHADER,
+            Stage::Fragment => gl::FRAGMENT_SHADER,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Stage::Vertex => "vertex",
+            Stage::Fragment => "fragment",
+        }
+    }
+}
+
+/// The step of building a program that failed.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Step {
+    Compile(Stage),
+    Link,
+}
+
+/// A line of shader source that an info log message refers to.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Annotation {
+    pub stage: Stage,
+
+    /// The 1-based line number within the stage's source.
+    pub line: usize,
+
+    /// The text of the source line.
+    pub source: String,
+
+    /// The info log message about the line.
+    pub message: String,
+
+    /// The file and line the source line originally came from, if the source was assembled from
+    /// files by the preprocessor.
+    pub origin: Option<(PathBuf, usize)>,
+}
+
+/// An error from compiling or linking a shader program.
+#[derive(Clone, Debug)]
+pub struct ShaderError {
+    pub step: Step,
+
+    /// The info log from the driver, exactly as reported.
+    pub log: String,
+
+    /// The source lines the log refers to, in the order the log mentions them.
+    pub annotations: Vec<Annotation>,
+}
+
+impl fmt::Display for ShaderError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.step {
+            Step::Compile(stage) => write!(f, "{} shader failed to compile", stage.name())?,
+            Step::Link => write!(f, "shader program failed to link")?,
+        }
+
+        writeln!(f, ":")?;
+        for line in self.log.lines() {
+            writeln!(f, "    {}", line)?;
+        }
+
+        for annotation in &self.annotations {
+            match annotation.origin {
+                Some((ref path, line)) =>
+                    writeln!(f, "{}:{}: {}", path.display(), line, annotation.message)?,
+                None => writeln!(f, "{} shader line {}: {}", annotation.stage.name(),
+                                 annotation.line, annotation.message)?,
+            }
+            writeln!(f, "    {}", annotation.source.trim())?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Error for ShaderError {}
+
+/// Whether a program variable is a uniform or a vertex attribute.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum VariableKind {
+    Uniform,
+    Attribute,
+}
+
+impl VariableKind {
+    pub fn name(self) -> &'static str {
+        match self {
+            VariableKind::Uniform => "uniform",
+            VariableKind::Attribute => "attribute",
+        }
+    }
+}
+
+/// An active uniform or vertex attribute of a linked program.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Variable {
+    /// The name, without the `[0]` the driver appends to arrays.
+    pub name: String,
+
+    /// The GL type, such as `gl::FLOAT_VEC3` or `gl::SAMPLER_2D`.
+    pub gl_type: GLenum,
+
+    /// The number of array elements, or 1 if the variable isn't an array.
+    pub size: GLint,
+
+    /// The location, or -1 for uniforms in a uniform block.
+    pub location: GLint,
+}
+
+/// An error from looking up a program variable the Rust code expects to exist.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ReflectionError {
+    /// No active variable has the name. Variables that don't contribute to the output are
+    /// optimized out by the driver, so this can also mean the shader doesn't use it.
+    Missing { kind: VariableKind, name: String },
+
+    WrongType { kind: VariableKind, name: String, expected: GLenum, actual: GLenum },
+
+    /// An array value with more elements than the uniform array has.
+    TooLong { name: String, size: GLint, len: usize },
+
+    /// An attribute the program reads that the vertex type has no field for.
+    NoVertexField { name: String },
+}
+
+impl fmt::Display for ReflectionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ReflectionError::Missing { kind, ref name } =>
+                write!(f, "{} `{}` is not active in the shader program", kind.name(), name),
+            ReflectionError::WrongType { kind, ref name, expected, actual } =>
+                write!(f, "{} `{}` has type {}, but {} was expected", kind.name(), name,
+                       TypeName(actual), TypeName(expected)),
+            ReflectionError::TooLong { ref name, size, len } =>
+                write!(f, "uniform `{}` has {} elements, but {} were given", name, size, len),
+            ReflectionError::NoVertexField { ref name } =>
+                write!(f, "attribute `{}` is read by the shader program, but the vertex type has \
+                           no field for it", name),
+        }
+    }
+}
+
+impl Error for ReflectionError {}
+
+/// Displays a GL type enum as the GLSL type name.
+struct TypeName(GLenum);
+
+impl fmt::Display for TypeName {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self.0 {
+            gl::FLOAT => "float",
+            gl::FLOAT_VEC2 => "vec2",
+            gl::FLOAT_VEC3 => "vec3",
+            gl::FLOAT_VEC4 => "vec4",
+            gl::INT => "int",
+            gl::INT_VEC2 => "ivec2",
+            gl::INT_VEC3 => "ivec3",
+            gl::INT_VEC4 => "ivec4",
+            gl::UNSIGNED_INT => "uint",
+            gl::UNSIGNED_INT_VEC2 => "uvec2",
+            gl::UNSIGNED_INT_VEC3 => "uvec3",
+            gl::UNSIGNED_INT_VEC4 => "uvec4",
+            gl::BOOL => "bool",
+            gl::BOOL_VEC2 => "bvec2",
+            gl::BOOL_VEC3 => "bvec3",
+            gl::BOOL_VEC4 => "bvec4",
+            gl::FLOAT_MAT2 => "mat2",
+            gl::FLOAT_MAT3 => "mat3",
+            gl::FLOAT_MAT4 => "mat4",
+            gl::SAMPLER_1D => "sampler1D",
+            gl::SAMPLER_2D => "sampler2D",
+            gl::SAMPLER_3D => "sampler3D",
+            gl::SAMPLER_CUBE => "samplerCube",
+            gl::SAMPLER_2D_SHADOW => "sampler2DShadow",
+            other => return write!(f, "type 0x{:x}", other),
+        };
+        f.write_str(name)
+    }
+}
+
+/// A linked shader program made of a vertex and a fragment shader.
+pub struct Program<'a> {
+    id: GLuint,
+
+    /// The active uniforms, as reported by the driver after linking.
+    pub uniforms: Vec<Variable>,
+
+    /// The active vertex attributes, not including built-ins like `gl_VertexID`.
+    pub attributes: Vec<Variable>,
+
+    _context: PhantomData<&'a GlContext>,
+}
+
+impl<'a> Program<'a> {
+    /// Compile both shaders and link them into a program. The fragment shader outputs named in
+    /// `outputs` are bound to the draw buffers at their respective indices.
+    pub fn new(_context: &'a GlContext, vertex_source: &str, fragment_source: &str,
+               outputs: &[&str]) -> Result<Program<'a>, ShaderError> {
+        unsafe {
+            let sources = [(Stage::Vertex, vertex_source), (Stage::Fragment, fragment_source)];
+
+            let vertex_shader = compile_shader(Stage::Vertex, vertex_source)?;
+            let fragment_shader = match compile_shader(Stage::Fragment, fragment_source) {
+                Ok(shader) => shader,
+                Err(e) => {
+                    gl::DeleteShader(vertex_shader);
+                    return Err(e);
+                }
+            };
+
+            let program = gl::CreateProgram();
+            gl::AttachShader(program, vertex_shader);
+            gl::AttachShader(program, fragment_shader);
+
+            for (i, output) in outputs.iter().enumerate() {
+                let output = CString::new(*output).unwrap();
+                gl::BindFragDataLocation(program, i as GLuint, output.as_ptr());
+            }
+
+            gl::LinkProgram(program);
+
+            // The shader objects aren't needed once the program is linked (or failed to link).
+            gl::DetachShader(program, vertex_shader);
+            gl::DetachShader(program, fragment_shader);
+            gl::DeleteShader(vertex_shader);
+            gl::DeleteShader(fragment_shader);
+
+            // From here on, dropping `program` deletes it.
+            let mut program = Program {
+                id: program,
+                uniforms: Vec::new(),
+                attributes: Vec::new(),
+                _context: PhantomData,
+            };
+
+            let mut status = gl::FALSE as GLint;
+            gl::GetProgramiv(program.id, gl::LINK_STATUS, &mut status);
+            if status != gl::TRUE as GLint {
+                let log = program_info_log(program.id);
+                return Err(ShaderError {
+                    step: Step::Link,
+                    annotations: annotate(&log, None, &sources),
+                    log,
+                });
+            }
+
+            // The program isn't validated here: validation checks it against the current state,
+            // like the textures bound to its samplers' units, which isn't set up until a draw.
+            program.uniforms = active_variables(program.id, VariableKind::Uniform);
+            program.attributes = active_variables(program.id, VariableKind::Attribute);
+
+            Ok(program)
+        }
+    }
+
+    pub fn id(&self) -> GLuint {
+        self.id
+    }
+
+    /// Look up an active uniform, checking that it has the given GL type.
+    pub fn uniform(&self, name: &str, gl_type: GLenum) -> Result<&Variable, ReflectionError> {
+        find_variable(&self.uniforms, VariableKind::Uniform, name, gl_type)
+    }
+
+    /// Set a uniform of this program, which must be the current program. Fails if there is no
+    /// active uniform with the name or its type doesn't match the value.
+    pub fn set<U: Uniform>(&self, name: &str, value: U) -> Result<(), ReflectionError> {
+        let variable = self.uniforms.iter().find(|v| v.name == name).ok_or_else(|| {
+            ReflectionError::Missing { kind: VariableKind::Uniform, name: name.to_string() }
+        })?;
+
+        if !U::accepts(variable.gl_type) {
+            return Err(ReflectionError::WrongType {
+                kind: VariableKind::Uniform,
+                name: name.to_string(),
+                expected: U::gl_type(),
+                actual: variable.gl_type,
+            });
+        }
+
+        if value.count() > variable.size as usize {
+            return Err(ReflectionError::TooLong {
+                name: name.to_string(),
+                size: variable.size,
+                len: value.count(),
+            });
+        }
+
+        unsafe { gl_check!(value.set(variable.location)); }
+        Ok(())
+    }
+
+    /// Look up an active vertex attribute, checking that it has the given GL type.
+    pub fn attribute(&self, name: &str, gl_type: GLenum) -> Result<&Variable, ReflectionError> {
+        find_variable(&self.attributes, VariableKind::Attribute, name, gl_type)
+    }
+
+    /// Make this the current program.
+    pub fn bind(&self) {
+        unsafe { gl::UseProgram(self.id); }
+    }
+}
+
+impl<'a> Drop for Program<'a> {
+    fn drop(&mut self) {
+        unsafe { gl::DeleteProgram(self.id); }
+    }
+}
+
+unsafe fn compile_shader(stage: Stage, source: &str) -> Result<GLuint, ShaderError> {
+    let shader = gl::CreateShader(stage.gl_enum());
+    let source_ptr = source.as_bytes().as_ptr() as *const GLchar;
+    let source_len = source.len() as i32;
+    gl::ShaderSource(shader, 1, &source_ptr, &source_len);
+    gl::CompileShader(shader);
+
+    let mut status = gl::FALSE as i32;
+    gl::GetShaderiv(shader, gl::COMPILE_STATUS, &mut status);
+
+    if status == gl::TRUE as i32 {
+        Ok(shader)
+    } else {
+        let log = info_log(shader, gl::GetShaderiv, gl::GetShaderInfoLog);
+        gl::DeleteShader(shader);
+
+        Err(ShaderError {
+            step: Step::Compile(stage),
+            annotations: annotate(&log, Some(stage), &[(stage, source)]),
+            log,
+        })
+    }
+}
+
+unsafe fn program_info_log(program: GLuint) -> String {
+    info_log(program, gl::GetProgramiv, gl::GetProgramInfoLog)
+}
+
+unsafe fn info_log(object: GLuint,
+                   get_iv: unsafe fn(GLuint, GLenum, *mut GLint),
+                   get_log: unsafe fn(GLuint, GLsizei, *mut GLsizei, *mut GLchar)) -> String {
+    let mut log_len = 0;
+    get_iv(object, gl::INFO_LOG_LENGTH, &mut log_len);
+    if log_len == 0 { return String::new() }
+
+    let mut buf = vec![0u8; log_len as usize];
+    get_log(object, log_len, ptr::null_mut(), buf.as_mut_ptr() as *mut GLchar);
+    buf.pop(); // Ignore the trailing null.
+
+    String::from_utf8_lossy(&buf).into_owned()
+}
+
+/// Query the active uniforms or attributes of a linked program.
+unsafe fn active_variables(program: GLuint, kind: VariableKind) -> Vec<Variable> {
+    type GetActive = unsafe fn(GLuint, GLuint, GLsizei, *mut GLsizei, *mut GLint, *mut GLenum,
+                               *mut GLchar);
+    type GetLocation = unsafe fn(GLuint, *const GLchar) -> GLint;
+
+    let (count_enum, max_len_enum, get_active, get_location) = match kind {
+        VariableKind::Uniform =>
+            (gl::ACTIVE_UNIFORMS, gl::ACTIVE_UNIFORM_MAX_LENGTH,
+             gl::GetActiveUniform as GetActive, gl::GetUniformLocation as GetLocation),
+        VariableKind::Attribute =>
+            (gl::ACTIVE_ATTRIBUTES, gl::ACTIVE_ATTRIBUTE_MAX_LENGTH,
+             gl::GetActiveAttrib as GetActive, gl::GetAttribLocation as GetLocation),
+    };
+
+    let mut count = 0;
+    let mut max_len = 0;
+    gl::GetProgramiv(program, count_enum, &mut count);
+    gl::GetProgramiv(program, max_len_enum, &mut max_len);
+
+    let mut variables = Vec::new();
+    for index in 0..count as GLuint {
+        let mut buf = vec![0u8; max_len as usize];
+        let mut len = 0;
+        let mut size = 0;
+        let mut gl_type = 0;
+        get_active(program, index, max_len, &mut len, &mut size, &mut gl_type,
+                   buf.as_mut_ptr() as *mut GLchar);
+        buf.truncate(len as usize);
+
+        let name = String::from_utf8_lossy(&buf).into_owned();
+        if name.starts_with("gl_") {
+            continue;
+        }
+
+        let c_name = CString::new(&name[..]).unwrap();
+        variables.push(Variable {
+            location: get_location(program, c_name.as_ptr()),
+            name: name.strip_suffix("[0]").unwrap_or(&name).to_string(),
+            gl_type,
+            size,
+        });
+    }
+
+    variables
+}
+
+fn find_variable<'a>(variables: &'a [Variable], kind: VariableKind, name: &str, gl_type: GLenum)
+                     -> Result<&'a Variable, ReflectionError> {
+    let variable = variables.iter().find(|v| v.name == name).ok_or_else(|| {
+        ReflectionError::Missing { kind, name: name.to_string() }
+    })?;
+
+    if variable.gl_type != gl_type {
+        return Err(ReflectionError::WrongType {
+            kind,
+            name: name.to_string(),
+            expected: gl_type,
+            actual: variable.gl_type,
+        });
+    }
+
+    Ok(variable)
+}
+
+/// Find the source lines an info log refers to.
+///
+/// Compile logs always refer to the stage being compiled. Link logs rarely carry line numbers, so
+/// a message is matched to a stage by name when it has one, and otherwise the identifiers it quotes
+/// are looked up in each stage's declarations.
+pub fn annotate(log: &str, stage: Option<Stage>, sources: &[(Stage, &str)]) -> Vec<Annotation> {
+    let mut annotations = Vec::new();
+
+    for log_line in log.lines() {
+        if let Some((line, message)) = parse_location(log_line) {
+            let stage = stage.or_else(|| {
+                sources.iter().map(|&(s, _)| s).find(|s| log_line.contains(s.name()))
+            });
+
+            if let Some(stage) = stage {
+                let source = sources.iter().find(|&&(s, _)| s == stage).unwrap().1;
+                if let Some(text) = source.lines().nth(line.wrapping_sub(1)) {
+                    annotations.push(Annotation {
+                        stage,
+                        line,
+                        source: text.to_string(),
+                        message: message.to_string(),
+                        origin: None,
+                    });
+                }
+            }
+        } else if stage.is_none() {
+            for identifier in quoted_identifiers(log_line) {
+                for &(stage, source) in sources {
+                    let declaration = source.lines().enumerate().find(|&(_, text)| {
+                        declares(text, identifier)
+                    });
+
+                    if let Some((i, text)) = declaration {
+                        annotations.push(Annotation {
+                            stage,
+                            line: i + 1,
+                            source: text.to_string(),
+                            message: log_line.trim().to_string(),
+                            origin: None,
+                        });
+                    }
+                }
+            }
+        }
+    }
+
+    annotations
+}
+
+/// Parse the source location at the start of an info log line, returning the line number and the
+/// rest of the message. Handles the formats used by the common drivers:
+///
+/// * Mesa: `0:12(5): error: ...`
+/// * NVIDIA: `0(12) : error C0000: ...`
+/// * AMD, Apple and ANGLE: `ERROR: 0:12: ...`
+pub fn parse_location(log_line: &str) -> Option<(usize, &str)> {
+    let mut rest = log_line.trim_start();
+    for prefix in &["ERROR: ", "WARNING: "] {
+        if let Some(stripped) = rest.strip_prefix(prefix) {
+            rest = stripped;
+        }
+    }
+
+    let (_, rest) = take_number(rest)?;
+    let (line, rest) = if let Some(rest) = rest.strip_prefix(':') {
+        take_number(rest)?
+    } else if let Some(rest) = rest.strip_prefix('(') {
+        let (line, rest) = take_number(rest)?;
+        (line, rest.strip_prefix(')')?)
+    } else {
+        return None;
+    };
+
+    // Skip Mesa's column number.
+    let rest = match rest.strip_prefix('(').and_then(take_number) {
+        Some((_, after)) => after.strip_prefix(')').unwrap_or(after),
+        None => rest,
+    };
+
+    let message = rest.trim_start_matches(|c: char| c == ':' || c.is_whitespace());
+    Some((line, message))
+}
+
+fn take_number(s: &str) -> Option<(usize, &str)> {
+    let end = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    s[..end].parse().ok().map(|n| (n, &s[end..]))
+}
+
+/// The identifiers a log line quotes, as in "`Color' not written by vertex shader".
+fn quoted_identifiers(log_line: &str) -> Vec<&str> {
+    log_line.split(['`', '\'', '"']).enumerate()
+        .filter(|&(i, part)| i % 2 == 1 && !part.is_empty() &&
+                             part.chars().all(|c| c.is_alphanumeric() || c == '_'))
+        .map(|(_, part)| part)
+        .collect()
+}
+
+/// Whether a line of GLSL declares a global with the given name.
+fn declares(line: &str, identifier: &str) -> bool {
+    let line = line.trim().trim_end_matches(';');
+    let mut words = line.split_whitespace();
+    let qualified = matches!(words.next(), Some("in") | Some("out") | Some("uniform"));
+    qualified && words.last().is_some_and(|name| name.split('[').next() == Some(identifier))
+}
+
+#[test]
+fn test_parse_location() {
+    assert_eq!(Some((12, "error: syntax error")), parse_location("0:12(5): error: syntax error"));
+    assert_eq!(Some((3, "error C0000: oops")), parse_location("0(3) : error C0000: oops"));
+    assert_eq!(Some((7, "'x' : undeclared")), parse_location("ERROR: 0:7: 'x' : undeclared"));
+    assert_eq!(None, parse_location("error: linking failed"));
+}
+
+#[test]
+fn test_annotate_link_log() {
+    let vertex = "#version 150\nout vec2 Color;\n";
+    let fragment = "#version 150\nin vec3 Color;\nout vec4 out_color;\n";
+    let sources = [(Stage::Vertex, vertex), (Stage::Fragment, fragment)];
+    let log = "error: vertex shader output `Color' declared as type `vec2', \
+               but fragment shader input declared as type `vec3'";
+
+    assert_eq!(vec![
+        Annotation {
+            stage: Stage::Vertex,
+            line: 2,
+            source: "out vec2 Color;".to_string(),
+            message: log.to_string(),
+            origin: None,
+        },
+        Annotation {
+            stage: Stage::Fragment,
+            line: 2,
+            source: "in vec3 Color;".to_string(),
+            message: log.to_string(),
+            origin: None,
+        },
+    ], annotate(log, None, &sources));
+}
+
+#[test]
+fn test_link_failure() {
+    use headless::HeadlessContext;
+
+    let context = match HeadlessContext::for_test(1, 1) {
+        Some(context) => context,
+        None => return,
+    };
+    let vertex = "#version 150\nout vec2 Color;\n\
+                  void main() { Color = vec2(0.0); gl_Position = vec4(0.0); }\n";
+    let fragment = "#version 150\nin vec3 Color;\nout vec4 out_color;\n\
+                    void main() { out_color = vec4(Color, 1.0); }\n";
+
+    let error = Program::new(context.gl(), vertex, fragment, &["out_color"]).err().unwrap();
+    assert_eq!(Step::Link, error.step);
+    assert_eq!(2, error.annotations.len());
+}
+
+#[test]
+fn test_reflection() {
+    use headless::HeadlessContext;
+
+    let context = match HeadlessContext::for_test(1, 1) {
+        Some(context) => context,
+        None => return,
+    };
+    let vertex = "#version 150\nin vec2 position;\nuniform mat4 trans;\n\
+                  void main() { gl_Position = trans * vec4(position, 0.0, 1.0); }\n";
+    let fragment = "#version 150\nuniform float weights[3];\nout vec4 out_color;\n\
+                    void main() { out_color = vec4(weights[0], weights[1], weights[2], 1.0); }\n";
+
+    let program = Program::new(context.gl(), vertex, fragment, &["out_color"]).unwrap();
+
+    let weights = program.uniform("weights", gl::FLOAT).unwrap();
+    assert_eq!(3, weights.size);
+    assert!(weights.location >= 0);
+    assert!(program.attribute("position", gl::FLOAT_VEC2).is_ok());
+
+    assert_eq!(Err(ReflectionError::WrongType {
+        kind: VariableKind::Uniform,
+        name: "trans".to_string(),
+        expected: gl::FLOAT_MAT3,
+        actual: gl::FLOAT_MAT4,
+    }), program.uniform("trans", gl::FLOAT_MAT3));
+
+    assert_eq!(Err(ReflectionError::Missing {
+        kind: VariableKind::Attribute,
+        name: "color".to_string(),
+    }), program.attribute("color", gl::FLOAT_VEC3));
+}
@@ -0,0 +1,354 @@
+//! Frame timing statistics: how long each frame spent on the CPU, on the GPU and in swapping
+//! buffers, over a rolling window of recent frames, and a graph of them drawn over the scene.
+
+use assets::Assets;
+use context::GlContext;
+use gl;
+use mesh::{Mesh, Primitive};
+use object::{Query, Usage};
+use reload::LoadError;
+use render_state::RenderState;
+use shader::Program;
+use std::collections::VecDeque;
+use std::fmt;
+use std::rc::Rc;
+use vertex::AttributeBindings;
+
+/// The number of timer queries in flight. GPU times are read a few frames late, once the GPU has
+/// caught up, so that reading them doesn't stall.
+const TIMER_QUERIES: usize = 4;
+
+/// The most recent values of a measurement, in seconds.
+#[derive(Clone, Debug)]
+pub struct Series {
+    values: VecDeque<f64>,
+    capacity: usize,
+}
+
+/// The distribution of the values in a `Series`.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Summary {
+    pub min: f64,
+    pub avg: f64,
+    pub p99: f64,
+    pub max: f64,
+}
+
+impl fmt::Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "min {:.2} ms, avg {:.2} ms, p99 {:.2} ms, max {:.2} ms",
+               self.min * 1e3, self.avg * 1e3, self.p99 * 1e3, self.max * 1e3)
+    }
+}
+
+impl Series {
+    /// Keep the last `capacity` values.
+    pub fn new(capacity: usize) -> Series {
+        Series { values: VecDeque::with_capacity(capacity), capacity }
+    }
+
+    pub fn push(&mut self, value: f64) {
+        if self.values.len() == self.capacity {
+            self.values.pop_front();
+        }
+        self.values.push_back(value);
+    }
+
+    /// The values, oldest first.
+    pub fn values(&self) -> &VecDeque<f64> {
+        &self.values
+    }
+
+    /// The value at `index` counting back from the most recent, or `None` past the oldest.
+    pub fn recent(&self, index: usize) -> Option<f64> {
+        self.values.len().checked_sub(index + 1).map(|i| self.values[i])
+    }
+
+    /// The distribution of the values, or `None` if there aren't any. The 99th percentile is the
+    /// smallest value at least 99% of the values are no greater than.
+    pub fn summary(&self) -> Option<Summary> {
+        if self.values.is_empty() {
+            return None;
+        }
+
+        let mut sorted = self.values.iter().cloned().collect::<Vec<_>>();
+        sorted.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        let rank = (sorted.len() as f64 * 0.99).ceil() as usize;
+
+        Some(Summary {
+            min: sorted[0],
+            avg: sorted.iter().sum::<f64>() / sorted.len() as f64,
+            p99: sorted[rank - 1],
+            max: sorted[sorted.len() - 1],
+        })
+    }
+
+    /// Count the values in `buckets` buckets of `width` seconds each, starting from zero. Values
+    /// past the last bucket are counted in it.
+    pub fn histogram(&self, width: f64, buckets: usize) -> Vec<usize> {
+        let mut counts = vec![0; buckets];
+        for &value in &self.values {
+            counts[((value / width) as usize).min(buckets - 1)] += 1;
+        }
+        counts
+    }
+}
+
+/// The timings of recent frames.
+pub struct FrameStats {
+    /// From the start of the frame to handing it to `swap_buffers`.
+    pub cpu: Series,
+
+    /// The GPU time of the frame's drawing, if the context has timer queries.
+    pub gpu: Series,
+
+    /// How long `swap_buffers` took, which includes waiting for vertical sync.
+    pub swap: Series,
+
+    /// From the start of one frame to the start of the next.
+    pub frame: Series,
+}
+
+impl FrameStats {
+    /// Keep the timings of the last `capacity` frames.
+    pub fn new(capacity: usize) -> FrameStats {
+        FrameStats {
+            cpu: Series::new(capacity),
+            gpu: Series::new(capacity),
+            swap: Series::new(capacity),
+            frame: Series::new(capacity),
+        }
+    }
+
+    /// A summary of each timing, and a histogram of the frame times in milliseconds.
+    pub fn report(&self) -> String {
+        let mut report = format!("Timings of the last {} frames:\n", self.frame.values().len());
+        for &(name, series) in &[("frame", &self.frame), ("cpu", &self.cpu), ("gpu", &self.gpu),
+                                 ("swap", &self.swap)] {
+            match series.summary() {
+                Some(summary) => report += &format!("  {:<5} {}\n", name, summary),
+                None => report += &format!("  {:<5} not measured\n", name),
+            }
+        }
+
+        let histogram = self.frame.histogram(0.002, 25);
+        let most = histogram.iter().cloned().max().unwrap_or(0).max(1);
+        report += "Frame times:\n";
+        for (i, &count) in histogram.iter().enumerate() {
+            if count > 0 {
+                let label = if i == histogram.len() - 1 {
+                    format!("{:>2}+   ms", i * 2)
+                } else {
+                    format!("{:>2}-{:<2} ms", i * 2, i * 2 + 2)
+                };
+                let bar = "#".repeat((count * 40).div_ceil(most));
+                report += &format!("  {} {:>5} {}\n", label, count, bar);
+            }
+        }
+        report
+    }
+}
+
+/// Measures the GPU time of each frame with `GL_TIME_ELAPSED` queries.
+pub struct GpuTimer<'a> {
+    queries: Vec<Query<'a>>,
+
+    /// The query the next frame uses.
+    next: usize,
+
+    /// The number of ended queries not read yet, which are the ones before `next`.
+    pending: usize,
+
+    /// Whether a query has begun and not ended.
+    running: bool,
+}
+
+impl<'a> GpuTimer<'a> {
+    /// Create a timer, or return `None` if the context has no timer queries, which came with GL
+    /// 3.3.
+    pub fn new(context: &'a GlContext) -> Option<GpuTimer<'a>> {
+        if context.version() < (3, 3) && !context.has_extension("GL_ARB_timer_query") {
+            return None;
+        }
+
+        Some(GpuTimer {
+            queries: (0..TIMER_QUERIES).map(|_| Query::new(context)).collect(),
+            next: 0,
+            pending: 0,
+            running: false,
+        })
+    }
+
+    /// Start timing a frame. If all the queries are still waiting for the GPU, the frame isn't
+    /// timed.
+    pub fn begin(&mut self) {
+        if self.pending < self.queries.len() {
+            unsafe { gl::BeginQuery(gl::TIME_ELAPSED, self.queries[self.next].id()) };
+            self.running = true;
+        }
+    }
+
+    pub fn end(&mut self) {
+        if self.running {
+            unsafe { gl::EndQuery(gl::TIME_ELAPSED) };
+            self.running = false;
+            self.next = (self.next + 1) % self.queries.len();
+            self.pending += 1;
+        }
+    }
+
+    /// Read the GPU times of the frames that the GPU has finished, oldest first, in seconds.
+    pub fn finished(&mut self) -> Vec<f64> {
+        let mut times = Vec::new();
+
+        while self.pending > 0 {
+            let oldest = (self.next + self.queries.len() - self.pending) % self.queries.len();
+            let id = self.queries[oldest].id();
+
+            let mut available = 0;
+            unsafe { gl::GetQueryObjectiv(id, gl::QUERY_RESULT_AVAILABLE, &mut available) };
+            if available == 0 {
+                break;
+            }
+
+            let mut nanoseconds = 0;
+            unsafe { gl::GetQueryObjectui64v(id, gl::QUERY_RESULT, &mut nanoseconds) };
+            times.push(nanoseconds as f64 / 1e9);
+            self.pending -= 1;
+        }
+
+        times
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, VertexLayout)]
+#[repr(C, packed)]
+pub struct OverlayVertex {
+    pub position: [f32; 2],
+    pub color: [f32; 3],
+}
+
+/// The number of frames the overlay graph shows.
+pub const GRAPH_FRAMES: usize = 120;
+
+/// The corners of the graph in normalized device coordinates, and the frame time that reaches
+/// its top.
+const GRAPH_LEFT: f32 = -0.95;
+const GRAPH_BOTTOM: f32 = -0.95;
+const GRAPH_WIDTH: f32 = 0.8;
+const GRAPH_HEIGHT: f32 = 0.4;
+const GRAPH_SECONDS: f64 = 1.0 / 30.0;
+
+const CPU_COLOR: [f32; 3] = [0.2, 0.8, 0.2];
+const SWAP_COLOR: [f32; 3] = [0.2, 0.4, 1.0];
+const GPU_COLOR: [f32; 3] = [1.0, 0.3, 0.2];
+const LINE_COLOR: [f32; 3] = [1.0, 1.0, 1.0];
+
+/// The triangles of a rectangle in normalized device coordinates.
+fn rectangle(vertices: &mut Vec<OverlayVertex>, left: f32, bottom: f32, right: f32, top: f32,
+             color: [f32; 3]) {
+    for &(x, y) in &[(left, bottom), (right, bottom), (right, top),
+                     (right, top), (left, top), (left, bottom)] {
+        vertices.push(OverlayVertex { position: [x, y], color });
+    }
+}
+
+/// The vertices of the graph: a bar for each of the last `GRAPH_FRAMES` frames, newest on the
+/// right, stacking the CPU time and the swap time, with a tick for the GPU time and a line at
+/// 60 frames per second. Always `graph_vertex_count()` vertices; missing frames are empty.
+pub fn graph_vertices(stats: &FrameStats) -> Vec<OverlayVertex> {
+    let bar_width = GRAPH_WIDTH / GRAPH_FRAMES as f32;
+    let height = |seconds: f64| (seconds.min(GRAPH_SECONDS) / GRAPH_SECONDS) as f32 * GRAPH_HEIGHT;
+    let mut vertices = Vec::with_capacity(graph_vertex_count());
+
+    for i in 0..GRAPH_FRAMES {
+        let right = GRAPH_LEFT + GRAPH_WIDTH - i as f32 * bar_width;
+        let left = right - bar_width;
+
+        let cpu = height(stats.cpu.recent(i).unwrap_or(0.0));
+        let swap = height(stats.swap.recent(i).unwrap_or(0.0));
+        rectangle(&mut vertices, left, GRAPH_BOTTOM, right, GRAPH_BOTTOM + cpu, CPU_COLOR);
+        rectangle(&mut vertices, left, GRAPH_BOTTOM + cpu, right,
+                  GRAPH_BOTTOM + (cpu + swap).min(GRAPH_HEIGHT), SWAP_COLOR);
+
+        // GPU times arrive a few frames late, so they line up with the frames a little behind.
+        let gpu = stats.gpu.recent(i).map_or(0.0, height);
+        let tick = if gpu > 0.0 { 0.005 } else { 0.0 };
+        rectangle(&mut vertices, left, GRAPH_BOTTOM + gpu - tick, right, GRAPH_BOTTOM + gpu,
+                  GPU_COLOR);
+    }
+
+    let line = GRAPH_BOTTOM + height(1.0 / 60.0);
+    rectangle(&mut vertices, GRAPH_LEFT, line, GRAPH_LEFT + GRAPH_WIDTH, line + 0.003,
+              LINE_COLOR);
+    vertices
+}
+
+pub fn graph_vertex_count() -> usize {
+    (GRAPH_FRAMES * 3 + 1) * 6
+}
+
+/// The graph of frame timings, drawn over the scene in the bottom-left corner of the viewport.
+pub struct Overlay<'a> {
+    program: Rc<Program<'a>>,
+    mesh: Mesh<'a, OverlayVertex>,
+}
+
+impl<'a> Overlay<'a> {
+    /// Load the `overlay` shader program from `assets`.
+    pub fn new(assets: &Assets<'a>) -> Result<Overlay<'a>, LoadError> {
+        let program = assets.program("overlay")?;
+        let attributes = AttributeBindings::new::<OverlayVertex>(&program)?;
+        let empty = vec![OverlayVertex { position: [0.0; 2], color: [0.0; 3] };
+                         graph_vertex_count()];
+        let mesh = Mesh::new(assets.context(), attributes, &empty, Usage::Stream,
+                             Primitive::Triangles);
+        Ok(Overlay { program, mesh })
+    }
+
+    /// Draw the graph of `stats` without depth testing or culling.
+    pub fn draw(&self, stats: &FrameStats) {
+        self.mesh.update_vertices(0, &graph_vertices(stats));
+
+        RenderState { depth_test: None, cull_face: None, ..RenderState::default() }.apply();
+        self.program.bind();
+        self.mesh.draw();
+    }
+}
+
+#[test]
+fn test_series() {
+    let mut series = Series::new(100);
+    assert_eq!(None, series.summary());
+
+    // Only the last 100 values are kept, which are 1 to 100.
+    for value in 0..101 {
+        series.push(value as f64);
+    }
+    assert_eq!(Some(100.0), series.recent(0));
+    assert_eq!(Some(1.0), series.recent(99));
+    assert_eq!(None, series.recent(100));
+    assert_eq!(Some(Summary { min: 1.0, avg: 50.5, p99: 99.0, max: 100.0 }), series.summary());
+
+    // Values past the last bucket go in it.
+    assert_eq!(vec![9, 10, 81], series.histogram(10.0, 3));
+}
+
+#[test]
+fn test_graph_vertices() {
+    let mut stats = FrameStats::new(GRAPH_FRAMES);
+    stats.cpu.push(GRAPH_SECONDS / 2.0);
+    stats.swap.push(GRAPH_SECONDS);
+
+    let vertices = graph_vertices(&stats);
+    assert_eq!(graph_vertex_count(), vertices.len());
+
+    // The newest bar is on the right, with the swap time stacked on the CPU time and cut off at
+    // the top of the graph.
+    let top = |first: usize| vertices[first + 2].position;
+    assert_eq!([GRAPH_LEFT + GRAPH_WIDTH, GRAPH_BOTTOM + GRAPH_HEIGHT / 2.0], top(0));
+    assert_eq!([GRAPH_LEFT + GRAPH_WIDTH, GRAPH_BOTTOM + GRAPH_HEIGHT], top(6));
+
+    // Frames that weren't measured are empty.
+    assert_eq!(GRAPH_BOTTOM, { vertices[18 + 2].position }[1]);
+}
@@ -0,0 +1,399 @@
+//! Loading images from disk into textures, with their sampling parameters.
+
+use context::GlContext;
+use gl;
+use gl::types::*;
+use imagefmt::{self, ColFmt};
+use object::Texture2d;
+use screenshot;
+use std::error::Error;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::io;
+use std::path::{Path, PathBuf};
+
+// From EXT_texture_filter_anisotropic, which the bindings don't include.
+const TEXTURE_MAX_ANISOTROPY: GLenum = 0x84FE;
+const MAX_TEXTURE_MAX_ANISOTROPY: GLenum = 0x84FF;
+
+/// The channels of an image, in the order they're stored for each pixel.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum PixelFormat {
+    Grey,
+    GreyAlpha,
+    Rgb,
+    Rgba,
+}
+
+impl PixelFormat {
+    pub fn channels(self) -> usize {
+        match self {
+            PixelFormat::Grey => 1,
+            PixelFormat::GreyAlpha => 2,
+            PixelFormat::Rgb => 3,
+            PixelFormat::Rgba => 4,
+        }
+    }
+
+    /// The internal format and pixel transfer format to upload the image with.
+    fn gl_formats(self) -> (GLenum, GLenum) {
+        match self {
+            PixelFormat::Grey => (gl::R8, gl::RED),
+            PixelFormat::GreyAlpha => (gl::RG8, gl::RG),
+            PixelFormat::Rgb => (gl::RGB8, gl::RGB),
+            PixelFormat::Rgba => (gl::RGBA8, gl::RGBA),
+        }
+    }
+
+    /// How the shader should see the channels of a texture in this format. Greyscale images are
+    /// stored in the red (and green) channels, so they have to be spread back out.
+    fn swizzle(self) -> Option<[GLenum; 4]> {
+        match self {
+            PixelFormat::Grey => Some([gl::RED, gl::RED, gl::RED, gl::ONE]),
+            PixelFormat::GreyAlpha => Some([gl::RED, gl::RED, gl::RED, gl::GREEN]),
+            PixelFormat::Rgb | PixelFormat::Rgba => None,
+        }
+    }
+}
+
+/// An image in memory, in the format it was stored in.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Image {
+    pub width: u32,
+    pub height: u32,
+    pub format: PixelFormat,
+
+    /// The pixels, row by row from the top, with no padding between rows.
+    pub pixels: Vec<u8>,
+}
+
+impl Image {
+    /// Read a PNG, TGA, BMP or JPEG file, keeping its alpha channel, or its single channel if it
+    /// is greyscale.
+    pub fn read<P: AsRef<Path>>(path: P) -> Result<Image, TextureError> {
+        let path = path.as_ref();
+        imagefmt::read(path, ColFmt::Auto).map(Image::from_imagefmt)
+            .map_err(|e| TextureError::Read(path.to_owned(), e))
+    }
+
+    /// Decode an image file that is already in memory, as by `read`.
+    pub fn decode(data: &[u8]) -> io::Result<Image> {
+        imagefmt::read_from(&mut io::Cursor::new(data), ColFmt::Auto).map(Image::from_imagefmt)
+    }
+
+    fn from_imagefmt(image: imagefmt::Image) -> Image {
+        // `ColFmt::Auto` only ever produces these four.
+        let format = match image.fmt {
+            ColFmt::Y => PixelFormat::Grey,
+            ColFmt::YA => PixelFormat::GreyAlpha,
+            ColFmt::RGB => PixelFormat::Rgb,
+            _ => PixelFormat::Rgba,
+        };
+
+        Image { width: image.w as u32, height: image.h as u32, format, pixels: image.buf }
+    }
+
+    /// Put the bottom row first, which is where OpenGL expects texture coordinate 0.
+    pub fn flip_vertically(&mut self) {
+        let row_len = self.width as usize * self.format.channels();
+        screenshot::flip_rows(&mut self.pixels, row_len);
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Wrap {
+    Repeat,
+    MirroredRepeat,
+    ClampToEdge,
+}
+
+impl Wrap {
+    pub fn gl_enum(self) -> GLenum {
+        match self {
+            Wrap::Repeat => gl::REPEAT,
+            Wrap::MirroredRepeat => gl::MIRRORED_REPEAT,
+            Wrap::ClampToEdge => gl::CLAMP_TO_EDGE,
+        }
+    }
+}
+
+/// A texture filter. Only `Nearest` and `Linear` can be used for magnification.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Filter {
+    Nearest,
+    Linear,
+    NearestMipmapNearest,
+    LinearMipmapNearest,
+    NearestMipmapLinear,
+    LinearMipmapLinear,
+}
+
+impl Filter {
+    pub fn gl_enum(self) -> GLenum {
+        match self {
+            Filter::Nearest => gl::NEAREST,
+            Filter::Linear => gl::LINEAR,
+            Filter::NearestMipmapNearest => gl::NEAREST_MIPMAP_NEAREST,
+            Filter::LinearMipmapNearest => gl::LINEAR_MIPMAP_NEAREST,
+            Filter::NearestMipmapLinear => gl::NEAREST_MIPMAP_LINEAR,
+            Filter::LinearMipmapLinear => gl::LINEAR_MIPMAP_LINEAR,
+        }
+    }
+
+    /// Whether the filter reads from mipmap levels other than the base level.
+    pub fn uses_mipmaps(self) -> bool {
+        !matches!(self, Filter::Nearest | Filter::Linear)
+    }
+}
+
+/// How a texture is sampled.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct SamplerDesc {
+    pub wrap_s: Wrap,
+    pub wrap_t: Wrap,
+    pub min_filter: Filter,
+    pub mag_filter: Filter,
+
+    /// The maximum degree of anisotropic filtering, where 1 turns it off. Clamped to what the
+    /// implementation supports, and ignored if it doesn't support anisotropic filtering at all.
+    pub anisotropy: f32,
+
+    /// Whether to generate mipmaps when uploading the image.
+    pub mipmaps: bool,
+}
+
+impl Default for SamplerDesc {
+    fn default() -> SamplerDesc {
+        SamplerDesc {
+            wrap_s: Wrap::Repeat,
+            wrap_t: Wrap::Repeat,
+            min_filter: Filter::LinearMipmapLinear,
+            mag_filter: Filter::Linear,
+            anisotropy: 1.0,
+            mipmaps: true,
+        }
+    }
+}
+
+// So that samplers can be part of cache keys. Valid samplers never have a NaN anisotropy.
+impl Eq for SamplerDesc {}
+
+impl Hash for SamplerDesc {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        (self.wrap_s, self.wrap_t, self.min_filter, self.mag_filter).hash(state);
+        (self.anisotropy.to_bits(), self.mipmaps).hash(state);
+    }
+}
+
+impl SamplerDesc {
+    /// Check for combinations that GL would reject or that would leave the texture incomplete,
+    /// which samples as black rather than failing.
+    pub fn validate(&self) -> Result<(), TextureError> {
+        if self.mag_filter.uses_mipmaps() {
+            return Err(TextureError::MipmapMagFilter(self.mag_filter));
+        }
+        if self.min_filter.uses_mipmaps() && !self.mipmaps {
+            return Err(TextureError::NoMipmaps(self.min_filter));
+        }
+        if self.anisotropy.is_nan() || self.anisotropy < 1.0 {
+            return Err(TextureError::Anisotropy(self.anisotropy));
+        }
+        Ok(())
+    }
+
+    /// Set the parameters of the texture bound to `TEXTURE_2D`.
+    fn apply(&self, context: &GlContext) {
+        unsafe {
+            gl_check!(gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S,
+                                        self.wrap_s.gl_enum() as GLint));
+            gl_check!(gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T,
+                                        self.wrap_t.gl_enum() as GLint));
+            gl_check!(gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER,
+                                        self.min_filter.gl_enum() as GLint));
+            gl_check!(gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER,
+                                        self.mag_filter.gl_enum() as GLint));
+
+            if self.anisotropy > 1.0
+                && context.has_extension("GL_EXT_texture_filter_anisotropic") {
+                let mut max = 1.0;
+                gl::GetFloatv(MAX_TEXTURE_MAX_ANISOTROPY, &mut max);
+                gl_check!(gl::TexParameterf(gl::TEXTURE_2D, TEXTURE_MAX_ANISOTROPY,
+                                            self.anisotropy.min(max)));
+            }
+        }
+    }
+}
+
+/// An error from loading a texture.
+#[derive(Debug)]
+pub enum TextureError {
+    Read(PathBuf, io::Error),
+
+    /// A mipmapping filter was given as the magnification filter.
+    MipmapMagFilter(Filter),
+
+    /// A mipmapping minification filter was given without generating mipmaps.
+    NoMipmaps(Filter),
+
+    /// The anisotropy was less than 1, or not a number.
+    Anisotropy(f32),
+}
+
+impl fmt::Display for TextureError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            TextureError::Read(ref path, ref e) =>
+                write!(f, "failed to read image {}: {}", path.display(), e),
+            TextureError::MipmapMagFilter(filter) =>
+                write!(f, "{:?} can't be used as a magnification filter", filter),
+            TextureError::NoMipmaps(filter) =>
+                write!(f, "{:?} minification filter needs mipmaps, but they are turned off",
+                       filter),
+            TextureError::Anisotropy(anisotropy) =>
+                write!(f, "anisotropy must be at least 1, not {}", anisotropy),
+        }
+    }
+}
+
+impl Error for TextureError {}
+
+/// Read an image file into a new texture, optionally flipping it so that its bottom row is at
+/// texture coordinate 0.
+pub fn load<'a, P: AsRef<Path>>(context: &'a GlContext, path: P, sampler: &SamplerDesc,
+                                 flip: bool) -> Result<Texture2d<'a>, TextureError> {
+    sampler.validate()?;
+    let mut image = Image::read(path)?;
+    if flip {
+        image.flip_vertically();
+    }
+    upload(context, &image, sampler)
+}
+
+/// Create a texture holding `image`. The texture is left bound to `TEXTURE_2D` on the active
+/// texture unit.
+pub fn upload<'a>(context: &'a GlContext, image: &Image, sampler: &SamplerDesc)
+                  -> Result<Texture2d<'a>, TextureError> {
+    sampler.validate()?;
+
+    let texture = Texture2d::new(context);
+    unsafe { gl::BindTexture(gl::TEXTURE_2D, texture.id()); }
+
+    let (internal_format, format) = image.format.gl_formats();
+    unsafe {
+        // Rows are tightly packed, so they are only 4-byte aligned when their length happens to
+        // be a multiple of 4.
+        gl::PixelStorei(gl::UNPACK_ALIGNMENT, 1);
+        gl_check!(gl::TexImage2D(gl::TEXTURE_2D, 0, internal_format as GLint,
+                                 image.width as GLsizei, image.height as GLsizei, 0, format,
+                                 gl::UNSIGNED_BYTE, image.pixels.as_ptr() as *const ()));
+
+        if let Some(swizzle) = image.format.swizzle() {
+            if context.version() >= (3, 3) || context.has_extension("GL_ARB_texture_swizzle") {
+                gl::TexParameteriv(gl::TEXTURE_2D, gl::TEXTURE_SWIZZLE_RGBA,
+                                   swizzle.as_ptr() as *const GLint);
+            }
+        }
+
+        if sampler.mipmaps {
+            gl_check!(gl::GenerateMipmap(gl::TEXTURE_2D));
+        }
+    }
+    sampler.apply(context);
+
+    Ok(texture)
+}
+
+/// A magenta and black checkerboard to draw in place of a texture that hasn't loaded yet.
+pub fn placeholder<'a>(context: &'a GlContext) -> Texture2d<'a> {
+    let image = Image {
+        width: 2,
+        height: 2,
+        format: PixelFormat::Rgb,
+        pixels: vec![255, 0, 255, 0, 0, 0,
+                     0, 0, 0, 255, 0, 255],
+    };
+    let sampler = SamplerDesc {
+        min_filter: Filter::Nearest,
+        mag_filter: Filter::Nearest,
+        mipmaps: false,
+        ..SamplerDesc::default()
+    };
+    upload(context, &image, &sampler).expect("the placeholder sampler is valid")
+}
+
+/// A single white texel, for drawing surfaces without a texture with the same shader as those
+/// with one.
+pub fn white<'a>(context: &'a GlContext) -> Texture2d<'a> {
+    let image = Image { width: 1, height: 1, format: PixelFormat::Rgb, pixels: vec![255; 3] };
+    let sampler = SamplerDesc {
+        min_filter: Filter::Nearest,
+        mag_filter: Filter::Nearest,
+        mipmaps: false,
+        ..SamplerDesc::default()
+    };
+    upload(context, &image, &sampler).expect("the sampler is valid")
+}
+
+#[test]
+fn test_validate_sampler() {
+    assert!(SamplerDesc::default().validate().is_ok());
+
+    let sampler = SamplerDesc { mag_filter: Filter::LinearMipmapLinear, ..SamplerDesc::default() };
+    assert!(matches!(sampler.validate(), Err(TextureError::MipmapMagFilter(_))));
+
+    let sampler = SamplerDesc { mipmaps: false, ..SamplerDesc::default() };
+    assert!(matches!(sampler.validate(), Err(TextureError::NoMipmaps(_))));
+    let sampler = SamplerDesc { min_filter: Filter::Nearest, ..sampler };
+    assert!(sampler.validate().is_ok());
+
+    let sampler = SamplerDesc { anisotropy: 0.5, ..SamplerDesc::default() };
+    assert!(matches!(sampler.validate(), Err(TextureError::Anisotropy(_))));
+}
+
+#[test]
+fn test_upload() {
+    use headless::HeadlessContext;
+    use std::env;
+    use std::fs;
+    use std::process;
+
+    let context = match HeadlessContext::for_test(1, 1) {
+        Some(context) => context,
+        None => return,
+    };
+
+    // An odd width, so the rows aren't 4-byte aligned. The file is named after the process, so
+    // that test runs at the same time don't write over each other's.
+    let path = env::temp_dir().join(format!("gl-test-texture-{}.png", process::id()));
+    let pixels = [10, 20, 30, 40, 50, 60, 70, 80, 90, 100, 110, 120,
+                  1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12];
+    imagefmt::write(&path, 3, 2, ColFmt::RGBA, &pixels, imagefmt::ColType::ColorAlpha).unwrap();
+
+    let mut image = Image::read(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+    assert_eq!((3, 2, PixelFormat::Rgba), (image.width, image.height, image.format));
+    assert_eq!(&pixels[..], &image.pixels[..]);
+
+    let read_back = |image: &Image| {
+        let sampler = SamplerDesc { min_filter: Filter::Nearest, mipmaps: false,
+                                    anisotropy: 16.0, ..SamplerDesc::default() };
+        let _texture = upload(context.gl(), image, &sampler).unwrap();
+        let mut pixels = vec![0u8; image.pixels.len()];
+        unsafe {
+            gl::PixelStorei(gl::PACK_ALIGNMENT, 1);
+            let (_, format) = image.format.gl_formats();
+            gl::GetTexImage(gl::TEXTURE_2D, 0, format, gl::UNSIGNED_BYTE,
+                            pixels.as_mut_ptr() as *mut _);
+            assert_eq!(gl::NO_ERROR, gl::GetError());
+        }
+        pixels
+    };
+    assert_eq!(&pixels[..], &read_back(&image)[..]);
+
+    // Flipping swaps the rows.
+    image.flip_vertically();
+    assert_eq!(&pixels[12..], &image.pixels[..12]);
+
+    let grey = Image { width: 3, height: 1, format: PixelFormat::Grey, pixels: vec![7, 8, 9] };
+    assert_eq!(vec![7, 8, 9], read_back(&grey));
+}
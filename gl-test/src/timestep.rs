@@ -0,0 +1,148 @@
+//! Advancing a simulation in fixed steps, independently of how fast frames are drawn.
+//!
+//! Each frame adds the real time it took, scaled by the time scale, to an accumulator, and the
+//! simulation is updated once for every whole step in it. Frames are drawn between the last two
+//! updates, interpolated by how far the accumulator is into the next step, so motion stays smooth
+//! when the frame rate and update rate differ.
+
+/// The time of a simulation updated at a fixed rate.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Timestep {
+    /// The simulated seconds between updates.
+    step: f64,
+
+    /// The most updates run for one frame. When frames take longer than this many steps, for
+    /// example because the updates themselves are too slow, the simulation falls behind rather
+    /// than running ever more updates per frame.
+    max_steps: u32,
+
+    /// How many simulated seconds pass for each real second.
+    time_scale: f64,
+
+    paused: bool,
+
+    /// Single steps requested while paused.
+    pending_steps: u32,
+
+    /// Simulated time not yet used up by updates, which is less than a step between frames.
+    accumulator: f64,
+
+    /// The number of updates run so far.
+    updates: u64,
+}
+
+impl Timestep {
+    /// Step `rate` times per simulated second.
+    pub fn new(rate: f64, max_steps: u32) -> Timestep {
+        Timestep {
+            step: 1.0 / rate,
+            max_steps,
+            time_scale: 1.0,
+            paused: false,
+            pending_steps: 0,
+            accumulator: 0.0,
+            updates: 0,
+        }
+    }
+
+    /// Add a frame that took `seconds` of real time. Returns the number of updates to run.
+    pub fn advance(&mut self, seconds: f64) -> u32 {
+        if self.paused {
+            let steps = self.pending_steps;
+            self.pending_steps = 0;
+            self.updates += steps as u64;
+            return steps;
+        }
+
+        let cap = self.max_steps as f64 * self.step;
+        self.accumulator = (self.accumulator + seconds * self.time_scale).min(cap);
+
+        let steps = (self.accumulator / self.step).floor() as u32;
+        self.accumulator -= steps as f64 * self.step;
+        self.updates += steps as u64;
+        steps
+    }
+
+    pub fn step(&self) -> f64 {
+        self.step
+    }
+
+    /// The simulated time of the last update.
+    pub fn time(&self) -> f64 {
+        self.updates as f64 * self.step
+    }
+
+    /// How far the accumulator is between the last update and the next, from 0 to 1.
+    pub fn alpha(&self) -> f64 {
+        self.accumulator / self.step
+    }
+
+    /// The simulated time to draw at: between the last two updates, by `alpha`. This is a step
+    /// behind the last update, so that it only goes between states the simulation has reached.
+    pub fn render_time(&self) -> f64 {
+        (self.time() - self.step * (1.0 - self.alpha())).max(0.0)
+    }
+
+    pub fn time_scale(&self) -> f64 {
+        self.time_scale
+    }
+
+    /// Run the simulation `scale` times as fast as real time. Negative scales are taken as zero.
+    pub fn set_time_scale(&mut self, scale: f64) {
+        self.time_scale = scale.max(0.0);
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    pub fn set_paused(&mut self, paused: bool) {
+        self.paused = paused;
+        self.pending_steps = 0;
+    }
+
+    /// Run one update on the next frame, if paused.
+    pub fn single_step(&mut self) {
+        if self.paused {
+            self.pending_steps += 1;
+        }
+    }
+}
+
+#[test]
+fn test_timestep() {
+    let mut timestep = Timestep::new(10.0, 5);
+
+    // Updates only run for whole steps, and the rest carries over.
+    assert_eq!(0, timestep.advance(0.05));
+    assert_eq!(0.0, timestep.render_time());
+    assert_eq!(1, timestep.advance(0.075));
+    assert!((timestep.alpha() - 0.25).abs() < 1e-9);
+    assert!((timestep.render_time() - 0.025).abs() < 1e-9);
+
+    // A long frame runs at most `max_steps` updates and drops the rest.
+    assert_eq!(5, timestep.advance(10.0));
+    assert_eq!(0.0, timestep.alpha());
+    assert!((timestep.time() - 0.6).abs() < 1e-9);
+
+    // Time scale.
+    timestep.set_time_scale(0.5);
+    assert_eq!(1, timestep.advance(0.2));
+    timestep.set_time_scale(-1.0);
+    assert_eq!(0, timestep.advance(1.0));
+    timestep.set_time_scale(1.0);
+
+    // Paused, frames run no updates, except for single steps.
+    timestep.set_paused(true);
+    assert_eq!(0, timestep.advance(1.0));
+    timestep.single_step();
+    timestep.single_step();
+    assert_eq!(2, timestep.advance(0.0));
+    assert_eq!(0, timestep.advance(1.0));
+    assert!((timestep.time() - 0.9).abs() < 1e-9);
+
+    // Steps requested while running don't pile up.
+    timestep.set_paused(false);
+    timestep.single_step();
+    assert_eq!(1, timestep.advance(0.1));
+}
@@ -0,0 +1,182 @@
+//! Values that can be assigned to shader uniforms, with the GL types they match.
+
+use gl;
+use gl::types::*;
+use math::{Mat4, Vec3, Vec4};
+
+/// A value that can be assigned to a uniform with `Program::set`.
+pub trait Uniform {
+    /// The GL type of uniforms this value is meant for, like `gl::FLOAT_VEC3`. For arrays this is
+    /// the element type.
+    fn gl_type() -> GLenum;
+
+    /// Whether a uniform of the given GL type can hold this value.
+    fn accepts(gl_type: GLenum) -> bool {
+        gl_type == Self::gl_type()
+    }
+
+    /// The number of array elements this value sets.
+    fn count(&self) -> usize {
+        1
+    }
+
+    /// Assign the value to the uniform at `location` in the current program.
+    unsafe fn set(&self, location: GLint);
+}
+
+/// A uniform type that can also be set as an array with a single call.
+pub trait UniformElement: Uniform + Sized {
+    unsafe fn set_array(values: &[Self], location: GLint);
+}
+
+/// The texture unit a sampler uniform reads from, as in `gl::TEXTURE0 + unit`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct TextureUnit(pub GLint);
+
+impl Uniform for f32 {
+    fn gl_type() -> GLenum { gl::FLOAT }
+    unsafe fn set(&self, location: GLint) { gl::Uniform1f(location, *self); }
+}
+
+impl UniformElement for f32 {
+    unsafe fn set_array(values: &[f32], location: GLint) {
+        gl::Uniform1fv(location, values.len() as GLsizei, values.as_ptr());
+    }
+}
+
+impl Uniform for i32 {
+    fn gl_type() -> GLenum { gl::INT }
+    unsafe fn set(&self, location: GLint) { gl::Uniform1i(location, *self); }
+}
+
+impl UniformElement for i32 {
+    unsafe fn set_array(values: &[i32], location: GLint) {
+        gl::Uniform1iv(location, values.len() as GLsizei, values.as_ptr());
+    }
+}
+
+impl Uniform for Vec3 {
+    fn gl_type() -> GLenum { gl::FLOAT_VEC3 }
+    unsafe fn set(&self, location: GLint) { gl::Uniform3fv(location, 1, self.0.as_ptr()); }
+}
+
+impl UniformElement for Vec3 {
+    unsafe fn set_array(values: &[Vec3], location: GLint) {
+        let flat: Vec<f32> = values.iter().flat_map(|v| v.0.iter().cloned()).collect();
+        gl::Uniform3fv(location, values.len() as GLsizei, flat.as_ptr());
+    }
+}
+
+impl Uniform for Vec4 {
+    fn gl_type() -> GLenum { gl::FLOAT_VEC4 }
+    unsafe fn set(&self, location: GLint) { gl::Uniform4fv(location, 1, self.0.as_ptr()); }
+}
+
+impl UniformElement for Vec4 {
+    unsafe fn set_array(values: &[Vec4], location: GLint) {
+        let flat: Vec<f32> = values.iter().flat_map(|v| v.0.iter().cloned()).collect();
+        gl::Uniform4fv(location, values.len() as GLsizei, flat.as_ptr());
+    }
+}
+
+impl Uniform for Mat4 {
+    fn gl_type() -> GLenum { gl::FLOAT_MAT4 }
+
+    unsafe fn set(&self, location: GLint) {
+        gl::UniformMatrix4fv(location, 1, gl::FALSE, &self[0][0]);
+    }
+}
+
+impl UniformElement for Mat4 {
+    unsafe fn set_array(values: &[Mat4], location: GLint) {
+        let flat: Vec<f32> = values.iter()
+            .flat_map(|m| m.0.iter().flat_map(|column| column.iter().cloned()))
+            .collect();
+        gl::UniformMatrix4fv(location, values.len() as GLsizei, gl::FALSE, flat.as_ptr());
+    }
+}
+
+impl Uniform for TextureUnit {
+    fn gl_type() -> GLenum { gl::SAMPLER_2D }
+
+    /// Any sampler type can be pointed at a texture unit.
+    fn accepts(gl_type: GLenum) -> bool {
+        matches!(gl_type,
+                 gl::SAMPLER_1D | gl::SAMPLER_2D | gl::SAMPLER_3D | gl::SAMPLER_CUBE |
+                 gl::SAMPLER_1D_SHADOW | gl::SAMPLER_2D_SHADOW | gl::SAMPLER_1D_ARRAY |
+                 gl::SAMPLER_2D_ARRAY | gl::SAMPLER_CUBE_SHADOW | gl::SAMPLER_2D_RECT |
+                 gl::SAMPLER_BUFFER | gl::SAMPLER_2D_MULTISAMPLE)
+    }
+
+    unsafe fn set(&self, location: GLint) { gl::Uniform1i(location, self.0); }
+}
+
+impl UniformElement for TextureUnit {
+    unsafe fn set_array(values: &[TextureUnit], location: GLint) {
+        let units: Vec<GLint> = values.iter().map(|unit| unit.0).collect();
+        gl::Uniform1iv(location, values.len() as GLsizei, units.as_ptr());
+    }
+}
+
+impl<T: UniformElement> Uniform for &[T] {
+    fn gl_type() -> GLenum { T::gl_type() }
+    fn accepts(gl_type: GLenum) -> bool { T::accepts(gl_type) }
+    fn count(&self) -> usize { self.len() }
+    unsafe fn set(&self, location: GLint) { T::set_array(self, location); }
+}
+
+impl<T: UniformElement, const N: usize> Uniform for [T; N] {
+    fn gl_type() -> GLenum { T::gl_type() }
+    fn accepts(gl_type: GLenum) -> bool { T::accepts(gl_type) }
+    fn count(&self) -> usize { N }
+    unsafe fn set(&self, location: GLint) { T::set_array(self, location); }
+}
+
+#[test]
+fn test_set_uniforms() {
+    use headless::HeadlessContext;
+    use shader::{Program, ReflectionError, VariableKind};
+
+    let context = match HeadlessContext::for_test(1, 1) {
+        Some(context) => context,
+        None => return,
+    };
+    let vertex = "#version 150\nuniform mat4 trans;\nuniform float weights[3];\n\
+                  void main() { gl_Position = trans * vec4(weights[0], weights[1], weights[2], \
+                  1.0); }\n";
+    let fragment = "#version 150\nuniform sampler2D tex;\nout vec4 out_color;\n\
+                    void main() { out_color = texture(tex, vec2(0.0)); }\n";
+
+    let program = Program::new(context.gl(), vertex, fragment, &["out_color"]).unwrap();
+    program.bind();
+
+    let trans = Mat4::translate(1.0, 2.0, 3.0);
+    program.set("trans", trans).unwrap();
+    program.set("weights", [0.25, 0.5, 0.75]).unwrap();
+    program.set("tex", TextureUnit(3)).unwrap();
+
+    unsafe {
+        let mut actual = Mat4::zero();
+        let location = program.uniform("trans", gl::FLOAT_MAT4).unwrap().location;
+        gl::GetUniformfv(program.id(), location, &mut actual[0][0]);
+        assert_eq!(trans, actual);
+
+        let mut weight = 0.0;
+        let location = gl::GetUniformLocation(program.id(), b"weights[2]\0".as_ptr() as *const _);
+        gl::GetUniformfv(program.id(), location, &mut weight);
+        assert_eq!(0.75, weight);
+    }
+
+    assert_eq!(Err(ReflectionError::WrongType {
+        kind: VariableKind::Uniform,
+        name: "trans".to_string(),
+        expected: gl::FLOAT_VEC3,
+        actual: gl::FLOAT_MAT4,
+    }), program.set("trans", Vec3([0.0; 3])));
+
+    assert_eq!(Err(ReflectionError::TooLong {
+        name: "weights".to_string(),
+        size: 3,
+        len: 4,
+    }), program.set("weights", &[0.0f32; 4][..]));
+}
@@ -0,0 +1,158 @@
+//! Describing vertex structs to OpenGL.
+//!
+//! Vertex types implement `VertexLayout`, normally with `#[derive(VertexLayout)]`, to list their
+//! fields as attributes. `AttributeBindings` then matches those attributes by name against the
+//! attributes a linked program actually reads.
+
+use gl;
+use gl::types::*;
+use math::{Vec3, Vec4};
+use shader::{Program, ReflectionError, VariableKind};
+use std::mem;
+
+/// One field of a vertex struct, as passed to `glVertexAttribPointer`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Attribute {
+    /// The field name, which is also the name of the shader input it feeds.
+    pub name: &'static str,
+    pub components: GLint,
+
+    /// The type of each component in memory, like `gl::FLOAT`.
+    pub gl_type: GLenum,
+
+    /// Whether integer components are mapped to [0, 1] (or [-1, 1] for signed types).
+    pub normalized: bool,
+
+    /// The byte offset of the field within the struct.
+    pub offset: usize,
+}
+
+impl Attribute {
+    /// The GLSL type the shader input must have to receive this attribute. `VertexAttribPointer`
+    /// converts every component type to floats.
+    pub fn shader_type(&self) -> GLenum {
+        match self.components {
+            1 => gl::FLOAT,
+            2 => gl::FLOAT_VEC2,
+            3 => gl::FLOAT_VEC3,
+            _ => gl::FLOAT_VEC4,
+        }
+    }
+}
+
+/// A vertex struct whose fields can be fed to shader attributes.
+pub trait VertexLayout: Sized {
+    fn attributes() -> Vec<Attribute>;
+
+    /// The distance in bytes between consecutive vertices.
+    fn stride() -> usize {
+        mem::size_of::<Self>()
+    }
+}
+
+/// A field type that can be used in a `VertexLayout` struct.
+pub trait AttributeType {
+    const COMPONENTS: GLint;
+    const GL_TYPE: GLenum;
+}
+
+macro_rules! attribute_type {
+    ($ty:ty, $components:expr, $gl_type:expr) => (
+        impl AttributeType for $ty {
+            const COMPONENTS: GLint = $components;
+            const GL_TYPE: GLenum = $gl_type;
+        }
+    )
+}
+
+attribute_type!(f32, 1, gl::FLOAT);
+attribute_type!([f32; 2], 2, gl::FLOAT);
+attribute_type!([f32; 3], 3, gl::FLOAT);
+attribute_type!([f32; 4], 4, gl::FLOAT);
+attribute_type!(Vec3, 3, gl::FLOAT);
+attribute_type!(Vec4, 4, gl::FLOAT);
+attribute_type!([u8; 4], 4, gl::UNSIGNED_BYTE);
+attribute_type!([u16; 2], 2, gl::UNSIGNED_SHORT);
+
+/// The attributes of a vertex type matched to the attribute locations of a program.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct AttributeBindings {
+    bindings: Vec<(GLuint, Attribute)>,
+    stride: usize,
+}
+
+impl AttributeBindings {
+    /// Match the attributes of `V` against the active attributes of `program`. Every attribute
+    /// the program reads must be fed by a field with a matching number of components; fields the
+    /// program doesn't read are left out.
+    pub fn new<V: VertexLayout>(program: &Program)
+                                -> Result<AttributeBindings, ReflectionError> {
+        let attributes = V::attributes();
+        let mut bindings = Vec::new();
+
+        for variable in &program.attributes {
+            let attribute = attributes.iter().find(|a| a.name == variable.name).ok_or_else(|| {
+                ReflectionError::NoVertexField { name: variable.name.clone() }
+            })?;
+
+            if attribute.shader_type() != variable.gl_type {
+                return Err(ReflectionError::WrongType {
+                    kind: VariableKind::Attribute,
+                    name: variable.name.clone(),
+                    expected: attribute.shader_type(),
+                    actual: variable.gl_type,
+                });
+            }
+
+            bindings.push((variable.location as GLuint, *attribute));
+        }
+
+        Ok(AttributeBindings { bindings, stride: V::stride() })
+    }
+
+    /// Point the attributes at the vertices in the bound array buffer. This is recorded in the
+    /// bound vertex array.
+    pub fn enable(&self) {
+        for &(location, ref attribute) in &self.bindings {
+            unsafe {
+                gl::EnableVertexAttribArray(location);
+                gl_check!(gl::VertexAttribPointer(location, attribute.components,
+                                                  attribute.gl_type,
+                                                  attribute.normalized as GLboolean,
+                                                  self.stride as GLsizei,
+                                                  attribute.offset as *const ()));
+            }
+        }
+    }
+
+    /// Disable the attributes in the bound vertex array, as when switching to a program that may
+    /// use other locations.
+    pub fn disable(&self) {
+        for &(location, _) in &self.bindings {
+            unsafe { gl::DisableVertexAttribArray(location); }
+        }
+    }
+}
+
+#[test]
+fn test_derive_vertex_layout() {
+    #[derive(Copy, Clone, VertexLayout)]
+    #[repr(C, packed)]
+    #[allow(dead_code)]
+    struct TestVertex {
+        position: [f32; 3],
+        #[vertex(normalized)]
+        color: [u8; 4],
+        texcoord: [f32; 2],
+    }
+
+    assert_eq!(vec![
+        Attribute { name: "position", components: 3, gl_type: gl::FLOAT, normalized: false,
+                    offset: 0 },
+        Attribute { name: "color", components: 4, gl_type: gl::UNSIGNED_BYTE, normalized: true,
+                    offset: 12 },
+        Attribute { name: "texcoord", components: 2, gl_type: gl::FLOAT, normalized: false,
+                    offset: 16 },
+    ], TestVertex::attributes());
+    assert_eq!(24, TestVertex::stride());
+}
@@ -0,0 +1,118 @@
+//! `#[derive(VertexLayout)]` for gl-test's vertex structs.
+//!
+//! Each field becomes a vertex attribute named after the field. Its component count and GL type
+//! come from the field type's `vertex::AttributeType` impl, and its offset is the sum of the sizes
+//! of the fields before it, which is why the struct must be `#[repr(C, packed)]`. Fields marked
+//! `#[vertex(normalized)]` have integer components mapped to [0, 1] (or [-1, 1]) in the shader.
+//!
+//! The generated impl refers to `::vertex`, so the deriving crate must have a `vertex` module at
+//! its root.
+
+extern crate proc_macro;
+extern crate proc_macro2;
+extern crate quote;
+extern crate syn;
+
+use proc_macro::TokenStream;
+use proc_macro2::Span;
+use quote::quote;
+use syn::{Data, DeriveInput, Error, Fields};
+
+#[proc_macro_derive(VertexLayout, attributes(vertex))]
+pub fn derive_vertex_layout(input: TokenStream) -> TokenStream {
+    let input = syn::parse_macro_input!(input as DeriveInput);
+    match expand(&input) {
+        Ok(tokens) => tokens.into(),
+        Err(e) => e.to_compile_error().into(),
+    }
+}
+
+fn expand(input: &DeriveInput) -> Result<proc_macro2::TokenStream, Error> {
+    if !is_packed_c(input)? {
+        return Err(Error::new(Span::call_site(),
+                              "VertexLayout can only be derived for #[repr(C, packed)] structs"));
+    }
+
+    let fields = match input.data {
+        Data::Struct(ref data) => match data.fields {
+            Fields::Named(ref fields) => &fields.named,
+            _ => return Err(Error::new(Span::call_site(),
+                                       "VertexLayout requires a struct with named fields")),
+        },
+        _ => return Err(Error::new(Span::call_site(),
+                                   "VertexLayout can only be derived for structs")),
+    };
+
+    let mut attributes = Vec::new();
+    let mut offset = quote!(0);
+
+    for field in fields {
+        let name = field.ident.as_ref().unwrap().to_string();
+        let ty = &field.ty;
+        let normalized = is_normalized(field)?;
+
+        attributes.push(quote! {
+            ::vertex::Attribute {
+                name: #name,
+                components: <#ty as ::vertex::AttributeType>::COMPONENTS,
+                gl_type: <#ty as ::vertex::AttributeType>::GL_TYPE,
+                normalized: #normalized,
+                offset: #offset,
+            }
+        });
+
+        offset = quote!(#offset + ::std::mem::size_of::<#ty>());
+    }
+
+    let ident = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+
+    Ok(quote! {
+        impl #impl_generics ::vertex::VertexLayout for #ident #ty_generics #where_clause {
+            fn attributes() -> Vec<::vertex::Attribute> {
+                vec![#(#attributes),*]
+            }
+        }
+    })
+}
+
+/// Whether the struct has both `C` and `packed` in its `#[repr]` attributes.
+fn is_packed_c(input: &DeriveInput) -> Result<bool, Error> {
+    let mut c = false;
+    let mut packed = false;
+
+    for attr in input.attrs.iter().filter(|attr| attr.path().is_ident("repr")) {
+        attr.parse_nested_meta(|meta| {
+            // `packed(N)` and `align(N)` may add padding, which the offsets don't account for.
+            if meta.input.peek(syn::token::Paren) {
+                let _arguments;
+                syn::parenthesized!(_arguments in meta.input);
+            } else if meta.path.is_ident("C") {
+                c = true;
+            } else if meta.path.is_ident("packed") {
+                packed = true;
+            }
+            Ok(())
+        })?;
+    }
+
+    Ok(c && packed)
+}
+
+/// Whether a field is marked `#[vertex(normalized)]`.
+fn is_normalized(field: &syn::Field) -> Result<bool, Error> {
+    let mut normalized = false;
+
+    for attr in field.attrs.iter().filter(|attr| attr.path().is_ident("vertex")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("normalized") {
+                normalized = true;
+                Ok(())
+            } else {
+                Err(meta.error("expected `normalized`"))
+            }
+        })?;
+    }
+
+    Ok(normalized)
+}